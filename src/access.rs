@@ -0,0 +1,204 @@
+use std::fmt;
+use crate::ComponentId;
+
+const WORD_BITS: usize = usize::BITS as usize;
+
+/// A set of component ids, stored as a bit set indexed by id.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ComponentSet {
+    words: Vec<usize>,
+}
+
+impl ComponentSet {
+
+    /// Returns whether the id was newly inserted.
+    pub fn insert(&mut self, id: ComponentId) -> bool {
+        let (word, bit) = (id.index() / WORD_BITS, id.index() % WORD_BITS);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let prev = self.words[word];
+        self.words[word] |= 1 << bit;
+        prev & (1 << bit) == 0
+    }
+
+    #[inline]
+    pub fn contains(&self, id: ComponentId) -> bool {
+        self.words.get(id.index() / WORD_BITS).is_some_and(|word| word & (1 << (id.index() % WORD_BITS)) != 0)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    #[inline]
+    pub fn is_disjoint(&self, other: &ComponentSet) -> bool {
+        self.words.iter().zip(other.words.iter()).all(|(a, b)| a & b == 0)
+    }
+
+    #[inline]
+    pub fn is_subset(&self, other: &ComponentSet) -> bool {
+        self.words.iter().enumerate().all(|(idx, word)| word & !other.words.get(idx).copied().unwrap_or(0) == 0)
+    }
+
+    pub fn union_with(&mut self, other: &ComponentSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        self.words.iter_mut().zip(other.words.iter()).for_each(|(a, b)| *a |= b);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.words.iter().enumerate().flat_map(|(idx, word)| {
+            (0..WORD_BITS).filter(move |bit| word & (1 << bit) != 0).map(move |bit| ComponentId::new(idx * WORD_BITS + bit))
+        })
+    }
+
+}
+
+impl fmt::Debug for ComponentSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter().map(ComponentId::index)).finish()
+    }
+}
+
+/// The components and resources something reads and writes, writing implies reading. Resource ids are separate
+/// from component ids, so they are tracked separately.
+#[derive(Clone, Default, Debug)]
+pub struct Access {
+    reads: ComponentSet,
+    writes: ComponentSet,
+    resource_reads: ComponentSet,
+    resource_writes: ComponentSet,
+    // set for accesses which can read arbitrary components and resources, e.g. through `&World`
+    reads_all: bool,
+}
+
+impl Access {
+
+    #[inline]
+    pub fn add_read(&mut self, id: ComponentId) {
+        self.reads.insert(id);
+    }
+
+    #[inline]
+    pub fn add_write(&mut self, id: ComponentId) {
+        self.reads.insert(id);
+        self.writes.insert(id);
+    }
+
+    #[inline]
+    pub fn add_resource_read(&mut self, id: ComponentId) {
+        self.resource_reads.insert(id);
+    }
+
+    #[inline]
+    pub fn add_resource_write(&mut self, id: ComponentId) {
+        self.resource_reads.insert(id);
+        self.resource_writes.insert(id);
+    }
+
+    /// Reads every component and resource, including the ones which get registered later.
+    #[inline]
+    pub fn add_read_all(&mut self) {
+        self.reads_all = true;
+    }
+
+    #[inline(always)]
+    pub fn reads_all(&self) -> bool {
+        self.reads_all
+    }
+
+    /// The components which get read explicitly, see `reads_all`.
+    #[inline(always)]
+    pub fn reads(&self) -> &ComponentSet {
+        &self.reads
+    }
+
+    #[inline(always)]
+    pub fn writes(&self) -> &ComponentSet {
+        &self.writes
+    }
+
+    /// The resources which get read explicitly, see `reads_all`.
+    #[inline(always)]
+    pub fn resource_reads(&self) -> &ComponentSet {
+        &self.resource_reads
+    }
+
+    #[inline(always)]
+    pub fn resource_writes(&self) -> &ComponentSet {
+        &self.resource_writes
+    }
+
+    #[inline]
+    pub fn has_read(&self, id: ComponentId) -> bool {
+        self.reads_all || self.reads.contains(id)
+    }
+
+    #[inline]
+    pub fn has_write(&self, id: ComponentId) -> bool {
+        self.writes.contains(id)
+    }
+
+    #[inline]
+    pub fn has_resource_read(&self, id: ComponentId) -> bool {
+        self.reads_all || self.resource_reads.contains(id)
+    }
+
+    #[inline]
+    pub fn has_resource_write(&self, id: ComponentId) -> bool {
+        self.resource_writes.contains(id)
+    }
+
+    /// Whether anything gets written, be it components or resources.
+    #[inline]
+    pub fn writes_any(&self) -> bool {
+        !self.writes.is_empty() || !self.resource_writes.is_empty()
+    }
+
+    /// Whether both accesses can happen at the same time.
+    #[inline]
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        if (self.reads_all && other.writes_any()) || (other.reads_all && self.writes_any()) {
+            return false;
+        }
+        self.writes.is_disjoint(&other.reads) && other.writes.is_disjoint(&self.reads)
+            && self.resource_writes.is_disjoint(&other.resource_reads) && other.resource_writes.is_disjoint(&self.resource_reads)
+    }
+
+    /// Whether everything this accesses is also accessed by `other`, at least as exclusively.
+    #[inline]
+    pub fn is_subset(&self, other: &Access) -> bool {
+        (other.reads_all || (!self.reads_all && self.reads.is_subset(&other.reads) && self.resource_reads.is_subset(&other.resource_reads)))
+            && self.writes.is_subset(&other.writes) && self.resource_writes.is_subset(&other.resource_writes)
+    }
+
+    /// The components both access while at least one of them writes.
+    pub fn conflicts(&self, other: &Access) -> Vec<ComponentId> {
+        let mut candidates = self.reads.clone();
+        candidates.union_with(&other.reads);
+        candidates.iter()
+            .filter(|id| self.has_read(*id) && other.has_read(*id) && (self.has_write(*id) || other.has_write(*id)))
+            .collect()
+    }
+
+    /// The resources both access while at least one of them writes.
+    pub fn resource_conflicts(&self, other: &Access) -> Vec<ComponentId> {
+        let mut candidates = self.resource_reads.clone();
+        candidates.union_with(&other.resource_reads);
+        candidates.iter()
+            .filter(|id| self.has_resource_read(*id) && other.has_resource_read(*id) && (self.has_resource_write(*id) || other.has_resource_write(*id)))
+            .collect()
+    }
+
+    pub fn extend(&mut self, other: &Access) {
+        self.reads.union_with(&other.reads);
+        self.writes.union_with(&other.writes);
+        self.resource_reads.union_with(&other.resource_reads);
+        self.resource_writes.union_with(&other.resource_writes);
+        self.reads_all |= other.reads_all;
+    }
+
+}
@@ -0,0 +1,353 @@
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::ptr;
+use crate::atomic_bit_set::AtomicBitSet;
+use crate::blob_vec::BlobVec;
+use crate::tick::{ComponentTicks, Tick};
+use crate::value::{ComponentValue, ValueVTable};
+use crate::{ComponentId, ComponentInfo, Components, EntityId};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ArchetypeId(u32);
+
+impl ArchetypeId {
+
+    /// The archetype of entities without any components, it always exists.
+    pub const EMPTY: ArchetypeId = ArchetypeId(0);
+    pub(crate) const INVALID: ArchetypeId = ArchetypeId(u32::MAX);
+
+    #[inline(always)]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+}
+
+/// Where an entity's components are stored.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct EntityLocation {
+    pub archetype: ArchetypeId,
+    pub row: usize,
+}
+
+impl EntityLocation {
+
+    pub const INVALID: EntityLocation = EntityLocation {
+        archetype: ArchetypeId::INVALID,
+        row: usize::MAX,
+    };
+
+}
+
+/// Stores the values of one component type for all entities of an archetype contiguously.
+pub(crate) struct Column {
+    data: BlobVec,
+    vtable: ValueVTable,
+    // queries update the ticks of the components they hand out mutably through shared references
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+}
+
+impl Column {
+
+    pub fn new(info: &ComponentInfo) -> Self {
+        let vtable = info.vtable();
+        Self {
+            data: BlobVec::new(vtable.layout, vtable.drop),
+            vtable,
+            ticks: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The row has to be in bounds.
+    #[inline]
+    pub fn get_ptr(&self, row: usize) -> *mut u8 {
+        self.data.get_ptr(row)
+    }
+
+    /// The row has to be in bounds.
+    #[inline]
+    pub fn get_ticks_ptr(&self, row: usize) -> *mut ComponentTicks {
+        self.ticks[row].get()
+    }
+
+    #[inline]
+    pub fn get_ticks(&self, row: usize) -> ComponentTicks {
+        unsafe { *self.ticks[row].get() }
+    }
+
+    #[inline]
+    pub fn set_ticks(&mut self, row: usize, ticks: ComponentTicks) {
+        *self.ticks[row].get_mut() = ticks;
+    }
+
+    pub fn replace(&mut self, row: usize, component: ComponentValue, tick: Tick) -> ComponentValue {
+        self.ticks[row].get_mut().changed = tick;
+        let dst = self.data.get_ptr(row);
+        let old = unsafe { ComponentValue::read(dst, self.vtable) };
+        component.into_raw(|src| unsafe { ptr::copy_nonoverlapping(src, dst, self.vtable.layout.size()) });
+        old
+    }
+
+    #[inline]
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+        self.ticks.reserve(additional);
+    }
+
+    #[inline]
+    pub fn push(&mut self, component: ComponentValue, tick: Tick) {
+        component.into_raw(|src| unsafe { self.data.push(src) });
+        self.ticks.push(UnsafeCell::new(ComponentTicks::new(tick)));
+    }
+
+    #[inline]
+    pub fn swap_remove(&mut self, row: usize) -> ComponentValue {
+        self.ticks.swap_remove(row);
+        let vtable = self.vtable;
+        unsafe { self.data.swap_remove_with(row, |ptr| ComponentValue::read(ptr, vtable)) }
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.ticks.clear();
+        self.data.clear();
+    }
+
+    pub fn check_ticks(&mut self, tick: Tick) {
+        self.ticks.iter_mut().for_each(|ticks| ticks.get_mut().check_ticks(tick));
+    }
+
+}
+
+/// All entities sharing the exact same set of components, their components are stored in
+/// one column per component type where the entity at `entities[row]` owns the values at `row`.
+pub struct Archetype {
+    id: ArchetypeId,
+    // sorted, so the same set of components always maps to the same archetype
+    components: Box<[ComponentId]>,
+    // the same set as `components`, but with constant time lookups
+    component_set: AtomicBitSet,
+    columns: Box<[Column]>,
+    entities: Vec<EntityId>,
+    // caches the archetype entities move to when adding or removing a single component
+    add_edges: HashMap<ComponentId, ArchetypeId>,
+    remove_edges: HashMap<ComponentId, ArchetypeId>,
+}
+
+impl Archetype {
+
+    fn new(id: ArchetypeId, components: Box<[ComponentId]>, infos: &Components) -> Self {
+        let columns = components.iter().map(|component| Column::new(infos.get_info(*component).unwrap())).collect();
+        let component_set = AtomicBitSet::new();
+        for component in components.iter() {
+            component_set.add(component.index());
+        }
+        Self {
+            id,
+            components,
+            component_set,
+            columns,
+            entities: vec![],
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> ArchetypeId {
+        self.id
+    }
+
+    #[inline(always)]
+    pub fn components(&self) -> &[ComponentId] {
+        &self.components
+    }
+
+    #[inline(always)]
+    pub fn entities(&self) -> &[EntityId] {
+        &self.entities
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, component: ComponentId) -> bool {
+        self.component_set.contains(component.index())
+    }
+
+    #[inline]
+    fn column_index(&self, component_id: ComponentId) -> Option<usize> {
+        self.components.binary_search(&component_id).ok()
+    }
+
+    #[inline]
+    pub(crate) fn column(&self, component_id: ComponentId) -> Option<&Column> {
+        self.column_index(component_id).map(|idx| &self.columns[idx])
+    }
+
+    #[inline]
+    pub(crate) fn column_mut(&mut self, component_id: ComponentId) -> Option<&mut Column> {
+        self.column_index(component_id).map(|idx| &mut self.columns[idx])
+    }
+
+    /// How many entities the archetype can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.entities.capacity()
+    }
+
+    /// Reserves rows for at least `additional` more entities in all columns.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        self.columns.iter_mut().for_each(|column| column.reserve(additional));
+    }
+
+    /// Appends an entity, `component` has to provide a value for every component type of the archetype.
+    /// Returns the row the entity got stored in.
+    pub(crate) fn push(&mut self, id: EntityId, tick: Tick, mut component: impl FnMut(ComponentId) -> ComponentValue) -> usize {
+        for (component_id, column) in self.components.iter().zip(self.columns.iter_mut()) {
+            column.push(component(*component_id), tick);
+        }
+        self.entities.push(id);
+        self.entities.len() - 1
+    }
+
+    /// Removes the entity at `row` by moving the last entity into its place, all of the removed entity's
+    /// components get passed to `component`. Returns the entity which now occupies `row`, if any.
+    pub(crate) fn swap_remove(&mut self, row: usize, mut component: impl FnMut(ComponentId, ComponentValue)) -> Option<EntityId> {
+        for (component_id, column) in self.components.iter().zip(self.columns.iter_mut()) {
+            component(*component_id, column.swap_remove(row));
+        }
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+
+    fn clear(&mut self) {
+        self.entities.clear();
+        self.columns.iter_mut().for_each(Column::clear);
+    }
+
+    fn check_ticks(&mut self, tick: Tick) {
+        self.columns.iter_mut().for_each(|column| column.check_ticks(tick));
+    }
+
+}
+
+pub struct Archetypes {
+    archetypes: Vec<Archetype>,
+    by_components: HashMap<Box<[ComponentId]>, ArchetypeId>,
+}
+
+impl Archetypes {
+
+    #[inline]
+    pub fn get(&self, id: ArchetypeId) -> Option<&Archetype> {
+        self.archetypes.get(id.index())
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self, id: ArchetypeId) -> &mut Archetype {
+        &mut self.archetypes[id.index()]
+    }
+
+    /// Returns both archetypes mutably, they have to be distinct.
+    pub(crate) fn get_two_mut(&mut self, first: ArchetypeId, second: ArchetypeId) -> (&mut Archetype, &mut Archetype) {
+        assert_ne!(first, second);
+        if first.index() < second.index() {
+            let (left, right) = self.archetypes.split_at_mut(second.index());
+            (&mut left[first.index()], &mut right[0])
+        } else {
+            let (left, right) = self.archetypes.split_at_mut(first.index());
+            (&mut right[0], &mut left[second.index()])
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.archetypes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.archetypes.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter()
+    }
+
+    /// `components` has to be sorted and free of duplicates.
+    pub(crate) fn get_or_insert(&mut self, components: Box<[ComponentId]>, infos: &Components) -> ArchetypeId {
+        if let Some(id) = self.by_components.get(&components) {
+            return *id;
+        }
+        let id = ArchetypeId(u32::try_from(self.archetypes.len()).expect("too many archetypes"));
+        self.archetypes.push(Archetype::new(id, components.clone(), infos));
+        self.by_components.insert(components, id);
+        id
+    }
+
+    /// The archetype entities of `src` end up in after adding the component `component_id`.
+    pub(crate) fn with_component(&mut self, src: ArchetypeId, component_id: ComponentId, infos: &Components) -> ArchetypeId {
+        if let Some(id) = self.archetypes[src.index()].add_edges.get(&component_id) {
+            return *id;
+        }
+        let mut components = self.archetypes[src.index()].components.to_vec();
+        if let Err(idx) = components.binary_search(&component_id) {
+            components.insert(idx, component_id);
+        }
+        let dst = self.get_or_insert(components.into_boxed_slice(), infos);
+        self.archetypes[src.index()].add_edges.insert(component_id, dst);
+        self.archetypes[dst.index()].remove_edges.insert(component_id, src);
+        dst
+    }
+
+    /// The archetype entities of `src` end up in after removing the component `component_id`.
+    pub(crate) fn without_component(&mut self, src: ArchetypeId, component_id: ComponentId, infos: &Components) -> ArchetypeId {
+        if let Some(id) = self.archetypes[src.index()].remove_edges.get(&component_id) {
+            return *id;
+        }
+        let mut components = self.archetypes[src.index()].components.to_vec();
+        components.retain(|component| *component != component_id);
+        let dst = self.get_or_insert(components.into_boxed_slice(), infos);
+        self.archetypes[src.index()].remove_edges.insert(component_id, dst);
+        self.archetypes[dst.index()].add_edges.insert(component_id, src);
+        dst
+    }
+
+    /// Removes all entities from all archetypes, the archetypes themselves are kept.
+    pub(crate) fn clear_entities(&mut self) {
+        self.archetypes.iter_mut().for_each(Archetype::clear);
+    }
+
+    /// Clamps the ticks of all components stored in archetypes, see `Tick::check_tick`.
+    pub(crate) fn check_ticks(&mut self, tick: Tick) {
+        self.archetypes.iter_mut().for_each(|archetype| archetype.check_ticks(tick));
+    }
+
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        let mut archetypes = Self {
+            archetypes: vec![],
+            by_components: HashMap::new(),
+        };
+        archetypes.get_or_insert(Box::new([]), &Components::default());
+        archetypes
+    }
+}
@@ -71,8 +71,9 @@ impl AtomicBitSet {
 
     pub fn clear(&mut self) {
         for (i, bucket) in self.buckets.iter_mut().enumerate() {
+            // buckets get allocated on demand, so there might be gaps
             if bucket.get_mut().is_null() {
-                break;
+                continue;
             }
             unsafe { dealloc(bucket.get_mut().cast::<u8>(), Layout::array::<AtomicUsize>(1 << i).unwrap_unchecked()); }
             *bucket.get_mut() = null_mut();
@@ -81,11 +82,18 @@ impl AtomicBitSet {
 
 }
 
+impl Default for AtomicBitSet {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Drop for AtomicBitSet {
     fn drop(&mut self) {
         for (i, bucket) in self.buckets.iter_mut().enumerate() {
             if bucket.get_mut().is_null() {
-                break;
+                continue;
             }
             unsafe { dealloc(bucket.get_mut().cast::<u8>(), Layout::array::<AtomicUsize>(1 << i).unwrap_unchecked()); }
         }
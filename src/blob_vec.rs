@@ -0,0 +1,140 @@
+use std::alloc::{alloc, dealloc, handle_alloc_error, realloc, Layout};
+use std::ptr::{self, NonNull};
+
+/// A type erased `Vec` which stores its items contiguously, all items share the same `item_layout`.
+pub(crate) struct BlobVec {
+    item_layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+    data: NonNull<u8>,
+    len: usize,
+    capacity: usize,
+}
+
+impl BlobVec {
+
+    /// `drop` gets called for every item which gets dropped by the vec itself.
+    pub fn new(item_layout: Layout, drop: Option<unsafe fn(*mut u8)>) -> Self {
+        let item_layout = item_layout.pad_to_align();
+        Self {
+            item_layout,
+            drop,
+            data: dangling(item_layout),
+            len: 0,
+            // zero sized items never need any memory
+            capacity: if item_layout.size() == 0 { usize::MAX } else { 0 },
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let required = self.len.checked_add(additional).expect("capacity overflow");
+        if required <= self.capacity {
+            return;
+        }
+        let capacity = required.max(self.capacity * 2).max(4);
+        let new_layout = array_layout(self.item_layout, capacity);
+        let data = unsafe {
+            if self.capacity == 0 {
+                alloc(new_layout)
+            } else {
+                realloc(self.data.as_ptr(), array_layout(self.item_layout, self.capacity), new_layout.size())
+            }
+        };
+        self.data = NonNull::new(data).unwrap_or_else(|| handle_alloc_error(new_layout));
+        self.capacity = capacity;
+    }
+
+    /// The row has to be in bounds.
+    #[inline]
+    pub fn get_ptr(&self, row: usize) -> *mut u8 {
+        debug_assert!(row < self.len);
+        unsafe { self.data.as_ptr().add(row * self.item_layout.size()) }
+    }
+
+    /// # Safety
+    /// `value` has to point to a valid item, which the vec takes ownership of.
+    pub unsafe fn push(&mut self, value: *const u8) {
+        self.reserve(1);
+        self.len += 1;
+        ptr::copy_nonoverlapping(value, self.get_ptr(self.len - 1), self.item_layout.size());
+    }
+
+    /// Passes the item at `row` to `take`, which has to take ownership of it, and fills the gap with the last item.
+    ///
+    /// # Safety
+    /// `row` has to be in bounds.
+    pub unsafe fn swap_remove_with<R>(&mut self, row: usize, take: impl FnOnce(*mut u8) -> R) -> R {
+        let ret = take(self.get_ptr(row));
+        let last = self.len - 1;
+        if row != last {
+            ptr::copy_nonoverlapping(self.get_ptr(last), self.get_ptr(row), self.item_layout.size());
+        }
+        self.len -= 1;
+        ret
+    }
+
+    pub fn clear(&mut self) {
+        let len = self.len;
+        // the items are considered gone even if one of their drop fns panics
+        self.len = 0;
+        if let Some(drop) = self.drop {
+            for row in 0..len {
+                unsafe { drop(self.data.as_ptr().add(row * self.item_layout.size())); }
+            }
+        }
+    }
+
+}
+
+impl Drop for BlobVec {
+    fn drop(&mut self) {
+        self.clear();
+        if self.item_layout.size() != 0 && self.capacity != 0 {
+            unsafe { dealloc(self.data.as_ptr(), array_layout(self.item_layout, self.capacity)); }
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn dangling(layout: Layout) -> NonNull<u8> {
+    NonNull::new(ptr::without_provenance_mut(layout.align())).unwrap()
+}
+
+#[inline]
+fn array_layout(item_layout: Layout, len: usize) -> Layout {
+    Layout::from_size_align(item_layout.size().checked_mul(len).expect("capacity overflow"), item_layout.align()).expect("capacity overflow")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+    use std::rc::Rc;
+    use super::*;
+
+    unsafe fn drop_rc(ptr: *mut u8) {
+        ptr::drop_in_place(ptr.cast::<Rc<u32>>());
+    }
+
+    #[test]
+    fn push_and_swap_remove() {
+        let counter = Rc::new(0u32);
+        let mut vec = BlobVec::new(Layout::new::<Rc<u32>>(), Some(drop_rc));
+        for _ in 0..10 {
+            let value = counter.clone();
+            unsafe { vec.push((&value as *const Rc<u32>).cast::<u8>()); }
+            std::mem::forget(value);
+        }
+        assert_eq!(Rc::strong_count(&counter), 11);
+        let removed = unsafe { vec.swap_remove_with(3, |ptr| ptr::read(ptr.cast::<Rc<u32>>())) };
+        drop(removed);
+        assert_eq!(vec.len(), 9);
+        assert_eq!(Rc::strong_count(&counter), 10);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+}
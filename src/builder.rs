@@ -0,0 +1,29 @@
+use crate::{ComponentId, ComponentValue, EntityId, World};
+
+/// Collects components for an entity which gets spawned all at once when calling `spawn`,
+/// dropping the builder without calling `spawn` doesn't touch the world at all.
+pub struct EntityBuilder<'w> {
+    world: &'w mut World,
+    components: Vec<(ComponentId, ComponentValue)>,
+}
+
+impl<'w> EntityBuilder<'w> {
+
+    pub(crate) fn new(world: &'w mut World) -> Self {
+        Self {
+            world,
+            components: vec![],
+        }
+    }
+
+    pub fn with<CT: 'static>(mut self, component: CT) -> Self {
+        let component_id = self.world.components.init::<CT>();
+        self.components.push((component_id, ComponentValue::new(component)));
+        self
+    }
+
+    pub fn spawn(self) -> EntityId {
+        self.world.spawn_boxed(self.components)
+    }
+
+}
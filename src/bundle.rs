@@ -0,0 +1,53 @@
+use bevy_utils::all_tuples;
+use crate::{ComponentId, ComponentValue, Components};
+
+/// A group of components which gets added to or removed from an entity as a whole.
+pub trait Bundle: 'static {
+
+    /// Passes the ids of the bundle's component types to `id` in the same order `into_components` yields their values.
+    fn component_ids(components: &mut Components, id: &mut impl FnMut(ComponentId));
+
+    fn into_components(self, component: &mut impl FnMut(ComponentValue));
+
+    /// `component` has to yield the values in the order of `component_ids`.
+    fn from_components(component: &mut impl FnMut() -> ComponentValue) -> Self;
+
+}
+
+macro_rules! impl_bundle {
+    ($($name: ident),*) => {
+        impl<$($name: 'static),*> Bundle for ($($name,)*) {
+            #[allow(unused_variables)]
+            fn component_ids(components: &mut Components, id: &mut impl FnMut(ComponentId)) {
+                $(id(components.init::<$name>());)*
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn into_components(self, component: &mut impl FnMut(ComponentValue)) {
+                let ($($name,)*) = self;
+                $(component(ComponentValue::new($name));)*
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn from_components(component: &mut impl FnMut() -> ComponentValue) -> Self {
+                ($(component().downcast::<$name>().unwrap(),)*)
+            }
+        }
+    };
+}
+
+all_tuples!(impl_bundle, 0, 15, C);
+
+pub(crate) fn component_ids<B: Bundle>(components: &mut Components) -> Vec<ComponentId> {
+    let mut ids = vec![];
+    B::component_ids(components, &mut |id| ids.push(id));
+    ids
+}
+
+/// Pairs up the bundle's values with their component ids.
+pub(crate) fn bundle_components<B: Bundle>(components: &mut Components, bundle: B) -> Vec<(ComponentId, ComponentValue)> {
+    let mut ids = component_ids::<B>(components).into_iter();
+    let mut values = Vec::with_capacity(ids.len());
+    bundle.into_components(&mut |component| values.push((ids.next().unwrap(), component)));
+    values
+}
@@ -0,0 +1,97 @@
+use crate::bundle::Bundle;
+use crate::{EntityId, World};
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// Changes to a world which got recorded to be applied later on.
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Vec<Command>,
+}
+
+impl CommandQueue {
+
+    #[inline]
+    pub fn push(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.commands.push(Box::new(command));
+    }
+
+    /// Applies the commands in the order they were recorded and empties the queue.
+    pub fn apply(&mut self, world: &mut World) {
+        // entities spawned through commands only got reserved so far
+        world.flush();
+        for command in self.commands.drain(..) {
+            command(world);
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+}
+
+/// Records structural changes while the world is accessed through shared references, e.g. while iterating queries.
+/// As a system parameter the changes get applied after the system ran or at the next sync point of the schedule.
+pub struct Commands<'w, 's> {
+    queue: &'s mut CommandQueue,
+    world: &'w World,
+}
+
+impl<'w, 's> Commands<'w, 's> {
+
+    #[inline]
+    pub fn new(queue: &'s mut CommandQueue, world: &'w World) -> Self {
+        Self {
+            queue,
+            world,
+        }
+    }
+
+    /// The returned id is valid right away, but the entity only gets its components once the commands get applied.
+    pub fn spawn<B: Bundle + Send>(&mut self, bundle: B) -> EntityId {
+        let id = self.world.reserve_entity();
+        self.insert(id, bundle);
+        id
+    }
+
+    #[inline]
+    pub fn despawn(&mut self, id: EntityId) {
+        self.queue.push(move |world| {
+            world.despawn(id);
+        });
+    }
+
+    /// Does nothing if the entity got despawned by the time the commands get applied.
+    #[inline]
+    pub fn insert<B: Bundle + Send>(&mut self, id: EntityId, bundle: B) {
+        self.queue.push(move |world| {
+            if let Some(mut entity) = world.entity_mut(id) {
+                entity.insert_bundle(bundle);
+            }
+        });
+    }
+
+    /// Removes all components of the bundle at once, see `EntityMut::remove_bundle`.
+    #[inline]
+    pub fn remove<B: Bundle>(&mut self, id: EntityId) {
+        self.queue.push(move |world| {
+            if let Some(mut entity) = world.entity_mut(id) {
+                entity.remove_bundle::<B>();
+            }
+        });
+    }
+
+    /// Records an arbitrary change.
+    #[inline]
+    pub fn add(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.queue.push(command);
+    }
+
+}
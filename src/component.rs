@@ -0,0 +1,294 @@
+use std::alloc::Layout;
+use std::any::{type_name, TypeId};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::value::ValueVTable;
+use crate::{ComponentValue, EntityId, World};
+
+/// Where the values of a component type get stored.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum StorageKind {
+    /// Stored in the columns of the entity's archetype, this makes iteration fast but adding
+    /// and removing the component requires moving the entity into a different archetype.
+    #[default]
+    Table,
+    /// Stored in a separate sparse set indexed by entity, this is meant for components which get
+    /// added and removed frequently as doing so doesn't affect the entity's archetype.
+    SparseSet,
+}
+
+/// A small dense id the world assigns to every component type the first time it sees it,
+/// ids are only meaningful within the world which assigned them.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct ComponentId(u32);
+
+impl ComponentId {
+
+    #[inline(always)]
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    #[inline(always)]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+}
+
+/// Everything needed to store values of a component type without knowing the type itself.
+#[derive(Clone)]
+pub(crate) struct ComponentDescriptor {
+    name: Cow<'static, str>,
+    // dynamic components don't have a rust type
+    type_id: Option<TypeId>,
+    vtable: ValueVTable,
+}
+
+impl ComponentDescriptor {
+
+    fn of<CT: 'static>() -> Self {
+        Self {
+            name: Cow::Borrowed(type_name::<CT>()),
+            type_id: Some(TypeId::of::<CT>()),
+            vtable: ValueVTable::of::<CT>(),
+        }
+    }
+
+}
+
+pub(crate) type RequiredConstructor = Arc<dyn Fn() -> ComponentValue + Send + Sync>;
+
+pub type ComponentHook = Arc<dyn Fn(&mut World, EntityId) + Send + Sync>;
+
+/// Callbacks which run whenever a component of a specific type appears on, gets replaced on or disappears from an entity.
+#[derive(Clone, Default)]
+pub struct ComponentHooks {
+    pub(crate) on_add: Option<ComponentHook>,
+    pub(crate) on_replace: Option<ComponentHook>,
+    pub(crate) on_remove: Option<ComponentHook>,
+}
+
+impl ComponentHooks {
+
+    #[inline]
+    pub(crate) fn get(&self, kind: HookKind) -> Option<&ComponentHook> {
+        match kind {
+            HookKind::Add => self.on_add.as_ref(),
+            HookKind::Replace => self.on_replace.as_ref(),
+            HookKind::Remove => self.on_remove.as_ref(),
+        }
+    }
+
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum HookKind {
+    Add,
+    Replace,
+    Remove,
+}
+
+pub struct ComponentInfo {
+    id: ComponentId,
+    descriptor: ComponentDescriptor,
+    storage_kind: StorageKind,
+    hooks: ComponentHooks,
+    // components which get inserted alongside this one if the entity doesn't have them yet
+    required: Vec<(ComponentId, RequiredConstructor)>,
+    singleton: bool,
+}
+
+impl ComponentInfo {
+
+    #[inline(always)]
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.descriptor.name
+    }
+
+    /// Returns `None` for dynamic components.
+    #[inline(always)]
+    pub fn type_id(&self) -> Option<TypeId> {
+        self.descriptor.type_id
+    }
+
+    #[inline(always)]
+    pub fn is_dynamic(&self) -> bool {
+        self.descriptor.type_id.is_none()
+    }
+
+    #[inline(always)]
+    pub fn layout(&self) -> Layout {
+        self.descriptor.vtable.layout
+    }
+
+    #[inline(always)]
+    pub(crate) fn vtable(&self) -> ValueVTable {
+        self.descriptor.vtable
+    }
+
+    #[inline(always)]
+    pub fn storage_kind(&self) -> StorageKind {
+        self.storage_kind
+    }
+
+    /// Whether values of the component type are zero sized, these don't take up any storage space.
+    #[inline(always)]
+    pub fn is_zero_sized(&self) -> bool {
+        self.descriptor.vtable.layout.size() == 0
+    }
+
+    /// Whether at most one entity may have the component at a time.
+    #[inline(always)]
+    pub fn is_singleton(&self) -> bool {
+        self.singleton
+    }
+
+    #[inline(always)]
+    pub fn hooks(&self) -> &ComponentHooks {
+        &self.hooks
+    }
+
+    /// The components which get inserted alongside this one if the entity doesn't have them yet.
+    #[inline]
+    pub fn required(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.required.iter().map(|(component_id, _)| *component_id)
+    }
+
+    #[inline]
+    pub(crate) fn required_constructors(&self) -> &[(ComponentId, RequiredConstructor)] {
+        &self.required
+    }
+
+}
+
+#[derive(Default)]
+pub struct Components {
+    infos: Vec<ComponentInfo>,
+    ids: HashMap<TypeId, ComponentId>,
+}
+
+impl Components {
+
+    #[inline]
+    pub fn get_id(&self, ty: TypeId) -> Option<ComponentId> {
+        self.ids.get(&ty).copied()
+    }
+
+    #[inline]
+    pub fn get_info(&self, id: ComponentId) -> Option<&ComponentInfo> {
+        self.infos.get(id.index())
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.infos.iter()
+    }
+
+    /// Fixes the storage kind of a component type, this has to happen before the first value of the type gets stored.
+    pub(crate) fn register<CT: 'static>(&mut self, kind: StorageKind) -> ComponentId {
+        let id = self.init_raw(ComponentDescriptor::of::<CT>(), kind);
+        assert_eq!(self.infos[id.index()].storage_kind, kind, "component type was already registered with a different storage kind");
+        id
+    }
+
+    /// Singletons are stored in sparse sets, so their only entity can be found right away.
+    pub(crate) fn register_singleton<CT: 'static>(&mut self) -> ComponentId {
+        let id = self.register::<CT>(StorageKind::SparseSet);
+        self.infos[id.index()].singleton = true;
+        id
+    }
+
+    /// Returns the id of the component type, registering it with `Table` storage if it wasn't registered before.
+    #[inline]
+    pub(crate) fn init<CT: 'static>(&mut self) -> ComponentId {
+        self.init_raw(ComponentDescriptor::of::<CT>(), StorageKind::Table)
+    }
+
+    /// Registers the same component type another world described through `info` used.
+    #[inline]
+    pub(crate) fn init_like(&mut self, info: &ComponentInfo) -> ComponentId {
+        let id = self.init_raw(info.descriptor.clone(), info.storage_kind);
+        self.infos[id.index()].singleton |= info.singleton;
+        id
+    }
+
+    /// Registers a component type which is only known at runtime, every call registers a new component.
+    pub(crate) fn register_dynamic(&mut self, name: Cow<'static, str>, layout: Layout, drop: Option<unsafe fn(*mut u8)>,
+                                   storage_kind: StorageKind) -> ComponentId {
+        self.push(ComponentDescriptor {
+            name,
+            type_id: None,
+            vtable: ValueVTable {
+                layout,
+                drop,
+                any: None,
+            },
+        }, storage_kind)
+    }
+
+    fn init_raw(&mut self, descriptor: ComponentDescriptor, storage_kind: StorageKind) -> ComponentId {
+        let Some(ty) = descriptor.type_id else {
+            return self.push(descriptor, storage_kind);
+        };
+        match self.ids.get(&ty) {
+            Some(id) => *id,
+            None => {
+                let id = self.push(descriptor, storage_kind);
+                self.ids.insert(ty, id);
+                id
+            }
+        }
+    }
+
+    fn push(&mut self, descriptor: ComponentDescriptor, storage_kind: StorageKind) -> ComponentId {
+        let id = ComponentId(u32::try_from(self.infos.len()).expect("too many component types"));
+        self.infos.push(ComponentInfo {
+            id,
+            descriptor,
+            storage_kind,
+            hooks: ComponentHooks::default(),
+            required: vec![],
+            singleton: false,
+        });
+        id
+    }
+
+    /// Both components have to be registered, a previous constructor for the same requirement gets replaced.
+    pub(crate) fn require(&mut self, id: ComponentId, required: ComponentId, constructor: RequiredConstructor) {
+        let requirements = &mut self.infos[id.index()].required;
+        match requirements.iter_mut().find(|(component_id, _)| *component_id == required) {
+            Some((_, old)) => *old = constructor,
+            None => requirements.push((required, constructor)),
+        }
+    }
+
+    /// The component has to be registered.
+    #[inline]
+    pub(crate) fn hooks_mut(&mut self, id: ComponentId) -> &mut ComponentHooks {
+        &mut self.infos[id.index()].hooks
+    }
+
+    /// The component has to be registered.
+    #[inline]
+    pub(crate) fn storage_kind(&self, id: ComponentId) -> StorageKind {
+        self.infos[id.index()].storage_kind
+    }
+
+}
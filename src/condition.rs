@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+use crate::access::Access;
+use crate::error::SystemResult;
+use crate::system::{AnySystem, BoxedSystem, IntoSystem, ReadOnlySystem};
+use crate::{Tick, World};
+
+pub type BoxedCondition = Box<dyn ReadOnlySystem<In = (), Out = bool>>;
+
+/// A read-only system returning whether another system should run, e.g. `|query: Query<(Read<Enemy>,)>| !query.is_empty()`.
+pub trait Condition<Marker>: Sized {
+
+    fn into_condition(self) -> BoxedCondition;
+
+}
+
+impl<Marker, C: IntoSystem<Marker>> Condition<Marker> for C where C::System: ReadOnlySystem<In = (), Out = bool> {
+    #[inline]
+    fn into_condition(self) -> BoxedCondition {
+        Box::new(self.into_system())
+    }
+}
+
+/// Runs a system only if all its conditions hold, they get evaluated in the order they were added
+/// until one of them doesn't hold.
+pub(crate) struct ConditionalSystem {
+    name: Cow<'static, str>,
+    system: BoxedSystem<(), SystemResult>,
+    conditions: Vec<BoxedCondition>,
+    // the access of the system together with the ones of its conditions
+    access: Access,
+}
+
+impl ConditionalSystem {
+
+    #[inline]
+    pub fn new(name: Cow<'static, str>, system: BoxedSystem<(), SystemResult>, conditions: Vec<BoxedCondition>) -> Self {
+        Self {
+            name,
+            system,
+            conditions,
+            access: Access::default(),
+        }
+    }
+
+}
+
+impl AnySystem for ConditionalSystem {
+    type In = ();
+    type Out = SystemResult;
+
+    #[inline]
+    fn name(&self) -> Cow<'static, str> {
+        self.name.clone()
+    }
+
+    fn initialize(&mut self, world: &mut World) {
+        self.system.initialize(world);
+        let mut access = self.system.access().clone();
+        for condition in &mut self.conditions {
+            condition.initialize(world);
+            access.extend(condition.access());
+        }
+        self.access = access;
+    }
+
+    #[inline(always)]
+    fn access(&self) -> &Access {
+        &self.access
+    }
+
+    #[inline]
+    fn is_exclusive(&self) -> bool {
+        self.system.is_exclusive()
+    }
+
+    #[inline]
+    fn is_send(&self) -> bool {
+        self.system.is_send() && self.conditions.iter().all(|condition| condition.is_send())
+    }
+
+    unsafe fn run_unsafe(&mut self, _input: (), world: &World) -> SystemResult {
+        if self.conditions.iter_mut().all(|condition| condition.run_unsafe((), world)) {
+            return self.system.run_unsafe((), world);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn has_deferred(&self) -> bool {
+        self.system.has_deferred() || self.conditions.iter().any(|condition| condition.has_deferred())
+    }
+
+    fn apply_deferred(&mut self, world: &mut World) {
+        for condition in &mut self.conditions {
+            condition.apply_deferred(world);
+        }
+        self.system.apply_deferred(world);
+    }
+
+    fn run(&mut self, _input: (), world: &mut World) -> SystemResult {
+        self.initialize(world);
+        world.flush();
+        // SAFETY: the world is borrowed mutably
+        let run = self.conditions.iter_mut().all(|condition| unsafe { condition.run_unsafe((), world) });
+        for condition in &mut self.conditions {
+            condition.apply_deferred(world);
+        }
+        if run {
+            return self.system.run((), world);
+        }
+        Ok(())
+    }
+
+    fn check_change_tick(&mut self, tick: Tick) {
+        self.system.check_change_tick(tick);
+        for condition in &mut self.conditions {
+            condition.check_change_tick(tick);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::access::Access;
+use crate::query::{matches_component, QueryEntityError, StorageFetch};
+use crate::{Archetype, ArchetypeId, ComponentId, EntityId, StorageKind, Tick, World, WorldId};
+
+/// A single term of a `DynamicQuery`, only `Read` and `Write` terms fetch a pointer.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DynamicTerm {
+    Read(ComponentId),
+    Write(ComponentId),
+    With(ComponentId),
+    Without(ComponentId),
+}
+
+impl DynamicTerm {
+
+    #[inline]
+    pub fn component_id(self) -> ComponentId {
+        match self {
+            Self::Read(id) | Self::Write(id) | Self::With(id) | Self::Without(id) => id,
+        }
+    }
+
+    #[inline]
+    fn fetches(self) -> bool {
+        matches!(self, Self::Read(_) | Self::Write(_))
+    }
+
+}
+
+/// A query whose components are only known at runtime, e.g. for editors and scripting. Instead of typed
+/// items it yields one untyped pointer per `Read` and `Write` term, in the order of the terms.
+/// The state gets cached like the one of a `QueryState`.
+pub struct DynamicQuery {
+    world_id: WorldId,
+    terms: Vec<(DynamicTerm, StorageKind)>,
+    access: Access,
+    archetypes: Vec<ArchetypeId>,
+    seen_archetypes: usize,
+}
+
+impl DynamicQuery {
+
+    /// Panics if any of the components isn't registered in `world` or a written component gets accessed by another term too.
+    pub fn new(world: &World, terms: impl IntoIterator<Item = DynamicTerm>) -> Self {
+        let mut access = Access::default();
+        let terms = terms.into_iter().map(|term| {
+            let info = world.components.get_info(term.component_id()).expect("unknown component id");
+            match term {
+                DynamicTerm::Read(id) => {
+                    assert!(!access.has_write(id), "reading {} conflicts with a mutable access in the same query", info.name());
+                    access.add_read(id);
+                }
+                DynamicTerm::Write(id) => {
+                    assert!(!access.has_read(id), "writing {} conflicts with another access in the same query", info.name());
+                    access.add_write(id);
+                }
+                DynamicTerm::With(_) | DynamicTerm::Without(_) => {}
+            }
+            (term, info.storage_kind())
+        }).collect();
+        let mut query = Self {
+            world_id: world.id(),
+            terms,
+            access,
+            archetypes: vec![],
+            seen_archetypes: 0,
+        };
+        query.update_archetypes(world);
+        query
+    }
+
+    #[inline]
+    pub fn terms(&self) -> impl Iterator<Item = DynamicTerm> + '_ {
+        self.terms.iter().map(|(term, _)| *term)
+    }
+
+    #[inline(always)]
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// Matches the archetypes which were created since the last update, panics if `world` isn't the world the query was created for.
+    pub fn update_archetypes(&mut self, world: &World) {
+        assert_eq!(self.world_id, world.id(), "the query was created for a different world");
+        for archetype in world.archetypes.iter().skip(self.seen_archetypes) {
+            if self.matches_archetype(archetype) {
+                self.archetypes.push(archetype.id());
+            }
+        }
+        self.seen_archetypes = world.archetypes.len();
+    }
+
+    fn matches_archetype(&self, archetype: &Archetype) -> bool {
+        self.terms.iter().all(|(term, kind)| match term {
+            DynamicTerm::Without(id) => *kind == StorageKind::SparseSet || !archetype.contains(*id),
+            _ => matches_component(*kind, term.component_id(), archetype),
+        })
+    }
+
+    /// Calls `f` with the id and the pointers of every matching entity. The pointers of `Write` terms may be written
+    /// through and those components get marked as changed, the others may only be read from.
+    pub fn for_each(&mut self, world: &mut World, mut f: impl FnMut(EntityId, &[*mut u8])) {
+        world.flush();
+        self.update_archetypes(world);
+        let this_run = world.change_tick();
+        let mut fetches = Vec::with_capacity(self.terms.len());
+        let mut ptrs = Vec::with_capacity(self.terms.len());
+        for archetype in &self.archetypes {
+            let archetype = world.archetypes.get(*archetype).unwrap();
+            fetches.clear();
+            fetches.extend(self.terms.iter().map(|(term, _)| StorageFetch::new(world, archetype, term.component_id())));
+            for (row, id) in archetype.entities().iter().enumerate() {
+                // SAFETY: the world is borrowed mutably and the terms were checked for conflicting accesses
+                if unsafe { self.fetch(&fetches, *id, row, &mut ptrs, this_run) } {
+                    f(*id, &ptrs);
+                }
+            }
+        }
+    }
+
+    /// Returns the pointers of a single entity, they stay valid until the world gets modified.
+    pub fn get(&mut self, world: &mut World, id: EntityId) -> Result<Vec<*mut u8>, QueryEntityError> {
+        world.flush();
+        self.update_archetypes(world);
+        let location = world.entities.get_location(id).ok_or(QueryEntityError::NoSuchEntity(id))?;
+        let archetype = world.archetypes.get(location.archetype).unwrap();
+        if !self.matches_archetype(archetype) {
+            return Err(QueryEntityError::QueryDoesNotMatch(id));
+        }
+        let fetches = self.terms.iter().map(|(term, _)| StorageFetch::new(world, archetype, term.component_id())).collect::<Vec<_>>();
+        let mut ptrs = Vec::with_capacity(self.terms.len());
+        // SAFETY: the world is borrowed mutably and the terms were checked for conflicting accesses
+        match unsafe { self.fetch(&fetches, id, location.row, &mut ptrs, world.change_tick()) } {
+            true => Ok(ptrs),
+            false => Err(QueryEntityError::QueryDoesNotMatch(id)),
+        }
+    }
+
+    /// Fills `ptrs` with the pointers of the entity, returns whether it matched.
+    unsafe fn fetch(&self, fetches: &[StorageFetch<'_>], id: EntityId, row: usize, ptrs: &mut Vec<*mut u8>, this_run: Tick) -> bool {
+        ptrs.clear();
+        for ((term, _), fetch) in self.terms.iter().zip(fetches) {
+            let ptr = fetch.get(id, row);
+            if matches!(term, DynamicTerm::Without(_)) == ptr.is_some() {
+                return false;
+            }
+            if term.fetches() {
+                ptrs.push(ptr.unwrap());
+            }
+        }
+        // only mark components as changed once the entity is known to match
+        for ((term, _), fetch) in self.terms.iter().zip(fetches) {
+            if let DynamicTerm::Write(_) = term {
+                (*fetch.get_ticks(id, row).unwrap()).changed = this_run;
+            }
+        }
+        true
+    }
+
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use crate::archetype::{ArchetypeId, EntityLocation};
+use crate::atomic_bit_set::AtomicBitSet;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EntityId {
+    index: u32,
+    generation: NonZeroU32,
+}
+
+impl EntityId {
+
+    /// The slot this entity occupies, slots get reused after their entity was despawned.
+    #[inline(always)]
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    /// The number of times the slot of this entity was (re)used, this is what allows
+    /// ids of despawned entities to be told apart from ids of entities reusing their slot.
+    #[inline(always)]
+    pub fn generation(self) -> NonZeroU32 {
+        self.generation
+    }
+
+}
+
+/// Maps ids of entities from one world to the ids they got assigned in another one.
+pub type EntityIdMap = HashMap<EntityId, EntityId>;
+
+struct EntityMeta {
+    generation: NonZeroU32,
+    location: EntityLocation,
+}
+
+#[derive(Default)]
+pub(crate) struct Entities {
+    meta: Vec<EntityMeta>,
+    // indices of despawned entities which can be reused, the ones at `free_cursor..` were already
+    // handed out by `reserve_entity` but aren't flushed yet
+    pending: Vec<u32>,
+    // if positive, this is the number of pending indices which are still available for reservation,
+    // if negative, this is the number of reservations which happened past the end of `meta`
+    free_cursor: AtomicIsize,
+    // this includes reserved entities, which allows `contains` to see them without exclusive access
+    alive: AtomicBitSet,
+}
+
+impl Entities {
+
+    /// Reserves an id which can be used right away, but only becomes an actual entity
+    /// once `flush` gets called.
+    pub fn reserve_entity(&self) -> EntityId {
+        let n = self.free_cursor.fetch_sub(1, Ordering::Relaxed);
+        let id = if n > 0 {
+            let index = self.pending[(n - 1) as usize];
+            EntityId {
+                index,
+                generation: self.meta[index as usize].generation,
+            }
+        } else {
+            EntityId {
+                index: u32::try_from(self.meta.len() as isize - n).expect("too many entities"),
+                generation: NonZeroU32::MIN,
+            }
+        };
+        self.alive.add(id.index as usize);
+        id
+    }
+
+    pub fn alloc(&mut self) -> EntityId {
+        debug_assert!(!self.needs_flush());
+        if let Some(index) = self.pending.pop() {
+            *self.free_cursor.get_mut() = self.pending.len() as isize;
+            self.alive.add(index as usize);
+            return EntityId {
+                index,
+                generation: self.meta[index as usize].generation,
+            };
+        }
+        let index = u32::try_from(self.meta.len()).expect("too many entities");
+        self.meta.push(EntityMeta {
+            generation: NonZeroU32::MIN,
+            location: EntityLocation::INVALID,
+        });
+        self.alive.add(index as usize);
+        EntityId {
+            index,
+            generation: NonZeroU32::MIN,
+        }
+    }
+
+    #[inline]
+    pub fn needs_flush(&mut self) -> bool {
+        *self.free_cursor.get_mut() != self.pending.len() as isize
+    }
+
+    /// Turns all reserved ids into alive entities, `init` has to provide their location.
+    pub fn flush(&mut self, mut init: impl FnMut(EntityId, &mut EntityLocation)) {
+        let free_cursor = *self.free_cursor.get_mut();
+        let new_free_cursor = if free_cursor >= 0 {
+            free_cursor as usize
+        } else {
+            let old_len = self.meta.len();
+            let new_len = old_len + (-free_cursor) as usize;
+            self.meta.resize_with(new_len, || EntityMeta {
+                generation: NonZeroU32::MIN,
+                location: EntityLocation::INVALID,
+            });
+            for (index, meta) in self.meta[old_len..].iter_mut().enumerate() {
+                init(EntityId {
+                    index: (old_len + index) as u32,
+                    generation: NonZeroU32::MIN,
+                }, &mut meta.location);
+            }
+            0
+        };
+        for index in self.pending.drain(new_free_cursor..) {
+            let meta = &mut self.meta[index as usize];
+            init(EntityId {
+                index,
+                generation: meta.generation,
+            }, &mut meta.location);
+        }
+        *self.free_cursor.get_mut() = new_free_cursor as isize;
+    }
+
+    /// Returns the number of additional slots which have to be created to fit
+    /// `additional` more entities, only these slots get reserved.
+    pub fn reserve(&mut self, additional: usize) -> usize {
+        let new_slots = additional.saturating_sub(self.pending.len());
+        self.meta.reserve(new_slots);
+        new_slots
+    }
+
+    /// Returns whether the entity was alive before.
+    pub fn free(&mut self, id: EntityId) -> bool {
+        debug_assert!(!self.needs_flush());
+        if !self.contains(id) {
+            return false;
+        }
+        self.alive.remove(id.index as usize);
+        let meta = &mut self.meta[id.index as usize];
+        meta.location = EntityLocation::INVALID;
+        // once a slot's generation overflows we start over, this is only a problem if someone holds onto an id
+        // for 2^32 despawns of the same slot
+        meta.generation = meta.generation.checked_add(1).unwrap_or(NonZeroU32::MIN);
+        self.pending.push(id.index);
+        *self.free_cursor.get_mut() = self.pending.len() as isize;
+        true
+    }
+
+    /// Frees all alive entities while keeping the allocated slots around for reuse.
+    pub fn clear(&mut self) {
+        debug_assert!(!self.needs_flush());
+        for (index, meta) in self.meta.iter_mut().enumerate() {
+            if self.alive.remove(index) {
+                meta.location = EntityLocation::INVALID;
+                meta.generation = meta.generation.checked_add(1).unwrap_or(NonZeroU32::MIN);
+                self.pending.push(index as u32);
+            }
+        }
+        *self.free_cursor.get_mut() = self.pending.len() as isize;
+    }
+
+    /// The number of alive entities, not including reserved ones which weren't flushed yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.meta.len() - self.pending.len()
+    }
+
+    /// Returns where the entity's components are stored, reserved entities don't have a location until they get flushed.
+    #[inline]
+    pub fn get_location(&self, id: EntityId) -> Option<EntityLocation> {
+        if !self.contains(id) {
+            return None;
+        }
+        let location = self.meta.get(id.index as usize)?.location;
+        if location.archetype == ArchetypeId::INVALID {
+            return None;
+        }
+        Some(location)
+    }
+
+    /// The entity has to be alive and flushed.
+    #[inline]
+    pub fn set_location(&mut self, id: EntityId, location: EntityLocation) {
+        self.meta[id.index as usize].location = location;
+    }
+
+    /// Reserved entities are treated as alive even if they weren't flushed yet.
+    #[inline]
+    pub fn contains(&self, id: EntityId) -> bool {
+        if !self.alive.contains(id.index as usize) {
+            return false;
+        }
+        // reserved entities past the end of `meta` always start out with the first generation
+        let generation = self.meta.get(id.index as usize).map_or(NonZeroU32::MIN, |meta| meta.generation);
+        generation == id.generation
+    }
+
+}
@@ -0,0 +1,305 @@
+use std::any::{Any, TypeId};
+use bevy_utils::all_tuples;
+use crate::archetype::{Archetype, EntityLocation};
+use crate::bundle;
+use crate::{Bundle, ComponentId, ComponentInfo, ComponentTicks, ComponentValue, EntityId, Shared, World};
+
+/// A read-only view of an entity, many of these can exist for the same world at once.
+#[derive(Copy, Clone)]
+pub struct EntityRef<'w> {
+    world: &'w World,
+    id: EntityId,
+    location: EntityLocation,
+}
+
+impl<'w> EntityRef<'w> {
+
+    /// The entity has to be alive and stored at `location`.
+    #[inline]
+    pub(crate) fn new(world: &'w World, id: EntityId, location: EntityLocation) -> Self {
+        Self {
+            world,
+            id,
+            location,
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    #[inline(always)]
+    pub fn world(&self) -> &'w World {
+        self.world
+    }
+
+    #[inline]
+    pub fn archetype(&self) -> &'w Archetype {
+        self.world.archetypes.get(self.location.archetype).unwrap()
+    }
+
+    pub fn get_component<CT: 'static>(&self) -> Option<&'w CT> {
+        let component_id = self.world.component_id::<CT>()?;
+        self.world.component_any(self.id, self.location, component_id).map(|val| val.downcast_ref::<CT>().unwrap())
+    }
+
+    /// Describes all components the entity has, this includes their type names.
+    pub fn component_infos(&self) -> impl Iterator<Item = &'w ComponentInfo> + 'w {
+        let components = &self.world.components;
+        self.world.component_ids_of(self.id, self.location).into_iter().map(|component_id| components.get_info(component_id).unwrap())
+    }
+
+    /// Dynamic components are skipped as they don't have a rust type.
+    #[inline]
+    pub fn component_types(&self) -> impl Iterator<Item = TypeId> + 'w {
+        self.component_infos().filter_map(ComponentInfo::type_id)
+    }
+
+    /// Points to the value of the component, works for both dynamic and typed components.
+    /// The pointer stays valid until the world gets modified.
+    #[inline]
+    pub fn get_dynamic(&self, component_id: ComponentId) -> Option<*const u8> {
+        self.world.component_ptr(self.id, self.location, component_id).map(|ptr| ptr.cast_const())
+    }
+
+    /// When the entity's `CT` component was added and last changed.
+    pub fn component_ticks<CT: 'static>(&self) -> Option<ComponentTicks> {
+        let component_id = self.world.component_id::<CT>()?;
+        self.world.component_ticks(self.id, self.location, component_id)
+    }
+
+    /// Resolves the entity's `Shared<T>` handle.
+    pub fn get_shared<T: 'static>(&self) -> Option<&'w T> {
+        self.world.shared(*self.get_component::<Shared<T>>()?)
+    }
+
+    pub fn has_component<CT: 'static>(&self) -> bool {
+        self.world.component_id::<CT>().is_some_and(|component_id| self.world.contains_component(self.id, self.location, component_id))
+    }
+
+}
+
+/// An exclusive view of an entity which allows structural changes like adding and removing components.
+pub struct EntityMut<'w> {
+    world: &'w mut World,
+    id: EntityId,
+    location: EntityLocation,
+}
+
+impl<'w> EntityMut<'w> {
+
+    /// The entity has to be alive and stored at `location`.
+    #[inline]
+    pub(crate) fn new(world: &'w mut World, id: EntityId, location: EntityLocation) -> Self {
+        Self {
+            world,
+            id,
+            location,
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    #[inline(always)]
+    pub fn world(&self) -> &World {
+        self.world
+    }
+
+    #[inline]
+    pub fn archetype(&self) -> &Archetype {
+        self.world.archetypes.get(self.location.archetype).unwrap()
+    }
+
+    #[inline]
+    pub fn as_ref(&self) -> EntityRef<'_> {
+        EntityRef::new(self.world, self.id, self.location)
+    }
+
+    pub fn add_component<CT: 'static>(&mut self, component: CT) {
+        let component_id = self.world.components.init::<CT>();
+        self.insert_boxed(component_id, ComponentValue::new(component));
+    }
+
+    /// Like `add_component`, but hands out the value the component had before.
+    pub fn replace_component<CT: 'static>(&mut self, component: CT) -> Option<CT> {
+        let component_id = self.world.components.init::<CT>();
+        self.insert_boxed(component_id, ComponentValue::new(component)).map(|old| old.downcast::<CT>().unwrap())
+    }
+
+    pub(crate) fn insert_boxed(&mut self, component_id: ComponentId, component: ComponentValue) -> Option<ComponentValue> {
+        let (location, old) = self.world.insert_component(self.id, self.location, component_id, component);
+        self.location = location;
+        old
+    }
+
+    /// Adds all components of the bundle at once, values of component types the entity already has get replaced.
+    pub fn insert_bundle<B: Bundle>(&mut self, bundle: B) {
+        let components = bundle::bundle_components(&mut self.world.components, bundle);
+        let (location, _) = self.world.insert_components(self.id, self.location, components);
+        self.location = location;
+    }
+
+    /// Removes all components of the bundle at once, if the entity lacks any of them it stays unchanged and `None` gets returned.
+    pub fn remove_bundle<B: Bundle>(&mut self) -> Option<B> {
+        let components = bundle::component_ids::<B>(&mut self.world.components);
+        let (location, removed) = self.world.remove_components(self.id, self.location, &components)?;
+        self.location = location;
+        let mut removed = removed.into_iter();
+        Some(B::from_components(&mut || removed.next().unwrap()))
+    }
+
+    pub fn remove_component<CT: 'static>(&mut self) -> Option<Box<CT>> {
+        let component_id = self.world.component_id::<CT>()?;
+        let (location, removed) = self.world.remove_component(self.id, self.location, component_id);
+        self.location = location;
+        removed.map(|val| Box::new(val.downcast::<CT>().unwrap()))
+    }
+
+    /// Strips all components off the entity and hands them out, the entity itself stays alive without any components.
+    /// Dynamic components get dropped instead.
+    pub fn take_components(&mut self) -> impl Iterator<Item = (TypeId, Box<dyn Any>)> {
+        let (location, taken) = self.world.take_components(self.id, self.location);
+        self.location = location;
+        taken.into_iter()
+            .filter_map(|(component_id, component)| Some((self.world.type_id_of(component_id)?, component.into_box().ok()?)))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    pub fn get_component<CT: 'static>(&self) -> Option<&CT> {
+        self.as_ref().get_component::<CT>()
+    }
+
+    /// Adds the value `component` points to as the component `component_id`, replacing a previous value.
+    ///
+    /// # Safety
+    /// `component` has to point to a valid value of the component's layout, which the entity takes ownership of.
+    /// The memory itself stays owned by the caller.
+    pub unsafe fn insert_dynamic(&mut self, component_id: ComponentId, component: *const u8) {
+        let vtable = self.world.components.get_info(component_id).expect("unknown component").vtable();
+        self.insert_boxed(component_id, ComponentValue::read(component, vtable));
+    }
+
+    #[inline]
+    pub fn get_dynamic(&self, component_id: ComponentId) -> Option<*const u8> {
+        self.as_ref().get_dynamic(component_id)
+    }
+
+    /// Marks the component as changed.
+    #[inline]
+    pub fn get_dynamic_mut(&mut self, component_id: ComponentId) -> Option<*mut u8> {
+        self.world.component_ptr_mut(self.id, self.location, component_id)
+    }
+
+    /// Removes and drops the component, returns whether the entity had it.
+    pub fn remove_dynamic(&mut self, component_id: ComponentId) -> bool {
+        let (location, removed) = self.world.remove_component(self.id, self.location, component_id);
+        self.location = location;
+        removed.is_some()
+    }
+
+    #[inline]
+    pub fn get_shared<T: 'static>(&self) -> Option<&T> {
+        self.as_ref().get_shared::<T>()
+    }
+
+    #[inline]
+    pub fn has_component<CT: 'static>(&self) -> bool {
+        self.as_ref().has_component::<CT>()
+    }
+
+    #[inline]
+    pub fn component_infos(&self) -> impl Iterator<Item = &ComponentInfo> + '_ {
+        self.as_ref().component_infos()
+    }
+
+    #[inline]
+    pub fn component_types(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.as_ref().component_types()
+    }
+
+    pub fn get_component_mut<CT: 'static>(&mut self) -> Option<&mut CT> {
+        let component_id = self.world.component_id::<CT>()?;
+        self.world.component_any_mut(self.id, self.location, component_id).map(|val| val.downcast_mut::<CT>().unwrap())
+    }
+
+    /// Returns the entity's `CT` component, adding the one `f` creates first if the entity doesn't have one yet.
+    pub fn get_or_insert_with<CT: 'static>(&mut self, f: impl FnOnce() -> CT) -> &mut CT {
+        if !self.has_component::<CT>() {
+            self.add_component(f());
+        }
+        self.get_component_mut::<CT>().expect("component got removed by a hook while being inserted")
+    }
+
+    /// Adds the default value of `CT` if the entity doesn't have a `CT` component yet, an existing one is kept as is.
+    #[inline]
+    pub fn insert_default<CT: Default + 'static>(&mut self) -> &mut CT {
+        self.get_or_insert_with(CT::default)
+    }
+
+    /// Borrows multiple components mutably at once, returns `None` if the entity lacks any of them.
+    /// Panics if the same component type is requested more than once.
+    pub fn get_components_mut<C: ComponentsMut>(&mut self) -> Option<C::Item<'_>> {
+        let component_ids = C::component_ids(self.world)?;
+        for (idx, component_id) in component_ids.iter().enumerate() {
+            assert!(!component_ids[..idx].contains(component_id), "the same component type can't be borrowed mutably more than once");
+        }
+        let mut components = Vec::with_capacity(component_ids.len());
+        for component_id in component_ids {
+            components.push(self.world.component_any_mut(self.id, self.location, component_id)? as *mut dyn Any);
+        }
+        let mut components = components.into_iter();
+        // SAFETY: all components are distinct, so none of the references alias
+        Some(unsafe { C::from_ptrs(&mut || components.next().unwrap()) })
+    }
+
+    pub fn despawn(self) {
+        self.world.despawn(self.id);
+    }
+
+}
+
+/// Tuples of component types which can be borrowed mutably all at once through `EntityMut::get_components_mut`.
+pub trait ComponentsMut {
+
+    type Item<'a>;
+
+    /// Returns `None` if any of the component types is unknown to the world.
+    fn component_ids(world: &World) -> Option<Vec<ComponentId>>;
+
+    /// # Safety
+    /// `ptr` has to yield valid and unaliased pointers to the components in the order of `component_ids`.
+    unsafe fn from_ptrs<'a>(ptr: &mut impl FnMut() -> *mut dyn Any) -> Self::Item<'a>;
+
+}
+
+macro_rules! impl_components_mut {
+    ($($name: ident),*) => {
+        impl<$($name: 'static),*> ComponentsMut for ($($name,)*) {
+            type Item<'a> = ($(&'a mut $name,)*);
+
+            #[allow(unused_variables)]
+            fn component_ids(world: &World) -> Option<Vec<ComponentId>> {
+                Some(vec![$(world.component_id::<$name>()?),*])
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            unsafe fn from_ptrs<'a>(ptr: &mut impl FnMut() -> *mut dyn Any) -> Self::Item<'a> {
+                ($((*ptr()).downcast_mut::<$name>().unwrap(),)*)
+            }
+        }
+    };
+}
+
+all_tuples!(impl_components_mut, 1, 15, C);
+
+impl<'w> From<EntityMut<'w>> for EntityRef<'w> {
+    #[inline]
+    fn from(entity: EntityMut<'w>) -> Self {
+        EntityRef::new(entity.world, entity.id, entity.location)
+    }
+}
@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::fmt;
+
+/// The error of a fallible system, errors of any type convert into it through `?`.
+pub struct SystemError(Box<dyn Error + Send + Sync>);
+
+impl SystemError {
+
+    /// Also accepts messages, e.g. `SystemError::new("the level failed to load")`.
+    #[inline]
+    pub fn new(error: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self(error.into())
+    }
+
+    #[inline(always)]
+    pub fn inner(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.0
+    }
+
+}
+
+impl<E: Error + Send + Sync + 'static> From<E> for SystemError {
+    #[inline]
+    fn from(error: E) -> Self {
+        Self(Box::new(error))
+    }
+}
+
+impl fmt::Debug for SystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for SystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+pub type SystemResult = Result<(), SystemError>;
+
+/// What the systems of a `Schedule` may return.
+pub trait SystemOutput {
+
+    fn into_result(self) -> SystemResult;
+
+}
+
+impl SystemOutput for () {
+    #[inline(always)]
+    fn into_result(self) -> SystemResult {
+        Ok(())
+    }
+}
+
+impl SystemOutput for SystemResult {
+    #[inline(always)]
+    fn into_result(self) -> SystemResult {
+        self
+    }
+}
+
+/// Gets called with the name of the failed system and its error, see `Schedule::set_error_handler`.
+pub type ErrorHandler = Box<dyn Fn(&str, SystemError) + Send + Sync>;
+
+/// The error handler schedules use by default.
+pub fn panic_on_error(system: &str, error: SystemError) {
+    panic!("{system} failed: {error}");
+}
+
+/// Prints the error to stderr and keeps going.
+pub fn log_error(system: &str, error: SystemError) {
+    eprintln!("{system} failed: {error}");
+}
@@ -0,0 +1,208 @@
+use std::mem;
+use crate::component::ComponentId;
+use crate::entity::EntityId;
+use crate::system_param::{Res, ResMut};
+
+/// When the events of a type get dropped, see `World::add_event_with_policy`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum EventUpdatePolicy {
+    /// Events are double buffered, they get dropped on the second update after they were sent.
+    /// Schedules with `Schedule::set_update_events` enabled update them at the start of every run.
+    #[default]
+    Automatic,
+    /// Like `Automatic`, but only calling `Events::update` explicitly updates the events, e.g. once per turn.
+    Manual,
+    /// Updates don't drop events, only `Events::clear` does.
+    Unbounded,
+}
+
+/// Events of type `T` which systems send through an `EventWriter` and receive through an `EventReader`, stored as a resource
+/// through `World::add_event`. By default, events get dropped on the second update after they were sent, so readers running
+/// once between updates see every event.
+pub struct Events<T> {
+    // the events sent before the last update followed by the ones sent since, each with their id
+    previous: Vec<(usize, T)>,
+    current: Vec<(usize, T)>,
+    // the id of the next event
+    event_count: usize,
+    policy: EventUpdatePolicy,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::with_policy(EventUpdatePolicy::default())
+    }
+}
+
+impl<T> Events<T> {
+
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_policy(policy: EventUpdatePolicy) -> Self {
+        Self {
+            previous: vec![],
+            current: vec![],
+            event_count: 0,
+            policy,
+        }
+    }
+
+    #[inline(always)]
+    pub fn policy(&self) -> EventUpdatePolicy {
+        self.policy
+    }
+
+    #[inline]
+    pub fn set_policy(&mut self, policy: EventUpdatePolicy) {
+        self.policy = policy;
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push((self.event_count, event));
+        self.event_count += 1;
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        for event in events {
+            self.send(event);
+        }
+    }
+
+    /// Drops the events sent before the previous update, unless the events are unbounded.
+    pub fn update(&mut self) {
+        if self.policy != EventUpdatePolicy::Unbounded {
+            mem::swap(&mut self.previous, &mut self.current);
+            self.current.clear();
+        }
+    }
+
+    /// Drops all events, readers which didn't read them yet miss them.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.current.clear();
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && self.current.is_empty()
+    }
+
+    /// All stored events, oldest first.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(&self.current).map(|(_, event)| event)
+    }
+
+    // the events with an id of at least `cursor`
+    fn iter_from(&self, cursor: usize) -> impl Iterator<Item = &T> {
+        let first = self.previous.first().or(self.current.first()).map_or(0, |(id, _)| *id);
+        self.iter().skip(cursor.saturating_sub(first))
+    }
+
+}
+
+/// Receives the events of type `T`, every reader keeps track of the events it read already.
+/// Readers see the events sent before they ran for the first time as long as they are still stored.
+pub struct EventReader<'w, 's, T> {
+    events: Res<'w, Events<T>>,
+    // the id of the first event which wasn't read yet
+    cursor: &'s mut usize,
+}
+
+impl<'w, 's, T> EventReader<'w, 's, T> {
+
+    #[inline]
+    pub(crate) fn new(events: Res<'w, Events<T>>, cursor: &'s mut usize) -> Self {
+        Self {
+            events,
+            cursor,
+        }
+    }
+
+    /// The events which weren't read yet, they count as read afterwards.
+    pub fn read(&mut self) -> impl Iterator<Item = &T> {
+        let events = &*self.events;
+        let cursor = std::mem::replace(self.cursor, events.event_count);
+        events.iter_from(cursor)
+    }
+
+    /// The number of events which weren't read yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.events.iter_from(*self.cursor).count()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks all events as read.
+    #[inline]
+    pub fn clear(&mut self) {
+        *self.cursor = self.events.event_count;
+    }
+
+}
+
+/// Sends events of type `T`.
+pub struct EventWriter<'w, T> {
+    events: ResMut<'w, Events<T>>,
+}
+
+impl<'w, T> EventWriter<'w, T> {
+
+    #[inline]
+    pub(crate) fn new(events: ResMut<'w, Events<T>>) -> Self {
+        Self {
+            events,
+        }
+    }
+
+    #[inline]
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+
+    #[inline]
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.events.send_batch(events);
+    }
+
+}
+
+/// Sent after an entity got spawned, including reserved entities once they get flushed. Lifecycle events are only sent
+/// after `World::add_lifecycle_events` got called.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct EntitySpawned {
+    pub entity: EntityId,
+}
+
+/// Sent after an entity got despawned, following a `ComponentRemoved` for each of its components.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct EntityDespawned {
+    pub entity: EntityId,
+}
+
+/// Sent after a component got added to an entity which didn't have one of its type before.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ComponentAdded {
+    pub entity: EntityId,
+    pub component: ComponentId,
+}
+
+/// Sent when a component gets removed from an entity, this includes despawning the entity.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ComponentRemoved {
+    pub entity: EntityId,
+    pub component: ComponentId,
+}
@@ -0,0 +1,225 @@
+use std::any::Any;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::error::{SystemError, SystemResult};
+use crate::system::BoxedSystem;
+use crate::World;
+
+/// How a `Schedule` runs its systems.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ExecutorKind {
+    /// Runs one system after another on the calling thread, the deferred changes of each system get applied right after it ran.
+    #[default]
+    SingleThreaded,
+    /// Runs systems whose accesses don't conflict on a pool of threads at once, systems which conflict still run
+    /// in the order they were added. The components the systems access have to be `Send` and `Sync`.
+    /// Exclusive systems run on the calling thread once all systems before them finished, before any later one starts.
+    /// Deferred changes get applied at these sync points and after the last system, in the order of the systems.
+    /// Unless disabled through `Schedule::set_auto_sync`, there also are sync points before systems ordered after systems
+    /// with deferred changes, e.g. ones using `Commands`. `apply_deferred` adds a sync point explicitly.
+    /// Systems which aren't `Send`, e.g. ones taking `&World`, only run on the calling thread, which takes part in running
+    /// the other systems too.
+    MultiThreaded,
+}
+
+/// The systems each system has to wait for.
+pub(crate) struct SystemGraph {
+    dependents: Vec<Vec<usize>>,
+    // the systems before which the deferred changes of the earlier systems get applied, besides exclusive systems
+    sync_points: Vec<usize>,
+}
+
+impl SystemGraph {
+
+    /// Systems depend on all earlier systems they conflict with and the ones in `dependencies`,
+    /// given as `(earlier, later)` pairs. With `auto_sync` systems see the deferred changes of the systems they depend on explicitly.
+    pub fn new(systems: &[ScheduledSystem], dependencies: &[(usize, usize)], auto_sync: bool) -> Self {
+        let mut dependents = vec![vec![]; systems.len()];
+        for (later, system) in systems.iter().enumerate() {
+            for (earlier, other) in systems[..later].iter().enumerate() {
+                if !system.access().is_compatible(other.access()) || dependencies.contains(&(earlier, later)) {
+                    dependents[earlier].push(later);
+                }
+            }
+        }
+        let mut sync_points = vec![];
+        if auto_sync {
+            // the deferred changes of the systems before `start` got applied already
+            let mut start = 0;
+            for (later, system) in systems.iter().enumerate() {
+                if system.is_exclusive() {
+                    start = later + 1;
+                } else if dependencies.iter().any(|(earlier, other)| *other == later && *earlier >= start && systems[*earlier].has_deferred()) {
+                    sync_points.push(later);
+                    start = later;
+                }
+            }
+        }
+        Self {
+            dependents,
+            sync_points,
+        }
+    }
+
+}
+
+/// Makes pointers shareable between the worker threads, the graph ensures accesses through them don't conflict.
+#[derive(Copy, Clone)]
+struct SharedPtr<T: ?Sized>(*mut T);
+
+impl<T: ?Sized> SharedPtr<T> {
+
+    // closures capturing the pointer itself instead of the wrapper wouldn't be `Send`
+    #[inline(always)]
+    fn get(&self) -> *mut T {
+        self.0
+    }
+
+}
+
+unsafe impl<T: ?Sized> Send for SharedPtr<T> {}
+unsafe impl<T: ?Sized> Sync for SharedPtr<T> {}
+
+struct ExecutorState {
+    ready: Vec<usize>,
+    // only the calling thread runs these
+    ready_non_send: Vec<usize>,
+    remaining: Vec<usize>,
+    finished: usize,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+type ScheduledSystem = BoxedSystem<(), SystemResult>;
+
+type ErrorHandler<'a> = &'a (dyn Fn(&str, SystemError) + Send + Sync);
+
+// how long each system took, if the schedule records it
+type Timings<'a> = Option<&'a mut [Option<Duration>]>;
+
+pub(crate) fn run_single_threaded(systems: &mut [ScheduledSystem], world: &mut World, handler: ErrorHandler, mut timings: Timings) {
+    for (idx, system) in systems.iter_mut().enumerate() {
+        let started = timings.is_some().then(Instant::now);
+        let result = system.run((), world);
+        if let (Some(timings), Some(started)) = (&mut timings, started) {
+            timings[idx] = Some(started.elapsed());
+        }
+        if let Err(error) = result {
+            handler(&system.name(), error);
+        }
+        world.run_deferred_observers();
+    }
+}
+
+/// The systems have to be initialized for `world` already.
+pub(crate) fn run_multi_threaded(systems: &mut [ScheduledSystem], graph: &SystemGraph, world: &mut World, handler: ErrorHandler, mut timings: Timings) {
+    let mut start = 0;
+    for idx in 0..systems.len() {
+        if systems[idx].is_exclusive() {
+            run_parallel(systems, graph, start..idx, world, handler, timings.as_deref_mut());
+            run_single_threaded(&mut systems[idx..=idx], world, handler, timings.as_deref_mut().map(|timings| &mut timings[idx..=idx]));
+            start = idx + 1;
+        } else if graph.sync_points.contains(&idx) {
+            run_parallel(systems, graph, start..idx, world, handler, timings.as_deref_mut());
+            start = idx;
+        }
+    }
+    run_parallel(systems, graph, start..systems.len(), world, handler, timings);
+}
+
+// runs the systems in `range`, none of them may be exclusive
+fn run_parallel(systems: &mut [ScheduledSystem], graph: &SystemGraph, range: Range<usize>, world: &mut World, handler: ErrorHandler, timings: Timings) {
+    if range.is_empty() {
+        return;
+    }
+    world.flush();
+    // the calling thread works as well, it's the only one running systems which aren't `Send`
+    let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get).min(range.len()) - 1;
+    // systems before the range already finished
+    let mut remaining = vec![0; systems.len()];
+    for system in range.clone() {
+        for dependent in &graph.dependents[system] {
+            remaining[*dependent] += 1;
+        }
+    }
+    let send = systems.iter().map(|system| system.is_send()).collect::<Vec<_>>();
+    let (ready, ready_non_send) = range.clone().rev()
+        .filter(|system| remaining[*system] == 0)
+        .partition(|system| send[*system]);
+    let state = Mutex::new(ExecutorState {
+        ready,
+        ready_non_send,
+        remaining,
+        finished: 0,
+        panic: None,
+    });
+    let changed = Condvar::new();
+    let world_ptr = SharedPtr(world as *mut World);
+    let systems_ptr = SharedPtr(systems as *mut [ScheduledSystem]);
+    let timings_ptr = timings.map(|timings| SharedPtr(timings as *mut [Option<Duration>]));
+    let work = |calling_thread: bool| loop {
+        let system = {
+            let mut state = state.lock().unwrap();
+            loop {
+                if state.finished == range.len() || state.panic.is_some() {
+                    return;
+                }
+                let next = if calling_thread {
+                    state.ready_non_send.pop().or_else(|| state.ready.pop())
+                } else {
+                    state.ready.pop()
+                };
+                if let Some(system) = next {
+                    break system;
+                }
+                state = changed.wait(state).unwrap();
+            }
+        };
+        // SAFETY: every system runs once and only at the same time as systems it doesn't conflict with
+        // the handler runs in here as well, as it might panic
+        let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            let started = timings_ptr.is_some().then(Instant::now);
+            let result = (*systems_ptr.get())[system].run_unsafe((), &*world_ptr.get());
+            if let (Some(timings), Some(started)) = (&timings_ptr, started) {
+                (*timings.get())[system] = Some(started.elapsed());
+            }
+            if let Err(error) = result {
+                handler(&(*systems_ptr.get())[system].name(), error);
+            }
+        }));
+        let mut state = state.lock().unwrap();
+        match result {
+            Ok(()) => {
+                state.finished += 1;
+                for dependent in graph.dependents[system].iter().filter(|dependent| range.contains(*dependent)) {
+                    state.remaining[*dependent] -= 1;
+                    if state.remaining[*dependent] == 0 {
+                        if send[*dependent] {
+                            state.ready.push(*dependent);
+                        } else {
+                            state.ready_non_send.push(*dependent);
+                        }
+                    }
+                }
+            }
+            Err(payload) => state.panic = Some(payload),
+        }
+        changed.notify_all();
+    };
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| work(false));
+        }
+        work(true);
+    });
+    if let Some(payload) = state.into_inner().unwrap().panic {
+        panic::resume_unwind(payload);
+    }
+    for system in &mut systems[range] {
+        system.apply_deferred(world);
+    }
+    world.run_deferred_observers();
+}
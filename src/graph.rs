@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// The resolved order of a schedule's systems, see `Schedule::graph`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ScheduleGraph {
+    /// The systems in the order they get started in.
+    pub nodes: Vec<SystemNode>,
+    pub edges: Vec<SystemEdge>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SystemNode {
+    pub name: Cow<'static, str>,
+    /// The names of the components and resources the system only reads.
+    pub reads: Vec<String>,
+    pub writes: Vec<String>,
+    /// Whether the system reads all components and resources, e.g. through `&World`.
+    pub reads_all: bool,
+    /// Exclusive systems run on their own, all systems before them finish before they start.
+    pub exclusive: bool,
+}
+
+/// The system at `to` only starts once the one at `from` finished.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SystemEdge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EdgeKind {
+    /// The systems got ordered explicitly, e.g. through `before` or a set.
+    Ordering,
+    /// The systems conflict, so they run in the order they were added.
+    Conflict,
+}
+
+impl ScheduleGraph {
+
+    /// Renders the graph in the DOT language of graphviz, conflicts are drawn as dashed edges.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph schedule {\n");
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let mut label = escape(&node.name);
+            if node.reads_all {
+                label.push_str("\\nreads: everything");
+            } else if !node.reads.is_empty() {
+                let _ = write!(label, "\\nreads: {}", escape(&node.reads.join(", ")));
+            }
+            if !node.writes.is_empty() {
+                let _ = write!(label, "\\nwrites: {}", escape(&node.writes.join(", ")));
+            }
+            let shape = if node.exclusive { "box" } else { "ellipse" };
+            let _ = writeln!(dot, "    {idx} [label=\"{label}\", shape={shape}];");
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Ordering => "solid",
+                EdgeKind::Conflict => "dashed",
+            };
+            let _ = writeln!(dot, "    {} -> {} [style={style}];", edge.from, edge.to);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
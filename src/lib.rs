@@ -1,149 +1,2877 @@
 #![feature(tuple_trait)]
+#![feature(ptr_metadata)]
 
+mod access;
+mod archetype;
 mod atomic_bit_set;
+mod blob_vec;
+mod builder;
+mod bundle;
+mod commands;
+mod component;
+mod condition;
+mod dynamic_query;
+mod entity;
+mod entity_ref;
+mod error;
+mod event;
+mod executor;
+mod graph;
+mod observer;
+mod query;
+mod resource;
+mod schedule;
+mod shared;
+mod sparse_set;
+mod system;
+mod system_param;
+mod task_pool;
+mod tick;
+mod value;
+mod world;
 
-use std::any::{Any, TypeId};
-use std::collections::HashMap;
-use std::marker::Tuple;
-use std::num::NonZeroUsize;
+use std::any::TypeId;
 use std::ops::{Deref, DerefMut};
-use bevy_utils::all_tuples;
 
-pub struct World {
-    entities: HashMap<EntityId, Entity>,
-    entity_cnt: NonZeroUsize,
+pub use crate::access::{Access, ComponentSet};
+pub use crate::archetype::{Archetype, ArchetypeId, Archetypes};
+pub use crate::atomic_bit_set::AtomicBitSet;
+pub use crate::builder::EntityBuilder;
+pub use crate::bundle::Bundle;
+pub use crate::commands::{CommandQueue, Commands};
+pub use crate::condition::{BoxedCondition, Condition};
+pub use crate::component::{ComponentHook, ComponentHooks, ComponentId, ComponentInfo, Components, StorageKind};
+pub use crate::dynamic_query::{DynamicQuery, DynamicTerm};
+pub use crate::entity::{EntityId, EntityIdMap};
+pub use crate::entity_ref::{ComponentsMut, EntityMut, EntityRef};
+pub use crate::error::{log_error, panic_on_error, ErrorHandler, SystemError, SystemOutput, SystemResult};
+pub use crate::event::{ComponentAdded, ComponentRemoved, EntityDespawned, EntitySpawned, EventReader, EventUpdatePolicy, EventWriter, Events};
+pub use crate::executor::ExecutorKind;
+pub use crate::graph::{EdgeKind, ScheduleGraph, SystemEdge, SystemNode};
+pub use crate::observer::{Observer, ObserverId, Trigger};
+pub use crate::query::{Added, AnyOf, Changed, Filter, Has, Or, Predicate, Query, QueryCombinationIter, QueryEntityError, QueryIter, QueryJoinIter, QueryLeftJoinIter, QueryManyIter, QuerySingleError, QueryState, ReadOnlyWorldQuery, With, Without, WorldQuery};
+pub use crate::resource::ResourceId;
+pub use crate::schedule::{apply_deferred, Ambiguity, IntoSetConfig, IntoSystemConfig, RunReport, Schedule, ScheduleLabel, SetConfig, SystemConfig, SystemInfo, SystemLabel, SystemSet, SystemTiming};
+pub use crate::shared::Shared;
+pub use crate::system::{deconstruct_access, deconstruct_params, AnySystem, AsyncSystem, Argument, BoxedSystem, ExclusiveFunctionSystem, FunctionSystem, IntoSystem, PipeSystem, ReadOnlySystem, System, SystemArg, SystemArgs, SystemParamFunction};
+pub use crate::task_pool::TaskPool;
+pub use crate::system_param::{In, Local, NonSend, NonSendMut, ReadOnlySystemParam, Res, ResMut, SystemParam, SystemParamItem};
+pub use crate::tick::{ComponentTicks, Tick, CHECK_TICK_THRESHOLD, MAX_CHANGE_AGE};
+pub use crate::value::ComponentValue;
+pub use crate::world::{FromWorld, World, WorldId, WorldStats};
+
+/// The component type a system argument accesses.
+pub trait InnerId {
+
+    fn inner_id() -> TypeId;
+
 }
 
-impl World {
+/// Shared access to a component of the entity a `System` runs for.
+pub struct Read<'a, T>(&'a T);
 
-    pub fn new_entity(&mut self) -> &mut Entity {
-        let id = self.entity_cnt;
-        self.entity_cnt = id.checked_add(1).unwrap();
-        self.entities.entry(id).or_insert(Entity {
-            id,
-            components: HashMap::new(),
-        })
+impl<'a, T: 'static> InnerId for Read<'a, T> {
+    fn inner_id() -> TypeId {
+        TypeId::of::<T>()
     }
-
 }
 
-impl Default for World {
-    fn default() -> Self {
-        Self {
-            entities: Default::default(),
-            entity_cnt: NonZeroUsize::new(1).unwrap(),
-        }
+impl<'a, T> Deref for Read<'a, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        self.0
     }
 }
 
-pub struct Entity {
-    id: NonZeroUsize,
-    components: HashMap<TypeId, Box<dyn Any>>,
+/// Exclusive access to a component of the entity a `System` runs for.
+pub struct Write<'a, T>(&'a mut T);
+
+impl<'a, T: 'static> InnerId for Write<'a, T> {
+    fn inner_id() -> TypeId {
+        TypeId::of::<T>()
+    }
 }
 
-impl Entity {
+impl<'a, T> Deref for Write<'a, T> {
+    type Target = T;
 
     #[inline(always)]
-    pub fn id(&self) -> NonZeroUsize {
-        self.id
+    fn deref(&self) -> &T {
+        self.0
     }
+}
 
-    pub fn add_component<CT: 'static>(&mut self, component: CT) {
-        self.components.insert(TypeId::of::<CT>(), Box::new(component));
+impl<'a, T> DerefMut for Write<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        self.0
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn remove_component<CT: 'static>(&mut self) -> Option<Box<CT>> {
-        self.components.remove(&TypeId::of::<CT>()).map(|val| val.downcast::<CT>().unwrap())
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Health {
+        value: f64,
     }
 
-    pub fn get_component<CT: 'static>(&self) -> Option<&CT> {
-        self.components.get(&TypeId::of::<CT>()).map(|val| val.downcast_ref::<CT>().unwrap())
+    #[derive(Copy, Clone, Debug, PartialEq, Default)]
+    struct Position {
+        x: f32,
+        y: f32,
     }
 
-    pub fn get_component_mut<CT: 'static>(&mut self) -> Option<&mut CT> {
-        self.components.get_mut(&TypeId::of::<CT>()).map(|val| val.downcast_mut::<CT>().unwrap())
+    #[derive(Copy, Clone, Debug, PartialEq, Default)]
+    struct Velocity {
+        x: f32,
+        y: f32,
     }
 
-}
+    #[test]
+    fn insertion() {
+        let mut world = World::default();
+        let mut entity = world.new_entity();
+        entity.add_component(Health {
+            value: 20.0,
+        });
+        assert_eq!(*entity.get_component::<Health>().unwrap(), Health {
+            value: 20.0,
+        });
+    }
 
-pub type EntityId = NonZeroUsize;
+    #[test]
+    fn despawn() {
+        let mut world = World::default();
+        let mut entity = world.new_entity();
+        entity.add_component(Health {
+            value: 20.0,
+        });
+        let id = entity.id();
+        assert!(world.despawn(id));
+        assert!(!world.despawn(id));
+    }
 
-trait InnerId {
+    #[test]
+    fn lookup() {
+        let mut world = World::default();
+        let id = world.new_entity().id();
+        world.entity_mut(id).unwrap().add_component(Health {
+            value: 20.0,
+        });
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>(), Some(&Health {
+            value: 20.0,
+        }));
+        world.despawn(id);
+        assert!(world.entity(id).is_none());
+    }
 
-    #[inline]
-    fn inner_id() -> TypeId;
+    #[test]
+    fn stale_ids() {
+        let mut world = World::default();
+        let old = world.new_entity().id();
+        world.despawn(old);
+        let new = world.new_entity().id();
+        // the slot gets reused but the old id must not refer to the new entity
+        assert_eq!(old.index(), new.index());
+        assert_ne!(old, new);
+        assert!(world.entity(old).is_none());
+        assert!(!world.despawn(old));
+        assert!(world.entity(new).is_some());
+    }
 
-}
+    #[test]
+    fn spawn_batch() {
+        let mut world = World::default();
+        let ids = world.spawn_batch((0..100usize).map(|i| (Health {
+            value: i as f64,
+        }, i)));
+        assert_eq!(ids.len(), 100);
+        for (i, id) in ids.into_iter().enumerate() {
+            let entity = world.entity(id).unwrap();
+            assert_eq!(entity.get_component::<Health>().unwrap().value, i as f64);
+            assert_eq!(*entity.get_component::<usize>().unwrap(), i);
+        }
+    }
 
-pub struct Read<'a, T>(&'a T);
+    #[test]
+    fn clear() {
+        let mut world = World::default();
+        let ids = world.spawn_batch((0..10).map(|_| (Health {
+            value: 20.0,
+        },)));
+        world.insert_resource(5usize);
+        world.insert_non_send_resource(std::rc::Rc::new(3u32));
+        world.clear();
+        assert!(ids.iter().all(|id| world.entity(*id).is_none()));
+        let id = world.new_entity().id();
+        assert!(world.entity(id).is_some());
+        assert!(!ids.contains(&id));
+        assert!(world.get_resource::<usize>().is_none());
+        assert!(world.get_non_send_resource::<std::rc::Rc<u32>>().is_none());
+        assert_eq!(world.insert_resource(6usize), None);
+        assert_eq!(*world.get_resource::<usize>().unwrap(), 6);
+    }
 
-impl<'a, T> InnerId for Read<'a, T> {
-    fn inner_id() -> TypeId {
-        TypeId::of::<T>()
+    #[test]
+    fn iter() {
+        let mut world = World::default();
+        let ids = world.spawn_batch((0..10).map(|_| (Health {
+            value: 20.0,
+        },)));
+        world.despawn(ids[3]);
+        world.for_each_mut(|mut entity| {
+            entity.get_component_mut::<Health>().unwrap().value = 10.0;
+        });
+        let found = world.iter().map(|entity| {
+            assert_eq!(entity.get_component::<Health>().unwrap().value, 10.0);
+            entity.id()
+        }).collect::<Vec<_>>();
+        assert_eq!(found.len(), 9);
+        assert!(!found.contains(&ids[3]));
     }
-}
 
-trait ReadRaw {}
+    #[test]
+    fn spawn() {
+        let mut world = World::default();
+        let id = world.spawn((Health {
+            value: 20.0,
+        }, Position::default()));
+        let entity = world.entity(id).unwrap();
+        assert_eq!(entity.get_component::<Health>(), Some(&Health {
+            value: 20.0,
+        }));
+        assert_eq!(entity.get_component::<Position>(), Some(&Position::default()));
+    }
 
-impl<'a, T> ReadRaw for Read<'a, T> {}
+    #[test]
+    fn reserve_entity() {
+        let mut world = World::default();
+        let freed = world.new_entity().id();
+        world.despawn(freed);
+        let reserved = {
+            let world = &world;
+            [world.reserve_entity(), world.reserve_entity()]
+        };
+        assert_eq!(reserved[0].index(), freed.index());
+        assert!(world.contains(reserved[1]));
+        assert!(world.entity(reserved[0]).is_none());
+        world.flush();
+        for id in reserved {
+            assert_eq!(world.entity(id).unwrap().id(), id);
+        }
+        let spawned = world.new_entity().id();
+        assert!(!reserved.contains(&spawned));
+    }
 
-pub struct Write<'a, T>(&'a mut T);
+    #[test]
+    fn contains() {
+        let mut world = World::default();
+        let id = world.new_entity().id();
+        assert!(world.contains(id));
+        world.despawn(id);
+        assert!(!world.contains(id));
+        let reused = world.new_entity().id();
+        assert!(world.contains(reused));
+        assert!(!world.contains(id));
+    }
 
-trait WriteRaw {}
+    #[test]
+    fn builder() {
+        let mut world = World::default();
+        let id = world.build_entity().with(Health {
+            value: 20.0,
+        }).with(Position::default()).spawn();
+        let entity = world.entity(id).unwrap();
+        assert_eq!(entity.get_component::<Health>().unwrap().value, 20.0);
+        assert_eq!(entity.get_component::<Position>(), Some(&Position::default()));
+    }
 
-impl<'a, T> WriteRaw for Write<'a, T> {}
+    #[test]
+    fn transfer() {
+        let mut staging = World::default();
+        let mut live = World::default();
+        live.new_entity();
+        let id = staging.spawn((Health {
+            value: 20.0,
+        },));
+        let transferred = staging.transfer_entity(id, &mut live).unwrap();
+        assert!(!staging.contains(id));
+        assert_ne!(id, transferred);
+        assert_eq!(live.entity(transferred).unwrap().get_component::<Health>().unwrap().value, 20.0);
+        assert!(staging.transfer_entity(id, &mut live).is_none());
+    }
 
-impl<'a, T> InnerId for Write<'a, T> {
-    fn inner_id() -> TypeId {
-        TypeId::of::<T>()
+    #[test]
+    fn extend() {
+        let mut world = World::default();
+        world.extend((0..10).map(|i| (Health {
+            value: i as f64,
+        },)));
+        assert_eq!(world.iter().count(), 10);
+        assert_eq!(world.iter().map(|entity| entity.get_component::<Health>().unwrap().value).sum::<f64>(), 45.0);
     }
-}
 
-fn deconstruct_params<Args: AsRef<[impl InnerId]>>() -> Vec<SystemArg> {
+    #[test]
+    fn merge() {
+        let mut world = World::default();
+        world.spawn((Health {
+            value: 1.0,
+        },));
+        let mut chunk = World::default();
+        let ids = chunk.spawn_batch((0..3).map(|i| (Health {
+            value: i as f64,
+        },)));
+        let mapping = world.merge(chunk);
+        assert_eq!(mapping.len(), 3);
+        assert_eq!(world.iter().count(), 4);
+        for (i, id) in ids.into_iter().enumerate() {
+            let merged = world.entity(mapping[&id]).unwrap();
+            assert_eq!(merged.get_component::<Health>().unwrap().value, i as f64);
+        }
+    }
 
-}
+    #[test]
+    fn despawn_deferred() {
+        let mut world = World::default();
+        world.spawn_batch((0..10).map(|i| (Health {
+            value: i as f64,
+        },)));
+        for entity in world.iter() {
+            if entity.get_component::<Health>().unwrap().value < 5.0 {
+                world.despawn_deferred(entity.id());
+            }
+        }
+        assert_eq!(world.iter().count(), 10);
+        world.maintain();
+        assert_eq!(world.iter().count(), 5);
+    }
 
-enum SystemArg {
-    Read(TypeId),
-    Write(TypeId),
-}
+    #[test]
+    fn stats() {
+        let mut world = World::default();
+        world.spawn_batch((0..3).map(|_| (Health {
+            value: 20.0,
+        }, Position::default())));
+        let id = world.spawn((Health {
+            value: 20.0,
+        },));
+        world.reserve_entity();
+        let stats = world.stats();
+        assert_eq!(stats.entity_count, 4);
+        assert_eq!(stats.total_components, 7);
+        assert_eq!(stats.component_counts[&TypeId::of::<Health>()], 4);
+        assert_eq!(stats.component_counts[&TypeId::of::<Position>()], 3);
+        world.despawn(id);
+        // the reserved entity got flushed while despawning
+        assert_eq!(world.entity_count(), 4);
+    }
+
+    #[test]
+    fn remove_entity() {
+        let mut world = World::default();
+        let id = world.spawn((Health {
+            value: 20.0,
+        }, Position::default()));
+        let mut components = world.remove_entity(id).unwrap();
+        assert!(!world.contains(id));
+        assert!(world.remove_entity(id).is_none());
+        components.sort_by_key(|(ty, _)| *ty != TypeId::of::<Health>());
+        assert_eq!(components[0].1.downcast_ref::<Health>().unwrap().value, 20.0);
+        assert!(components[1].1.is::<Position>());
+    }
 
-pub trait System<Args> {
+    #[test]
+    fn entity_access() {
+        let mut world = World::default();
+        let ids = world.spawn_batch((0..2).map(|i| (Health {
+            value: i as f64,
+        },)));
+        // read-only views of several entities can be held at the same time
+        let first = world.entity(ids[0]).unwrap();
+        let second = world.entity(ids[1]).unwrap();
+        assert_eq!(first.get_component::<Health>().unwrap().value + second.get_component::<Health>().unwrap().value, 1.0);
+        let mut entity = world.entity_mut(ids[0]).unwrap();
+        entity.add_component(Position::default());
+        assert!(entity.remove_component::<Health>().is_some());
+        assert!(entity.as_ref().get_component::<Position>().is_some());
+        entity.despawn();
+        assert!(!world.contains(ids[0]));
+    }
 
-    fn run(&mut self, args: Args);
+    #[test]
+    fn archetypes() {
+        let mut world = World::default();
+        let ids = world.spawn_batch((0..4).map(|i| (Health {
+            value: i as f64,
+        },)));
+        // moving entities out of the middle of an archetype must keep the remaining ones intact
+        world.entity_mut(ids[1]).unwrap().add_component(Position::default());
+        world.entity_mut(ids[2]).unwrap().add_component(Position::default());
+        world.despawn(ids[0]);
+        let first = world.entity(ids[1]).unwrap();
+        let second = world.entity(ids[2]).unwrap();
+        assert_eq!(first.archetype().id(), second.archetype().id());
+        assert_eq!(first.archetype().len(), 2);
+        assert_eq!(world.entity(ids[3]).unwrap().archetype().len(), 1);
+        for (i, id) in ids.iter().enumerate().skip(1) {
+            assert_eq!(world.entity(*id).unwrap().get_component::<Health>().unwrap().value, i as f64);
+        }
+        let removed = world.entity_mut(ids[1]).unwrap().remove_component::<Position>();
+        assert_eq!(removed.as_deref(), Some(&Position::default()));
+        assert_eq!(world.entity(ids[1]).unwrap().archetype().id(), world.entity(ids[3]).unwrap().archetype().id());
+    }
 
-}
+    #[test]
+    fn sparse_set_storage() {
+        struct Burning(u32);
 
-pub trait MultiTyId {
-    const SIZE: usize;
+        let mut world = World::default();
+        world.register::<Burning>(StorageKind::SparseSet);
+        let ids = world.spawn_batch((0..3).map(|i| (Health {
+            value: i as f64,
+        },)));
+        let archetype = world.entity(ids[1]).unwrap().archetype().id();
+        let mut entity = world.entity_mut(ids[1]).unwrap();
+        entity.add_component(Burning(3));
+        entity.get_component_mut::<Burning>().unwrap().0 -= 1;
+        assert_eq!(entity.archetype().id(), archetype);
+        assert_eq!(entity.get_component::<Burning>().unwrap().0, 2);
+        world.entity_mut(ids[0]).unwrap().add_component(Burning(1));
+        assert_eq!(world.stats().component_counts[&TypeId::of::<Burning>()], 2);
+        assert_eq!(world.entity_mut(ids[0]).unwrap().remove_component::<Burning>().unwrap().0, 1);
+        world.despawn(ids[1]);
+        assert_eq!(world.stats().component_counts.get(&TypeId::of::<Burning>()), None);
+        let reused = world.spawn((Health {
+            value: 0.0,
+        },));
+        assert!(world.entity(reused).unwrap().get_component::<Burning>().is_none());
+    }
 
-    fn acquire_many(&self) -> fn() -> [TypeId; Self::SIZE];
-}
+    #[test]
+    fn component_ids() {
+        let mut world = World::default();
+        assert_eq!(world.component_id::<Health>(), None);
+        let first = world.spawn((Health {
+            value: 1.0,
+        }, Position::default()));
+        let health = world.component_id::<Health>().unwrap();
+        let position = world.component_id::<Position>().unwrap();
+        assert_ne!(health, position);
+        assert_eq!(world.components().len(), 2);
+        assert!(health.index() < 2 && position.index() < 2);
+        assert!(world.entity(first).unwrap().archetype().components().contains(&health));
+        world.spawn((Health {
+            value: 2.0,
+        },));
+        assert_eq!(world.component_id::<Health>(), Some(health));
+        let info = world.components().get_info(position).unwrap();
+        assert_eq!(info.type_id(), Some(TypeId::of::<Position>()));
+        assert_eq!(info.storage_kind(), StorageKind::Table);
 
+        let mut other = World::default();
+        other.spawn((Position::default(),));
+        let moved = world.transfer_entity(first, &mut other).unwrap();
+        assert_eq!(other.entity(moved).unwrap().get_component::<Health>().unwrap().value, 1.0);
+        assert_eq!(other.components().len(), 2);
+    }
 
+    #[test]
+    fn bundles() {
+        #[derive(Debug, PartialEq)]
+        struct Marker(u8);
 
-/*macro_rules! impl_tuples {
-    ($(($name: ident)))
-}*/
+        let mut world = World::default();
+        world.register::<Marker>(StorageKind::SparseSet);
+        let id = world.spawn((Health {
+            value: 1.0,
+        },));
+        let mut entity = world.entity_mut(id).unwrap();
+        entity.insert_bundle((Position {
+            x: 1.0,
+            y: 2.0,
+        }, Marker(3), Health {
+            value: 2.0,
+        }));
+        assert_eq!(entity.archetype().components().len(), 2);
+        assert_eq!(entity.get_component::<Health>().unwrap().value, 2.0);
+        assert_eq!(entity.get_component::<Marker>(), Some(&Marker(3)));
+        assert!(entity.remove_bundle::<(Position, f32)>().is_none());
+        assert_eq!(entity.get_component::<Position>().unwrap().x, 1.0);
+        let (marker, position) = entity.remove_bundle::<(Marker, Position)>().unwrap();
+        assert_eq!((marker, position), (Marker(3), Position {
+            x: 1.0,
+            y: 2.0,
+        }));
+        assert!(entity.get_component::<Marker>().is_none());
+        assert_eq!(entity.archetype().components(), &[entity.world().component_id::<Health>().unwrap()]);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn has_component() {
+        struct Tagged;
 
-    #[derive(Copy, Clone)]
-    struct Health {
-        value: f64,
+        let mut world = World::default();
+        world.register::<Tagged>(StorageKind::SparseSet);
+        let id = world.spawn((Health {
+            value: 1.0,
+        },));
+        assert!(world.has::<Health>(id));
+        assert!(!world.has::<Position>(id));
+        assert!(!world.has::<Tagged>(id));
+        let mut entity = world.entity_mut(id).unwrap();
+        entity.add_component(Tagged);
+        assert!(entity.has_component::<Tagged>());
+        assert!(entity.as_ref().has_component::<Health>());
+        world.despawn(id);
+        assert!(!world.has::<Health>(id));
     }
 
     #[test]
-    fn insertion() {
+    fn component_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(vec![]));
         let mut world = World::default();
-        let mut entity = world.new_entity();
+        let added = events.clone();
+        world.on_add::<Health>(move |world, id| added.lock().unwrap().push(("add", world.entity(id).unwrap().get_component::<Health>().unwrap().value)));
+        let replaced = events.clone();
+        world.on_replace::<Health>(move |world, id| replaced.lock().unwrap().push(("replace", world.entity(id).unwrap().get_component::<Health>().unwrap().value)));
+        let removed = events.clone();
+        world.on_remove::<Health>(move |world, id| removed.lock().unwrap().push(("remove", world.entity(id).unwrap().get_component::<Health>().unwrap().value)));
+        // hooks may change the world themselves
+        world.on_add::<Position>(|world, id| world.entity_mut(id).unwrap().add_component(Health {
+            value: 10.0,
+        }));
+
+        let first = world.spawn((Health {
+            value: 1.0,
+        },));
+        let mut entity = world.entity_mut(first).unwrap();
         entity.add_component(Health {
-            value: 20.0,
+            value: 2.0,
         });
-        assert_eq!(*entity.get_component::<Health>().unwrap(), Health {
-            value: 20.0,
+        entity.remove_component::<Health>();
+        entity.add_component(Position::default());
+        assert_eq!(entity.get_component::<Health>().unwrap().value, 10.0);
+        world.despawn(first);
+        world.spawn((Health {
+            value: 3.0,
+        },));
+        world.clear();
+        assert_eq!(*events.lock().unwrap(), vec![("add", 1.0), ("replace", 2.0), ("remove", 2.0), ("add", 10.0), ("remove", 10.0), ("add", 3.0), ("remove", 3.0)]);
+    }
+
+    #[test]
+    fn zero_sized_components() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, PartialEq)]
+        struct Player;
+        struct Frozen;
+
+        impl Drop for Frozen {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut world = World::default();
+        world.register::<Frozen>(StorageKind::SparseSet);
+        let ids = world.spawn_batch((0..3).map(|i| (Player, Health {
+            value: i as f64,
+        })));
+        assert!(world.components().get_info(world.component_id::<Player>().unwrap()).unwrap().is_zero_sized());
+        assert_eq!(world.entity(ids[1]).unwrap().get_component::<Player>(), Some(&Player));
+        let mut entity = world.entity_mut(ids[1]).unwrap();
+        entity.add_component(Frozen);
+        entity.add_component(Frozen);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        assert!(entity.has_component::<Frozen>());
+        assert!(entity.remove_component::<Player>().is_some());
+        assert_eq!(entity.get_component::<Health>().unwrap().value, 1.0);
+        world.entity_mut(ids[2]).unwrap().add_component(Frozen);
+        assert_eq!(world.stats().component_counts[&TypeId::of::<Frozen>()], 2);
+        world.despawn(ids[1]);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+        let reused = world.spawn((Health {
+            value: 0.0,
+        },));
+        assert!(!world.has::<Frozen>(reused));
+        assert!(world.has::<Player>(ids[0]));
+        drop(world);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn take_components() {
+        struct Marker;
+
+        let mut world = World::default();
+        world.register::<Marker>(StorageKind::SparseSet);
+        let id = world.spawn((Health {
+            value: 3.0,
+        }, Position::default(), Marker));
+        let mut entity = world.entity_mut(id).unwrap();
+        let mut taken = entity.take_components().collect::<Vec<_>>();
+        assert_eq!(entity.archetype().id(), ArchetypeId::EMPTY);
+        assert!(!entity.has_component::<Marker>());
+        taken.sort_by_key(|(ty, _)| *ty);
+        let mut types = vec![TypeId::of::<Health>(), TypeId::of::<Position>(), TypeId::of::<Marker>()];
+        types.sort();
+        assert_eq!(taken.iter().map(|(ty, _)| *ty).collect::<Vec<_>>(), types);
+        let health = taken.iter().find_map(|(_, component)| component.downcast_ref::<Health>()).unwrap();
+        assert_eq!(health.value, 3.0);
+        assert!(world.contains(id));
+        assert_eq!(world.stats().total_components, 0);
+    }
+
+    #[test]
+    fn component_types() {
+        struct Marker;
+
+        let mut world = World::default();
+        world.register::<Marker>(StorageKind::SparseSet);
+        let id = world.spawn((Health {
+            value: 3.0,
+        }, Marker));
+        let entity = world.entity(id).unwrap();
+        let mut types = entity.component_types().collect::<Vec<_>>();
+        types.sort();
+        let mut expected = vec![TypeId::of::<Health>(), TypeId::of::<Marker>()];
+        expected.sort();
+        assert_eq!(types, expected);
+        assert!(entity.component_infos().any(|info| info.name().ends_with("Health")));
+        assert_eq!(world.entity_mut(id).unwrap().component_types().count(), 2);
+    }
+
+    #[test]
+    fn replace_component() {
+        let mut world = World::default();
+        let id = world.spawn((Position::default(),));
+        let mut entity = world.entity_mut(id).unwrap();
+        assert_eq!(entity.replace_component(Health {
+            value: 1.0,
+        }), None);
+        assert_eq!(entity.replace_component(Health {
+            value: 2.0,
+        }), Some(Health {
+            value: 1.0,
+        }));
+        assert_eq!(entity.get_component::<Health>().unwrap().value, 2.0);
+    }
+
+    #[test]
+    fn get_components_mut() {
+        let mut world = World::default();
+        let id = world.spawn((Position::default(), Health {
+            value: 1.0,
+        }));
+        let mut entity = world.entity_mut(id).unwrap();
+        let (position, health) = entity.get_components_mut::<(Position, Health)>().unwrap();
+        position.x = 2.0;
+        health.value += position.x as f64;
+        assert_eq!(entity.get_component::<Health>().unwrap().value, 3.0);
+        assert!(entity.get_components_mut::<(Position, u32)>().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn get_components_mut_aliasing() {
+        let mut world = World::default();
+        let id = world.spawn((Position::default(),));
+        world.entity_mut(id).unwrap().get_components_mut::<(Position, Position)>();
+    }
+
+    #[test]
+    fn required_components() {
+        struct Collider;
+        #[derive(Default)]
+        struct Transform(u8);
+
+        let mut world = World::default();
+        world.register_required::<Collider, Transform>();
+        world.register_required_with::<Transform, Health>(|| Health {
+            value: 100.0,
         });
+        let id = world.spawn((Collider,));
+        assert!(world.has::<Transform>(id));
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>().unwrap().value, 100.0);
+
+        let id = world.spawn((Position::default(), Transform(3)));
+        let mut entity = world.entity_mut(id).unwrap();
+        entity.remove_component::<Health>();
+        entity.add_component(Collider);
+        assert_eq!(entity.get_component::<Transform>().unwrap().0, 3);
+        assert!(!entity.has_component::<Health>());
+        let id = world.spawn((Health {
+            value: 1.0,
+        }, Collider));
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>().unwrap().value, 1.0);
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut world = World::default();
+        let id = world.spawn((Health {
+            value: 1.0,
+        },));
+        let mut entity = world.entity_mut(id).unwrap();
+        entity.get_or_insert_with(|| Health {
+            value: 5.0,
+        }).value += 1.0;
+        assert_eq!(entity.get_component::<Health>().unwrap().value, 2.0);
+        entity.insert_default::<Position>().x = 3.0;
+        assert_eq!(entity.insert_default::<Position>().x, 3.0);
+    }
+
+    #[test]
+    fn dynamic_components() {
+        use std::alloc::Layout;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe fn count_drop(_: *mut u8) {
+            DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut world = World::default();
+        let table = world.register_dynamic("ScriptTable", Layout::new::<[u32; 4]>(), Some(count_drop), StorageKind::Table);
+        let sparse = world.register_dynamic("ScriptSparse", Layout::new::<u64>(), Some(count_drop), StorageKind::SparseSet);
+        assert_ne!(table, sparse);
+        assert!(world.components().get_info(table).unwrap().is_dynamic());
+        assert_eq!(world.components().get_info(table).unwrap().name(), "ScriptTable");
+
+        let id = world.spawn((Position { x: 1.0, y: 2.0 },));
+        let mut entity = world.entity_mut(id).unwrap();
+        unsafe {
+            entity.insert_dynamic(table, [1u32, 2, 3, 4].as_ptr().cast());
+            entity.insert_dynamic(sparse, (&7u64 as *const u64).cast());
+        }
+        assert_eq!(unsafe { *entity.get_dynamic(table).unwrap().cast::<[u32; 4]>() }, [1, 2, 3, 4]);
+        unsafe { *entity.get_dynamic_mut(sparse).unwrap().cast::<u64>() += 1; }
+        assert_eq!(unsafe { *entity.get_dynamic(sparse).unwrap().cast::<u64>() }, 8);
+        // typed components can be accessed untyped as well
+        let position = world.component_id::<Position>().unwrap();
+        let entity = world.entity_mut(id).unwrap();
+        assert_eq!(unsafe { (*entity.get_dynamic(position).unwrap().cast::<Position>()).y }, 2.0);
+        assert_eq!(entity.component_types().collect::<Vec<_>>(), vec![TypeId::of::<Position>()]);
+
+        let mut entity = world.entity_mut(id).unwrap();
+        unsafe { entity.insert_dynamic(table, [5u32; 4].as_ptr().cast()); }
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        assert!(entity.remove_dynamic(sparse));
+        assert!(!entity.remove_dynamic(sparse));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+        assert_eq!(world.stats().component_counts.len(), 1);
+        world.despawn(id);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn singletons() {
+        #[derive(Debug, PartialEq)]
+        struct Camera {
+            zoom: f32,
+        }
+
+        #[derive(Debug, PartialEq)]
+        struct PlayerController;
+
+        let mut world = World::default();
+        world.register_singleton::<Camera>();
+        world.register_singleton::<PlayerController>();
+        assert!(world.singleton::<Camera>().is_none());
+        let camera = world.spawn((Camera { zoom: 1.0 }, Position::default()));
+        let player = world.spawn((PlayerController,));
+        assert_eq!(world.singleton::<Camera>(), Some((camera, &Camera { zoom: 1.0 })));
+        assert_eq!(world.singleton::<PlayerController>(), Some((player, &PlayerController)));
+        world.singleton_mut::<Camera>().unwrap().1.zoom = 2.0;
+        // replacing the value on the same entity is fine
+        world.entity_mut(camera).unwrap().add_component(Camera { zoom: 3.0 });
+        assert_eq!(world.singleton::<Camera>().unwrap().1.zoom, 3.0);
+        world.despawn(camera);
+        assert!(world.singleton::<Camera>().is_none());
+        let camera = world.spawn((Camera { zoom: 1.0 },));
+        assert_eq!(world.singleton::<Camera>().unwrap().0, camera);
+        let moved = world.entity_mut(player).unwrap().remove_component::<PlayerController>();
+        assert!(moved.is_some());
+        world.entity_mut(camera).unwrap().add_component(PlayerController);
+        assert_eq!(world.singleton::<PlayerController>().unwrap().0, camera);
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists on another entity")]
+    fn second_singleton() {
+        struct Camera;
+
+        let mut world = World::default();
+        world.register_singleton::<Camera>();
+        world.spawn((Camera,));
+        world.spawn((Camera,));
+    }
+
+    #[test]
+    fn second_singleton_leaves_world_unchanged() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        struct Camera;
+
+        let mut world = World::default();
+        world.register_singleton::<Camera>();
+        let camera = world.spawn((Camera,));
+        let other = world.spawn((Velocity::default(),));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| world.spawn((Camera, Position::default())))).is_err());
+        assert_eq!(world.entity_count(), 2);
+        assert_eq!(world.query::<(Read<Position>,)>().count(), 0);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| world.entity_mut(other).unwrap().insert_bundle((Camera, Health {
+            value: 1.0,
+        })))).is_err());
+        let other = world.entity(other).unwrap();
+        assert!(!other.has_component::<Health>() && !other.has_component::<Camera>());
+        assert_eq!(world.singleton::<Camera>().unwrap().0, camera);
+    }
+
+    #[test]
+    fn update() {
+        let mut world = World::default();
+        let id = world.spawn((Health { value: 10.0 },));
+        assert!(world.update::<Health>(id, |health| health.value -= 3.0));
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>().unwrap().value, 7.0);
+        assert!(!world.update::<Position>(id, |_| unreachable!()));
+        world.despawn(id);
+        assert!(!world.update::<Health>(id, |_| unreachable!()));
+    }
+
+    #[test]
+    fn shared_components() {
+        #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+        struct Material {
+            color: u32,
+        }
+
+        let mut world = World::default();
+        let red = world.add_shared(Material { color: 0xff0000 });
+        let blue = world.add_shared(Material { color: 0x0000ff });
+        assert_eq!(world.add_shared(Material { color: 0xff0000 }), red);
+        let sprites = world.spawn_batch((0..4).map(|idx| (if idx % 2 == 0 { red } else { blue },)));
+        assert_eq!(world.entity(sprites[0]).unwrap().get_shared::<Material>(), Some(&Material { color: 0xff0000 }));
+        assert_eq!(world.shared_batch(red), vec![sprites[0], sprites[2]]);
+
+        world.shared_mut(blue).unwrap().color = 0x00ff00;
+        assert_eq!(world.entity(sprites[1]).unwrap().get_shared::<Material>().unwrap().color, 0x00ff00);
+        assert_eq!(world.set_shared(blue, Material { color: 0x0000ff }), blue);
+        // blue turns red, so both batches merge
+        assert_eq!(world.set_shared(blue, Material { color: 0xff0000 }), red);
+        assert!(world.shared(blue).is_none());
+        assert_eq!(world.shared_batch(red).len(), 4);
+        assert_eq!(world.entity(sprites[3]).unwrap().get_component::<Shared<Material>>(), Some(&red));
+        assert_eq!(world.remove_shared(red), Some(Material { color: 0xff0000 }));
+        assert!(world.entity(sprites[0]).unwrap().get_shared::<Material>().is_none());
+
+        // handles get remapped to equal values when moving entities between worlds
+        let green = world.add_shared(Material { color: 0x00ff00 });
+        let sprite = world.spawn((green,));
+        let mut target = World::default();
+        let black = target.add_shared(Material { color: 0x000000 });
+        let moved = world.transfer_entity(sprite, &mut target).unwrap();
+        assert_eq!(target.entity(moved).unwrap().get_shared::<Material>(), Some(&Material { color: 0x00ff00 }));
+        assert_ne!(target.entity(moved).unwrap().get_component::<Shared<Material>>(), Some(&green));
+        assert_eq!(world.shared(green), Some(&Material { color: 0x00ff00 }));
+        let sprite = target.spawn((black,));
+        let mapping = world.merge(target);
+        assert_eq!(world.entity(mapping[&sprite]).unwrap().get_shared::<Material>(), Some(&Material { color: 0x000000 }));
+        // equal values get deduplicated
+        assert_eq!(world.entity(mapping[&moved]).unwrap().get_component::<Shared<Material>>(), Some(&green));
+        // removed values stay unresolvable
+        assert!(world.entity(sprites[0]).unwrap().get_shared::<Material>().is_none());
+        let mut target = World::default();
+        target.add_shared(Material { color: 0xff0000 });
+        let moved = world.transfer_entity(sprites[0], &mut target).unwrap();
+        assert!(target.entity(moved).unwrap().get_shared::<Material>().is_none());
+    }
+
+    #[test]
+    fn queries() {
+        struct Tag;
+
+        let mut world = World::default();
+        world.register::<Velocity>(StorageKind::SparseSet);
+        world.spawn((Position { x: 1.0, y: 0.0 }, Velocity { x: 1.0, y: 1.0 }));
+        world.spawn((Position { x: 2.0, y: 0.0 },));
+        world.spawn((Position { x: 3.0, y: 0.0 }, Velocity { x: 2.0, y: 2.0 }, Health { value: 1.0 }));
+        world.spawn((Velocity { x: 9.0, y: 9.0 },));
+        for (velocity, position) in world.query::<(Read<Velocity>, Write<Position>)>() {
+            position.x += velocity.x;
+            position.y += velocity.y;
+        }
+        let mut query = world.query::<(Read<Position>,)>();
+        let mut positions = query.iter().map(|(position,)| (position.x, position.y)).collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(positions, vec![(2.0, 0.0), (2.0, 1.0), (5.0, 2.0)]);
+        assert_eq!(query.iter_mut().count(), 3);
+        assert_eq!(world.query::<(Read<Health>, Read<Velocity>)>().into_iter().count(), 1);
+        assert_eq!(world.query::<(Read<Tag>,)>().into_iter().count(), 0);
+    }
+
+    #[test]
+    fn query_filters() {
+        struct Player;
+        struct Frozen;
+
+        let mut world = World::default();
+        world.register::<Frozen>(StorageKind::SparseSet);
+        let player = world.spawn((Player, Velocity::default()));
+        world.spawn((Player, Velocity::default(), Frozen));
+        world.spawn((Velocity::default(),));
+        let mut query = world.query_filtered::<(Write<Velocity>,), (With<Player>, Without<Frozen>)>();
+        for (velocity,) in query.iter_mut() {
+            velocity.x = 1.0;
+        }
+        assert_eq!(query.iter_mut().count(), 1);
+        assert_eq!(world.entity(player).unwrap().get_component::<Velocity>().unwrap().x, 1.0);
+        assert_eq!(world.query_filtered::<(), (Without<Player>,)>().into_iter().count(), 1);
+    }
+
+    #[test]
+    fn optional_query_components() {
+        struct Shield(f64);
+
+        let mut world = World::default();
+        world.register::<Shield>(StorageKind::SparseSet);
+        world.spawn((Health { value: 10.0 }, Shield(5.0)));
+        world.spawn((Health { value: 10.0 },));
+        world.spawn((Shield(1.0),));
+        for (health, shield) in world.query::<(Write<Health>, Option<Read<Shield>>)>() {
+            health.value -= 8.0 - shield.map_or(0.0, |shield| shield.0);
+        }
+        let mut query = world.query::<(Read<Health>, Option<Write<Position>>)>();
+        let mut health = query.iter_mut().map(|(health, position)| {
+            assert!(position.is_none());
+            health.value
+        }).collect::<Vec<_>>();
+        health.sort_by(f64::total_cmp);
+        assert_eq!(health, vec![2.0, 7.0]);
     }
+
+    #[test]
+    fn or_filter() {
+        struct Burning;
+        struct Poisoned;
+
+        let mut world = World::default();
+        world.register::<Poisoned>(StorageKind::SparseSet);
+        world.spawn((Health { value: 10.0 }, Burning));
+        world.spawn((Health { value: 10.0 }, Poisoned));
+        world.spawn((Health { value: 10.0 }, Burning, Poisoned));
+        world.spawn((Health { value: 10.0 },));
+        world.spawn((Burning,));
+        for (health,) in world.query_filtered::<(Write<Health>,), (Or<(With<Burning>, With<Poisoned>)>,)>() {
+            health.value -= 1.0;
+        }
+        let query = world.query::<(Read<Health>,)>();
+        assert_eq!(query.iter().filter(|(health,)| health.value == 9.0).count(), 3);
+    }
+
+    #[test]
+    fn changed_filter() {
+        let mut world = World::default();
+        let first = world.spawn((Position::default(), Velocity::default()));
+        let second = world.spawn((Position::default(),));
+        assert_eq!(world.query_filtered::<(), (Changed<Position>,)>().into_iter().count(), 2);
+        world.clear_trackers();
+        assert_eq!(world.query_filtered::<(), (Changed<Position>,)>().into_iter().count(), 0);
+
+        world.entity_mut(second).unwrap().get_component_mut::<Position>().unwrap().x = 1.0;
+        let query = world.query_filtered::<(Read<Position>,), (Changed<Position>,)>();
+        assert_eq!(query.iter().map(|(position,)| position.x).collect::<Vec<_>>(), vec![1.0]);
+        world.clear_trackers();
+
+        for (_, velocity) in world.query::<(Read<Position>, Write<Velocity>)>() {
+            velocity.x = 1.0;
+        }
+        // moving the entity to another archetype keeps its ticks
+        world.entity_mut(first).unwrap().add_component(Health { value: 1.0 });
+        assert_eq!(world.query_filtered::<(), (Changed<Velocity>,)>().into_iter().count(), 1);
+        assert_eq!(world.query_filtered::<(), (Changed<Position>,)>().into_iter().count(), 0);
+        let ticks = world.entity(first).unwrap().component_ticks::<Velocity>().unwrap();
+        assert!(ticks.changed.is_newer_than(world.last_change_tick(), world.change_tick()));
+        assert!(!ticks.added.is_newer_than(world.last_change_tick(), world.change_tick()));
+    }
+
+    #[test]
+    fn added_filter() {
+        struct Enemy;
+
+        let mut world = World::default();
+        world.register::<Enemy>(StorageKind::SparseSet);
+        let old = world.spawn((Enemy, Health { value: 1.0 }));
+        world.clear_trackers();
+        let new = world.spawn((Enemy, Health { value: 1.0 }));
+        world.entity_mut(old).unwrap().add_component(Enemy);
+        world.entity_mut(old).unwrap().get_component_mut::<Health>().unwrap().value = 2.0;
+        let mut query = world.query_filtered::<(Write<Health>,), (Added<Enemy>,)>();
+        for (health,) in query.iter_mut() {
+            health.value = 10.0;
+        }
+        assert_eq!(world.entity(new).unwrap().get_component::<Health>().unwrap().value, 10.0);
+        assert_eq!(world.entity(old).unwrap().get_component::<Health>().unwrap().value, 2.0);
+        assert_eq!(world.query_filtered::<(), (Added<Health>,)>().into_iter().count(), 1);
+        world.clear_trackers();
+        assert_eq!(world.query_filtered::<(), (Added<Enemy>,)>().into_iter().count(), 0);
+    }
+
+    #[test]
+    fn query_get() {
+        struct Frozen;
+
+        let mut world = World::default();
+        let moving = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+        let frozen = world.spawn((Position::default(), Velocity::default(), Frozen));
+        let still = world.spawn((Position::default(),));
+        let despawned = world.spawn((Position::default(), Velocity::default()));
+        world.despawn(despawned);
+        let mut query = world.query_filtered::<(Read<Velocity>, Write<Position>), (Without<Frozen>,)>();
+        let (velocity, position) = query.get(moving).unwrap();
+        position.x += velocity.x;
+        assert_eq!(query.get(moving).unwrap().1.x, 1.0);
+        assert_eq!(query.get(frozen).err(), Some(QueryEntityError::QueryDoesNotMatch(frozen)));
+        assert_eq!(query.get(still).err(), Some(QueryEntityError::QueryDoesNotMatch(still)));
+        assert_eq!(query.get(despawned).err(), Some(QueryEntityError::NoSuchEntity(despawned)));
+    }
+
+    #[test]
+    fn query_single() {
+        struct Camera;
+        struct Player;
+
+        let mut world = World::default();
+        world.spawn((Camera, Position { x: 5.0, y: 0.0 }));
+        world.spawn((Player, Health { value: 1.0 }));
+        world.spawn((Player, Health { value: 2.0 }));
+        let query = world.query_filtered::<(Read<Position>,), (With<Camera>,)>();
+        assert_eq!(query.single().unwrap().0.x, 5.0);
+        let mut query = world.query_filtered::<(Write<Health>,), (With<Player>,)>();
+        assert!(matches!(query.single_mut(), Err(QuerySingleError::MultipleEntities(_))));
+        let mut query = world.query::<(Write<Velocity>,)>();
+        let err = query.single_mut().unwrap_err();
+        assert!(matches!(err, QuerySingleError::NoEntities(_)));
+        assert!(err.to_string().contains("Velocity"));
+    }
+
+    #[test]
+    fn query_combinations() {
+        let mut world = World::default();
+        for x in 0..4 {
+            world.spawn((Position { x: x as f32, y: 0.0 }, Velocity::default()));
+        }
+        world.spawn((Position::default(),));
+        let query = world.query::<(Read<Position>, Read<Velocity>)>();
+        let pairs = query.iter_combinations::<2>().map(|[(a, _), (b, _)]| (a.x, b.x)).collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0.0, 1.0), (0.0, 2.0), (0.0, 3.0), (1.0, 2.0), (1.0, 3.0), (2.0, 3.0)]);
+        assert_eq!(query.iter_combinations::<3>().count(), 4);
+        assert_eq!(query.iter_combinations::<5>().count(), 0);
+
+        let mut query = world.query::<(Read<Position>, Write<Velocity>)>();
+        let mut combinations = query.iter_combinations_mut::<2>();
+        while let Some([(a, a_velocity), (b, b_velocity)]) = combinations.fetch_next() {
+            a_velocity.x += b.x - a.x;
+            b_velocity.x += a.x - b.x;
+        }
+        let velocities = world.query::<(Read<Velocity>,)>().into_iter().map(|(velocity,)| velocity.x).collect::<Vec<_>>();
+        assert_eq!(velocities, vec![6.0, 2.0, -2.0, -6.0]);
+    }
+
+    #[test]
+    fn query_iter_many() {
+        let mut world = World::default();
+        let a = world.spawn((Health { value: 1.0 },));
+        let b = world.spawn((Position::default(),));
+        let c = world.spawn((Health { value: 3.0 },));
+        let d = world.spawn((Health { value: 4.0 },));
+        world.despawn(d);
+        let query = world.query::<(Read<Health>,)>();
+        let health = query.iter_many(&[c, b, d, a]).map(|(health,)| health.value).collect::<Vec<_>>();
+        assert_eq!(health, vec![3.0, 1.0]);
+
+        let mut query = world.query::<(Write<Health>,)>();
+        let mut iter = query.iter_many_mut([a, a, c]);
+        while let Some((health,)) = iter.fetch_next() {
+            health.value *= 2.0;
+        }
+        assert_eq!(world.entity(a).unwrap().get_component::<Health>().unwrap().value, 4.0);
+        assert_eq!(world.entity(c).unwrap().get_component::<Health>().unwrap().value, 6.0);
+    }
+
+    #[test]
+    fn cached_query_state() {
+        let mut world = World::default();
+        let mut state = QueryState::<(Write<Health>,), (Without<Position>,)>::new(&mut world);
+        world.spawn((Health { value: 1.0 },));
+        assert_eq!(state.query(&mut world).iter_mut().count(), 1);
+        let matched = state.matched_archetypes().len();
+        // new archetypes get picked up incrementally
+        world.spawn((Health { value: 2.0 }, Velocity::default()));
+        world.spawn((Health { value: 3.0 }, Position::default()));
+        for (health,) in state.query(&mut world).iter_mut() {
+            health.value += 1.0;
+        }
+        assert_eq!(state.matched_archetypes().len(), matched + 1);
+        let mut read = QueryState::<(Read<Health>,)>::new(&mut world);
+        let mut health = read.query_ref(&world).iter().map(|(health,)| health.value).collect::<Vec<_>>();
+        health.sort_by(f64::total_cmp);
+        assert_eq!(health, vec![2.0, 3.0, 3.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "different world")]
+    fn query_state_of_other_world() {
+        let mut world = World::default();
+        let mut state = QueryState::<(Read<Health>,)>::new(&mut world);
+        state.query(&mut World::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "Read<tecs::tests::Health> conflicts")]
+    fn query_access_conflict() {
+        let mut world = World::default();
+        world.query::<(Write<Health>, Read<Health>)>();
+    }
+
+    #[test]
+    fn query_access() {
+        let mut world = World::default();
+        let state = QueryState::<(Write<Health>, Read<Position>), (Changed<Health>,)>::new(&mut world);
+        let other = QueryState::<(Read<Position>,)>::new(&mut world);
+        let health = world.components.init::<Health>();
+        assert!(state.access().has_write(health));
+        assert!(state.access().is_compatible(other.access()));
+        let writer = QueryState::<(Write<Position>,)>::new(&mut world);
+        assert_eq!(state.access().conflicts(writer.access()), vec![world.components.init::<Position>()]);
+    }
+
+    #[test]
+    fn query_entity_ids() {
+        let mut world = World::default();
+        let first = world.spawn((Health { value: 1.0 },));
+        world.spawn((Position::default(),));
+        let second = world.spawn((Health { value: 2.0 }, Position::default()));
+        let query = world.query::<(EntityId, Read<Health>)>();
+        let mut found = query.iter().map(|(id, health)| (id, health.value)).collect::<Vec<_>>();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        assert_eq!(found, vec![(first, 1.0), (second, 2.0)]);
+    }
+
+    #[test]
+    fn query_for_each() {
+        let mut world = World::default();
+        world.spawn((Health { value: 1.0 },));
+        world.spawn((Health { value: 2.0 }, Position::default()));
+        world.spawn((Position::default(),));
+        let mut query = world.query::<(Write<Health>,)>();
+        query.for_each_mut(|(health,)| health.value *= 2.0);
+        let mut total = 0.0;
+        world.query::<(Read<Health>,)>().for_each(|(health,)| total += health.value);
+        assert_eq!(total, 6.0);
+    }
+
+    #[test]
+    fn dynamic_query() {
+        use std::alloc::Layout;
+
+        let mut world = World::default();
+        let score = world.register_dynamic("Score", Layout::new::<u64>(), None, StorageKind::Table);
+        let position = world.components.init::<Position>();
+        let health = world.components.init::<Health>();
+        let first = world.spawn((Position { x: 1.0, y: 0.0 },));
+        let second = world.spawn((Position { x: 2.0, y: 0.0 }, Health { value: 1.0 }));
+        for id in [first, second] {
+            unsafe { world.entity_mut(id).unwrap().insert_dynamic(score, (&10u64 as *const u64).cast()); }
+        }
+
+        let mut query = DynamicQuery::new(&world, [DynamicTerm::Write(score), DynamicTerm::Read(position), DynamicTerm::Without(health)]);
+        let mut visited = vec![];
+        query.for_each(&mut world, |id, ptrs| unsafe {
+            *ptrs[0].cast::<u64>() += (*ptrs[1].cast::<Position>()).x as u64;
+            visited.push(id);
+        });
+        assert_eq!(visited, vec![first]);
+        let ptrs = query.get(&mut world, first).unwrap();
+        assert_eq!(unsafe { *ptrs[0].cast::<u64>() }, 11);
+        assert_eq!(query.get(&mut world, second).unwrap_err(), QueryEntityError::QueryDoesNotMatch(second));
+    }
+
+    #[test]
+    #[should_panic(expected = "writing Score conflicts")]
+    fn dynamic_query_conflict() {
+        use std::alloc::Layout;
+
+        let mut world = World::default();
+        let score = world.register_dynamic("Score", Layout::new::<u64>(), None, StorageKind::Table);
+        DynamicQuery::new(&world, [DynamicTerm::Read(score), DynamicTerm::Write(score)]);
+    }
+
+    #[test]
+    fn query_transmute() {
+        let mut world = World::default();
+        world.spawn((Health { value: 1.0 }, Position::default()));
+        world.spawn((Health { value: 2.0 },));
+        let state = QueryState::<(Write<Health>, Read<Position>), (With<Position>,)>::new(&mut world);
+        let mut narrowed = state.transmute::<(Read<Health>,)>(&mut world);
+        assert_eq!(narrowed.matched_archetypes(), state.matched_archetypes());
+        let health = narrowed.query(&mut world).iter().map(|(health,)| health.value).collect::<Vec<_>>();
+        assert_eq!(health, vec![1.0]);
+    }
+
+    #[test]
+    fn query_transmute_matches_consistently() {
+        let mut world = World::default();
+        world.spawn((Health { value: 1.0 }, Position::default()));
+        world.spawn((Health { value: 2.0 },));
+        let state = QueryState::<(Read<Health>, Read<Position>)>::new(&mut world);
+        let mut narrowed = state.transmute::<(Read<Health>,)>(&mut world);
+        world.spawn((Health { value: 3.0 }, Velocity::default()));
+        // archetypes which existed before and after transmuting get matched the same way
+        let mut health = narrowed.query(&mut world).iter().map(|(health,)| health.value).collect::<Vec<_>>();
+        health.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(health, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "accesses components")]
+    fn query_transmute_to_wider_access() {
+        let mut world = World::default();
+        let state = QueryState::<(Read<Health>,)>::new(&mut world);
+        state.transmute::<(Write<Health>,)>(&mut world);
+    }
+
+    #[test]
+    fn any_of_query() {
+        let mut world = World::default();
+        world.spawn((Health { value: 1.0 },));
+        world.spawn((Position { x: 2.0, y: 0.0 },));
+        world.spawn((Health { value: 3.0 }, Position { x: 4.0, y: 0.0 }));
+        world.spawn((Velocity::default(),));
+        let mut query = world.query::<(AnyOf<(Write<Health>, Read<Position>)>,)>();
+        let mut found = query.iter_mut()
+            .map(|(any,)| (any.0.map(|health| health.value), any.1.map(|position| position.x)))
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(found, vec![(None, Some(2.0)), (Some(1.0), None), (Some(3.0), Some(4.0))]);
+    }
+
+    #[test]
+    fn query_joins() {
+        let mut world = World::default();
+        world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+        world.spawn((Position::default(), Velocity { x: 2.0, y: 0.0 }, Health { value: 1.0 }));
+        world.spawn((Position::default(),));
+        world.flush();
+        let mut positions = QueryState::<(Write<Position>,)>::new(&mut world);
+        let mut velocities = QueryState::<(Read<Velocity>,)>::new(&mut world);
+        // SAFETY: the queries don't conflict and nothing else accesses the world
+        let (mut positions, mut velocities) = unsafe { (positions.query_unchecked(&world), velocities.query_unchecked(&world)) };
+        for ((position,), (velocity,)) in positions.join(&mut velocities) {
+            position.x += velocity.x;
+        }
+        let mut moved = positions.left_join(&mut velocities).map(|((position,), velocity)| (position.x, velocity.is_some())).collect::<Vec<_>>();
+        moved.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(moved, vec![(0.0, false), (1.0, true), (2.0, true)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflict")]
+    fn conflicting_query_join() {
+        let mut world = World::default();
+        let mut writer = QueryState::<(Write<Position>,)>::new(&mut world);
+        let mut reader = QueryState::<(Read<Position>,)>::new(&mut world);
+        let (mut writer, mut reader) = unsafe { (writer.query_unchecked(&world), reader.query_unchecked(&world)) };
+        writer.join(&mut reader).count();
+    }
+
+    #[test]
+    fn query_count() {
+        struct Marker;
+
+        let mut world = World::default();
+        world.register::<Marker>(StorageKind::SparseSet);
+        let first = world.spawn((Health { value: 1.0 },));
+        world.spawn((Health { value: 2.0 }, Position::default()));
+        world.spawn((Position::default(),));
+        assert_eq!(world.query::<(Read<Health>,)>().count(), 2);
+        assert!(!world.query::<(Read<Health>,)>().is_empty());
+        assert!(world.query_filtered::<(Read<Health>,), (With<Velocity>,)>().is_empty());
+        world.entity_mut(first).unwrap().add_component(Marker);
+        assert_eq!(world.query_filtered::<(Read<Health>,), (With<Marker>,)>().count(), 1);
+    }
+
+    #[test]
+    fn predicate_filter() {
+        let mut world = World::default();
+        let dead = world.spawn((Health { value: -1.0 },));
+        world.spawn((Health { value: 5.0 }, Position::default()));
+        world.clear_trackers();
+        let mut query = world.query_where::<(EntityId, Write<Health>), Health>(|health| health.value <= 0.0);
+        for (_, health) in query.iter_mut() {
+            health.value = 0.0;
+        }
+        assert_eq!(query.iter_mut().map(|(id, _)| id).collect::<Vec<_>>(), vec![dead]);
+        // entities failing the predicate don't get fetched, so they aren't marked as changed
+        assert_eq!(world.query_filtered::<(EntityId,), (Changed<Health>,)>().iter().map(|(id,)| id).collect::<Vec<_>>(), vec![dead]);
+    }
+
+    #[test]
+    fn masked_query() {
+        let mut world = World::default();
+        let ids = world.spawn_batch((0..8).map(|value| (Health { value: value as f64 },)));
+        let dirty = AtomicBitSet::new();
+        dirty.add(ids[2].index() as usize);
+        dirty.add(ids[5].index() as usize);
+        let mut query = world.query::<(Write<Health>,)>();
+        for (health,) in query.iter_masked_mut(&dirty) {
+            health.value *= 10.0;
+        }
+        let query = world.query::<(Read<Health>,)>();
+        assert_eq!(query.iter_masked(&dirty).map(|(health,)| health.value).collect::<Vec<_>>(), vec![20.0, 50.0]);
+        assert_eq!(query.iter().map(|(health,)| health.value).sum::<f64>(), 28.0 + 18.0 + 45.0);
+    }
+
+    #[test]
+    fn entity_ref_in_query() {
+        let mut world = World::default();
+        world.spawn((Health { value: 1.0 }, Position { x: 2.0, y: 3.0 }));
+        world.spawn((Health { value: 4.0 },));
+        let query = world.query::<(Read<Health>, EntityRef)>();
+        let mut found = query.iter()
+            .map(|(health, entity)| (health.value, entity.get_component::<Position>().map(|position| position.x)))
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(found, vec![(1.0, Some(2.0)), (4.0, None)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts")]
+    fn entity_ref_with_write_query() {
+        let mut world = World::default();
+        world.query::<(EntityRef, Write<Health>)>();
+    }
+
+    #[test]
+    fn has_query() {
+        struct Burning;
+
+        let mut world = World::default();
+        world.register::<Burning>(StorageKind::SparseSet);
+        let burning = world.spawn((Health { value: 1.0 }, Burning));
+        world.spawn((Health { value: 2.0 },));
+        let query = world.query::<(EntityId, Has<Burning>, Has<Position>)>();
+        let found = query.iter().filter(|(_, burning, _)| *burning).map(|(id, _, position)| (id, position)).collect::<Vec<_>>();
+        assert_eq!(found, vec![(burning, false)]);
+        assert_eq!(query.count(), 2);
+    }
+
+    #[test]
+    fn systems() {
+        let mut world = World::default();
+        let moving = world.spawn((Position::default(), Velocity { x: 1.0, y: 2.0 }));
+        let still = world.spawn((Position::default(),));
+
+        fn movement(mut position: Write<Position>, velocity: Read<Velocity>) {
+            position.x += velocity.x;
+            position.y += velocity.y;
+        }
+
+        movement.execute(&mut world);
+        movement.execute(&mut world);
+        assert_eq!(*world.entity(moving).unwrap().get_component::<Position>().unwrap(), Position { x: 2.0, y: 4.0 });
+        assert_eq!(*world.entity(still).unwrap().get_component::<Position>().unwrap(), Position::default());
+
+        let mut visited = 0;
+        (|_: Read<Position>| visited += 1).execute(&mut world);
+        assert_eq!(visited, 2);
+        assert_eq!(deconstruct_params::<(Write<Position>, Read<Velocity>)>(), vec![
+            SystemArg::Write(TypeId::of::<Position>()),
+            SystemArg::Read(TypeId::of::<Velocity>()),
+        ]);
+        let access = deconstruct_access::<(Write<Position>, Read<Velocity>)>(&mut world);
+        let (position, velocity) = (world.component_id::<Position>().unwrap(), world.component_id::<Velocity>().unwrap());
+        assert!(access.has_write(position) && access.has_read(velocity) && !access.has_write(velocity));
+        assert!(!access.is_compatible(&deconstruct_access::<(Read<Position>,)>(&mut world)));
+    }
+
+    #[test]
+    fn function_systems() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        let mut world = World::default();
+        let id = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+        let mut system = movement.into_system();
+        system.run((), &mut world);
+        system.run((), &mut world);
+        assert_eq!(world.entity(id).unwrap().get_component::<Position>().unwrap().x, 2.0);
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        let mut system: BoxedSystem = Box::new((move |query: Query<(Read<Position>,)>, _: Query<(Read<Velocity>,)>| {
+            counted.fetch_add(query.iter().count(), Ordering::Relaxed);
+        }).into_system());
+        world.spawn((Position::default(),));
+        system.run((), &mut world);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with a previous parameter")]
+    fn conflicting_system_params() {
+        fn system(_: Query<(Write<Position>,)>, _: Query<(Read<Position>,)>) {}
+
+        system.into_system().run((), &mut World::default());
+    }
+
+    #[test]
+    fn custom_system_params() {
+        // only uses the public api, like a parameter defined in another crate would
+        type Params = (Query<'static, 'static, (Write<'static, Position>, Read<'static, Velocity>)>, Query<'static, 'static, (Read<'static, Health>,)>);
+
+        struct Movables<'w, 's> {
+            query: Query<'w, 's, (Write<'static, Position>, Read<'static, Velocity>)>,
+            health: Query<'w, 's, (Read<'static, Health>,)>,
+        }
+
+        unsafe impl SystemParam for Movables<'_, '_> {
+            type State = <Params as SystemParam>::State;
+            type Item<'w, 's> = Movables<'w, 's>;
+
+            fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+                Params::init_state(world, access)
+            }
+
+            unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+                let (query, health) = Params::get_param(state, world, last_run, this_run);
+                Movables {
+                    query,
+                    health,
+                }
+            }
+        }
+
+        fn movement(mut movables: Movables) {
+            assert!(movables.health.is_empty());
+            for (position, velocity) in movables.query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        let mut world = World::default();
+        let id = world.spawn((Position::default(), Velocity { x: 3.0, y: 0.0 }));
+        movement.into_system().run((), &mut world);
+        assert_eq!(world.entity(id).unwrap().get_component::<Position>().unwrap().x, 3.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with a previous parameter")]
+    fn world_param_conflict() {
+        fn system(_: &World, _: Query<(Write<Position>,)>) {}
+
+        system.into_system().run((), &mut World::default());
+    }
+
+    #[test]
+    fn schedules() {
+        use std::sync::{Arc, Mutex};
+
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        let mut world = World::default();
+        let id = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+        let log = Arc::new(Mutex::new(vec![]));
+        let (first, second) = (log.clone(), log.clone());
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(move |query: Query<(Read<Position>,)>| first.lock().unwrap().push(query.single().unwrap().0.x))
+            .add_system(movement)
+            .add_system(move |query: Query<(Read<Position>,)>| second.lock().unwrap().push(query.single().unwrap().0.x));
+        assert_eq!(schedule.len(), 3);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(*log.lock().unwrap(), vec![0.0, 1.0, 1.0, 2.0]);
+        assert_eq!(world.entity(id).unwrap().get_component::<Position>().unwrap().x, 2.0);
+    }
+
+    #[test]
+    fn multi_threaded_schedule() {
+        use std::sync::{Arc, Barrier, Mutex};
+
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        let mut world = World::default();
+        let id = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }, Health { value: 1.0 }));
+        let log = Arc::new(Mutex::new(vec![]));
+        let (before, after) = (log.clone(), log.clone());
+        let mut schedule = Schedule::new();
+        schedule.set_executor_kind(ExecutorKind::MultiThreaded)
+            .add_system(move |query: Query<(Read<Position>,)>| before.lock().unwrap().push(query.single().unwrap().0.x))
+            .add_system(movement)
+            .add_system(|mut query: Query<(Write<Health>,)>| query.single_mut().unwrap().0.value += 1.0)
+            .add_system(move |query: Query<(Read<Position>,)>| after.lock().unwrap().push(query.single().unwrap().0.x));
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        // conflicting systems keep their order
+        assert_eq!(log.lock().unwrap().chunks(2).map(|run| run[1] - run[0]).collect::<Vec<_>>(), vec![1.0; 10]);
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>().unwrap().value, 11.0);
+
+        if std::thread::available_parallelism().unwrap().get() > 1 {
+            // both systems only read, so they have to run at the same time to get past the barrier
+            let barrier = Arc::new(Barrier::new(2));
+            let other = barrier.clone();
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(ExecutorKind::MultiThreaded)
+                .add_system(move |_: Query<(Read<Position>,)>| { barrier.wait(); })
+                .add_system(move |_: Query<(Read<Position>,)>| { other.wait(); });
+            schedule.run(&mut world);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "system failed")]
+    fn multi_threaded_schedule_panic() {
+        let mut schedule = Schedule::new();
+        schedule.set_executor_kind(ExecutorKind::MultiThreaded)
+            .add_system(|_: Query<(Read<Position>,)>| -> () { panic!("system failed") })
+            .add_system(|_: Query<(Write<Position>,)>| {});
+        schedule.run(&mut World::default());
+    }
+
+    #[test]
+    fn system_ordering() {
+        use std::sync::{Arc, Mutex};
+
+        static LOG: Mutex<Vec<&str>> = Mutex::new(vec![]);
+
+        fn collision(_: Query<(Read<Position>,)>) {
+            LOG.lock().unwrap().push("collision");
+        }
+
+        fn apply_damage(_: Query<(Read<Health>,)>) {
+            LOG.lock().unwrap().push("apply_damage");
+        }
+
+        fn render(_: Query<(Read<Velocity>,)>) {
+            LOG.lock().unwrap().push("render");
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .add_system(render.after(apply_damage))
+                .add_system(apply_damage.after(collision))
+                .add_system(collision);
+            let mut world = World::default();
+            schedule.run(&mut world);
+            assert_eq!(std::mem::take(&mut *LOG.lock().unwrap()), vec!["collision", "apply_damage", "render"]);
+        }
+
+        // systems which aren't ordered explicitly keep their order
+        let log = Arc::new(Mutex::new(vec![]));
+        let (first, second) = (log.clone(), log.clone());
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(move |_: Query<(Read<Position>,)>| first.lock().unwrap().push(1))
+            .add_system(collision.before(render))
+            .add_system(move |_: Query<(Read<Position>,)>| second.lock().unwrap().push(2))
+            .add_system(render);
+        schedule.run(&mut World::default());
+        assert_eq!(*log.lock().unwrap(), vec![1, 2]);
+        assert_eq!(std::mem::take(&mut *LOG.lock().unwrap()), vec!["collision", "render"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "form a cycle: tecs::tests::system_ordering_cycle::a -> tecs::tests::system_ordering_cycle::b -> tecs::tests::system_ordering_cycle::a")]
+    fn system_ordering_cycle() {
+        fn a(_: Query<(Read<Position>,)>) {}
+
+        fn b(_: Query<(Read<Position>,)>) {}
+
+        fn c(_: Query<(Read<Position>,)>) {}
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(c.after(a))
+            .add_system(a.after(b))
+            .add_system(b.after(a));
+        schedule.run(&mut World::default());
+    }
+
+    #[test]
+    fn system_sets() {
+        use std::sync::Mutex;
+
+        static LOG: Mutex<Vec<&str>> = Mutex::new(vec![]);
+
+        struct PhysicsSet;
+
+        impl SystemSet for PhysicsSet {}
+
+        struct RenderSet;
+
+        impl SystemSet for RenderSet {}
+
+        fn collision(_: Query<(Read<Position>,)>) {
+            LOG.lock().unwrap().push("collision");
+        }
+
+        fn movement(_: Query<(Read<Velocity>,)>) {
+            LOG.lock().unwrap().push("movement");
+        }
+
+        fn render(_: Query<(Read<Position>,)>) {
+            LOG.lock().unwrap().push("render");
+        }
+
+        fn input(_: Query<(Read<Health>,)>) {
+            LOG.lock().unwrap().push("input");
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .configure_set(PhysicsSet.before(RenderSet).after(input))
+                .add_system(render.in_set(RenderSet))
+                .add_system(movement.in_set(PhysicsSet))
+                .add_system(collision.in_set(PhysicsSet).after(movement))
+                .add_system(input);
+            schedule.run(&mut World::default());
+            assert_eq!(std::mem::take(&mut *LOG.lock().unwrap()), vec!["input", "movement", "collision", "render"]);
+        }
+    }
+
+    #[test]
+    fn run_conditions() {
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let mut world = World::default();
+            let id = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .add_system(movement.run_if(|query: Query<(Read<Health>,)>| !query.is_empty()))
+                .add_system(movement.run_if(|| true).run_if(|query: Query<(Read<Position>,)>| query.single().unwrap().0.x < 2.0));
+            schedule.run(&mut world);
+            assert_eq!(world.entity(id).unwrap().get_component::<Position>().unwrap().x, 1.0);
+            world.spawn((Health { value: 1.0 },));
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+            assert_eq!(world.entity(id).unwrap().get_component::<Position>().unwrap().x, 3.0);
+        }
+    }
+
+    #[test]
+    fn exclusive_systems() {
+        fn spawn_wave(world: &mut World) {
+            for _ in 0..2 {
+                world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+            }
+        }
+
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let mut world = World::default();
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .add_system(movement)
+                .add_system(spawn_wave.run_if(|query: Query<(Read<Position>,)>| query.count() < 4))
+                .add_system(movement);
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+            let mut positions = world.query::<(Read<Position>,)>().iter().map(|(position,)| position.x).collect::<Vec<_>>();
+            positions.sort_by(f32::total_cmp);
+            assert_eq!(positions, vec![3.0, 3.0, 5.0, 5.0]);
+        }
+    }
+
+    #[test]
+    fn commands() {
+        fn split(query: Query<(EntityId, Read<Health>)>, mut commands: Commands) {
+            for (id, health) in query.iter() {
+                if health.value > 1.0 {
+                    commands.spawn((Health { value: health.value / 2.0 }, Position::default()));
+                    commands.spawn((Health { value: health.value / 2.0 },));
+                    commands.despawn(id);
+                } else {
+                    commands.insert(id, (Velocity { x: 1.0, y: 0.0 },));
+                    commands.remove::<(Health,)>(id);
+                }
+            }
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let mut world = World::default();
+            world.spawn((Health { value: 2.0 },));
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .add_system(split);
+            schedule.run(&mut world);
+            assert_eq!(world.entity_count(), 2);
+            assert_eq!(world.query::<(Read<Health>, Read<Position>)>().iter().count(), 1);
+            schedule.run(&mut world);
+            assert_eq!(world.entity_count(), 2);
+            assert_eq!(world.query::<(Read<Velocity>,)>().iter().count(), 2);
+            assert_eq!(world.query::<(Read<Health>,)>().iter().count(), 0);
+        }
+    }
+
+    #[test]
+    fn local_params() {
+        use std::sync::{Arc, Mutex};
+
+        let log = Arc::new(Mutex::new(vec![]));
+        let (first, second) = (log.clone(), log.clone());
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(move |mut count: Local<usize>| {
+                *count += 1;
+                first.lock().unwrap().push(*count);
+            })
+            .add_system(move |mut count: Local<usize>| {
+                *count += 10;
+                second.lock().unwrap().push(*count);
+            });
+        let mut world = World::default();
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(*log.lock().unwrap(), vec![1, 10, 2, 20]);
+    }
+
+    #[test]
+    fn system_piping() {
+        use std::sync::{Arc, Mutex};
+
+        fn parse_input(query: Query<(EntityId, Read<Health>)>) -> Result<usize, EntityId> {
+            match query.iter().find(|(_, health)| health.value < 0.0) {
+                Some((id, _)) => Err(id),
+                None => Ok(query.count()),
+            }
+        }
+
+        fn double(In(result): In<Result<usize, EntityId>>) -> Result<usize, EntityId> {
+            result.map(|count| count * 2)
+        }
+
+        let mut world = World::default();
+        world.spawn((Health { value: 1.0 },));
+        let log = Arc::new(Mutex::new(vec![]));
+        let errors = log.clone();
+        let mut schedule = Schedule::new();
+        schedule.add_system(parse_input.pipe(double).pipe(move |result: In<Result<usize, EntityId>>, mut commands: Commands| {
+            match *result {
+                Ok(count) => errors.lock().unwrap().push(Ok(count)),
+                Err(id) => {
+                    errors.lock().unwrap().push(Err(id));
+                    commands.despawn(id);
+                }
+            }
+        }));
+        schedule.run(&mut world);
+        let invalid = world.spawn((Health { value: -1.0 },));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(*log.lock().unwrap(), vec![Ok(2), Err(invalid), Ok(2)]);
+        assert_eq!(parse_input.pipe(double).run((), &mut world), Ok(2));
+    }
+
+    #[test]
+    fn pipes_behave_the_same_on_all_executors() {
+        use std::sync::{Arc, Mutex};
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let log = Arc::new(Mutex::new(vec![]));
+            let counts = log.clone();
+            let mut world = World::default();
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .add_system((|mut commands: Commands| {
+                    commands.spawn((Health { value: 1.0 },));
+                }).pipe(move |query: Query<(Read<Health>,)>| counts.lock().unwrap().push(query.count())));
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+            // the commands of the first system get applied after the whole pipe ran
+            assert_eq!(*log.lock().unwrap(), vec![0, 1]);
+            assert_eq!(world.query::<(Read<Health>,)>().into_iter().count(), 2);
+        }
+    }
+
+    #[test]
+    fn run_system_once() {
+        fn spawn_health(mut commands: Commands) -> EntityId {
+            commands.spawn((Health { value: 5.0 },))
+        }
+
+        let mut world = World::default();
+        let id = world.run_system_once(spawn_health);
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>().unwrap().value, 5.0);
+        assert_eq!(world.run_system_once(|query: Query<(Read<Health>,)>| query.count()), 1);
+        world.run_system_once(move |world: &mut World| {
+            world.despawn(id);
+        });
+        assert!(!world.contains(id));
+    }
+
+    #[test]
+    fn named_schedules() {
+        struct Startup;
+
+        impl ScheduleLabel for Startup {}
+
+        struct Update;
+
+        impl ScheduleLabel for Update {}
+
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        let mut world = World::default();
+        let mut startup = Schedule::new();
+        startup.add_system(|mut commands: Commands| {
+            commands.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+        });
+        world.add_schedule(Startup, startup);
+        let mut update = Schedule::new();
+        update.add_system(movement);
+        assert!(world.add_schedule(Update, update).is_none());
+
+        world.run_schedule(Startup);
+        for _ in 0..3 {
+            world.run_schedule(Update);
+        }
+        world.schedule_mut(Update).unwrap().add_system(movement);
+        world.run_schedule(Update);
+        assert_eq!(world.query::<(Read<Position>,)>().single().unwrap().0.x, 5.0);
+        assert_eq!(world.remove_schedule(Startup).unwrap().len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "there is no schedule labeled")]
+    fn missing_schedule() {
+        struct Update;
+
+        impl ScheduleLabel for Update {}
+
+        World::default().run_schedule(Update);
+    }
+
+    #[test]
+    fn ambiguity_detection() {
+        fn movement(_: Query<(Write<Position>, Read<Velocity>)>) {}
+
+        fn collision(_: Query<(Write<Position>,)>) {}
+
+        fn damage(_: Query<(Write<Health>,)>) {}
+
+        fn render(_: Query<(Read<Position>, Read<Health>)>) {}
+
+        let mut world = World::default();
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(movement)
+            .add_system(collision.after(movement))
+            .add_system(damage)
+            .add_system(render.after(collision));
+        let ambiguities = schedule.ambiguities(&mut world);
+        assert_eq!(ambiguities.len(), 1);
+        assert_eq!(ambiguities[0].to_string(), format!("{} and {} conflict on {}",
+            std::any::type_name_of_val(&damage), std::any::type_name_of_val(&render), std::any::type_name::<Health>()));
+
+        // reading the whole world conflicts with every system which writes, unless it's ordered after it
+        schedule.add_system((|_: &World| {}).after(damage));
+        assert_eq!(schedule.ambiguities(&mut world).len(), 3);
+    }
+
+    #[test]
+    fn schedule_stepping() {
+        use std::sync::Mutex;
+
+        static LOG: Mutex<Vec<&str>> = Mutex::new(vec![]);
+
+        fn input(_: Query<(Read<Position>,)>) {
+            LOG.lock().unwrap().push("input");
+        }
+
+        fn movement(_: Query<(Read<Position>,)>) {
+            LOG.lock().unwrap().push("movement");
+        }
+
+        fn render(_: Query<(Read<Position>,)>) {
+            LOG.lock().unwrap().push("render");
+        }
+
+        let mut world = World::default();
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(render.after(movement))
+            .add_system(input)
+            .add_system(movement)
+            .set_stepping(true);
+        schedule.run(&mut world);
+        assert!(LOG.lock().unwrap().is_empty());
+        assert_eq!(schedule.next_system().unwrap(), std::any::type_name_of_val(&input));
+
+        schedule.step().run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(*LOG.lock().unwrap(), vec!["input"]);
+        assert_eq!(schedule.next_system().unwrap(), std::any::type_name_of_val(&movement));
+        schedule.step().run(&mut world);
+        schedule.continue_frame().run(&mut world);
+        schedule.step().run(&mut world);
+        assert_eq!(std::mem::take(&mut *LOG.lock().unwrap()), vec!["input", "movement", "render", "input"]);
+
+        schedule.set_stepping(false).run(&mut world);
+        assert_eq!(*LOG.lock().unwrap(), vec!["input", "movement", "render"]);
+    }
+
+    #[test]
+    fn async_systems() {
+        use std::future::Future;
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+
+        // stands in for waiting on IO
+        struct YieldOnce(bool);
+
+        impl Future for YieldOnce {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if self.0 {
+                    return Poll::Ready(());
+                }
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+
+        fn load_wave(query: Query<(Read<Health>,)>) -> impl Future<Output = impl FnOnce(&mut World) + Send> + Send {
+            let loaded = query.count();
+            async move {
+                YieldOnce(false).await;
+                move |world: &mut World| {
+                    if loaded == 0 {
+                        world.spawn((Health { value: 1.0 },));
+                    }
+                }
+            }
+        }
+
+        let mut world = World::default();
+        let mut schedule = Schedule::new();
+        schedule.add_system(load_wave.into_async());
+        for _ in 0..1000 {
+            schedule.run(&mut world);
+            if world.entity_count() != 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(world.entity_count() >= 1);
+    }
+
+    #[test]
+    fn fallible_systems() {
+        use std::sync::{Arc, Mutex};
+
+        fn heal(query: Query<(Read<Health>,)>) -> SystemResult {
+            let (health,) = query.single()?;
+            if health.value <= 0.0 {
+                return Err(SystemError::new("can't heal the dead"));
+            }
+            Ok(())
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let errors = Arc::new(Mutex::new(vec![]));
+            let handled = errors.clone();
+            let mut world = World::default();
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .set_error_handler(move |system, error| handled.lock().unwrap().push(format!("{system}: {error}")))
+                .add_system(heal)
+                .add_system(|_: Query<(Read<Position>,)>| {});
+            schedule.run(&mut world);
+            world.spawn((Health { value: 1.0 },));
+            schedule.run(&mut world);
+            world.query::<(Write<Health>,)>().single_mut().unwrap().0.value = 0.0;
+            schedule.run(&mut world);
+            let name = std::any::type_name_of_val(&heal);
+            assert_eq!(*errors.lock().unwrap(), vec![
+                format!("{name}: no entity matches the query {}", std::any::type_name::<(Read<Health>,)>()),
+                format!("{name}: can't heal the dead"),
+            ]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "failed: can't heal the dead")]
+    fn fallible_system_panics() {
+        let mut schedule = Schedule::new();
+        schedule.set_executor_kind(ExecutorKind::MultiThreaded)
+            .add_system(|| Err(SystemError::new("can't heal the dead")))
+            .add_system(|| {});
+        schedule.run(&mut World::default());
+    }
+
+    #[test]
+    fn run_once_systems() {
+        fn spawn_player(mut commands: Commands) {
+            commands.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+        }
+
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let mut world = World::default();
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .add_system(spawn_player.run_once())
+                .add_system(movement)
+                .add_system((|mut commands: Commands| {
+                    commands.spawn((Health { value: 1.0 },));
+                }).run_if(|query: Query<(Read<Position>,)>| !query.is_empty()).run_once());
+            for _ in 0..3 {
+                schedule.run(&mut world);
+            }
+            assert_eq!(world.query::<(Read<Position>,)>().iter().count(), 1);
+            assert_eq!(world.query::<(Read<Health>,)>().iter().count(), 1);
+            schedule.reactivate().run(&mut world);
+            schedule.run(&mut world);
+            assert_eq!(world.query::<(Read<Position>,)>().iter().count(), 2);
+            assert_eq!(world.query::<(Read<Health>,)>().iter().count(), 2);
+        }
+    }
+
+    #[test]
+    fn schedule_graph() {
+        fn movement(_: Query<(Write<Position>, Read<Velocity>)>) {}
+
+        fn collision(_: Query<(Write<Position>,)>) {}
+
+        fn damage(_: Query<(Write<Health>,)>) {}
+
+        fn spawn_wave(_: &mut World) {}
+
+        let mut world = World::default();
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(collision.after(movement))
+            .add_system(movement)
+            .add_system(damage)
+            .add_system(spawn_wave);
+        let graph = schedule.graph(&mut world);
+        let names = graph.nodes.iter().map(|node| node.name.as_ref()).collect::<Vec<_>>();
+        assert_eq!(names, vec![
+            std::any::type_name_of_val(&movement),
+            std::any::type_name_of_val(&collision),
+            std::any::type_name_of_val(&damage),
+            std::any::type_name_of_val(&spawn_wave),
+        ]);
+        assert_eq!(graph.nodes[0].reads, vec![std::any::type_name::<Velocity>()]);
+        assert_eq!(graph.nodes[0].writes, vec![std::any::type_name::<Position>()]);
+        assert!(graph.nodes[3].exclusive);
+        assert_eq!(graph.edges, vec![SystemEdge {
+            from: 0,
+            to: 1,
+            kind: EdgeKind::Ordering,
+        }]);
+
+        schedule.add_system(|_: Query<(Read<Health>,)>| {});
+        let dot = schedule.graph(&mut world).to_dot();
+        assert!(dot.starts_with("digraph schedule {\n"));
+        assert!(dot.contains(&format!("    0 [label=\"{}\\nreads: {}\\nwrites: {}\", shape=ellipse];\n",
+            std::any::type_name_of_val(&movement), std::any::type_name::<Velocity>(), std::any::type_name::<Position>())));
+        assert!(dot.contains("    0 -> 1 [style=solid];\n    2 -> 4 [style=dashed];\n}"));
+    }
+
+    #[test]
+    fn system_change_ticks() {
+        use std::sync::{Arc, Mutex};
+
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        for executor in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            let log = Arc::new(Mutex::new(vec![]));
+            let (before, after) = (log.clone(), log.clone());
+            let mut world = World::default();
+            let moving = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+            let resting = world.spawn((Position::default(),));
+            let mut schedule = Schedule::new();
+            schedule.set_executor_kind(executor)
+                .add_system(move |query: Query<(EntityId,), Changed<Position>>| before.lock().unwrap().push(query.iter().map(|(id,)| id).collect::<Vec<_>>()))
+                .add_system(movement)
+                .add_system(move |query: Query<(EntityId,), Changed<Position>>| after.lock().unwrap().push(query.iter().map(|(id,)| id).collect::<Vec<_>>()));
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+            world.entity_mut(moving).unwrap().remove_component::<Velocity>();
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+            world.entity_mut(resting).unwrap().get_component_mut::<Position>().unwrap().x = 5.0;
+            schedule.run(&mut world);
+            // the systems see all changes since their previous run, even the ones made by systems running after them
+            assert_eq!(*log.lock().unwrap(), vec![
+                vec![moving, resting], vec![moving, resting],
+                vec![moving], vec![moving],
+                vec![moving], vec![],
+                vec![], vec![],
+                vec![resting], vec![resting],
+            ]);
+        }
+    }
+
+    #[test]
+    fn stale_change_ticks() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        fn advance(world: &mut World) {
+            let tick = world.change_tick.get_mut();
+            *tick = tick.wrapping_add(CHECK_TICK_THRESHOLD);
+        }
+
+        // without checks the change looks new again once the tick counter wrapped around
+        let mut world = World::default();
+        world.spawn((Position::default(),));
+        for step in 0..9 {
+            advance(&mut world);
+            if step == 3 {
+                world.clear_trackers();
+            }
+        }
+        assert_eq!(world.query_filtered::<(), (Changed<Position>,)>().into_iter().count(), 1);
+
+        let log = Arc::new(Mutex::new(vec![]));
+        let enabled = Arc::new(AtomicBool::new(true));
+        let (changes, condition) = (log.clone(), enabled.clone());
+        let mut world = World::default();
+        world.spawn((Position::default(),));
+        let mut schedule = Schedule::new();
+        schedule.add_system((move |query: Query<(), Changed<Position>>| changes.lock().unwrap().push(query.count()))
+            .run_if(move || condition.load(Ordering::Relaxed)));
+        schedule.run(&mut world);
+        enabled.store(false, Ordering::Relaxed);
+        // the schedule checks the ticks every time it runs, the system only runs every few steps
+        for step in 0..9 {
+            advance(&mut world);
+            enabled.store(step == 3 || step == 8, Ordering::Relaxed);
+            schedule.run(&mut world);
+            if step == 3 {
+                world.clear_trackers();
+            }
+        }
+        assert_eq!(*log.lock().unwrap(), vec![1, 0, 0]);
+        assert_eq!(world.query_filtered::<(), (Changed<Position>,)>().into_iter().count(), 0);
+    }
+
+    #[test]
+    fn system_inputs() {
+        struct DamageEvent {
+            target: EntityId,
+            amount: f64,
+        }
+
+        fn apply_damage(In(event): In<DamageEvent>, mut query: Query<(Write<Health>,)>) -> bool {
+            let (health,) = query.get(event.target).unwrap();
+            health.value -= event.amount;
+            health.value <= 0.0
+        }
+
+        let mut world = World::default();
+        let target = world.spawn((Health { value: 5.0 },));
+        assert!(!world.run_system_with(apply_damage, DamageEvent { target, amount: 2.0 }));
+        assert!(world.run_system_with(apply_damage, DamageEvent { target, amount: 3.0 }));
+        assert_eq!(world.run_system_with(|In(factor): In<f64>, query: Query<(Read<Health>,)>| query.single().unwrap().0.value * factor, 2.0), 0.0);
+    }
+
+    #[test]
+    fn non_send_systems() {
+        use std::sync::{Arc, Mutex};
+        use std::thread::{self, ThreadId};
+
+        let mut world = World::default();
+        world.spawn((Position::default(), Velocity::default()));
+        let threads: Arc<Mutex<Vec<ThreadId>>> = Arc::new(Mutex::new(vec![]));
+        let mut schedule = Schedule::new();
+        schedule.set_executor_kind(ExecutorKind::MultiThreaded);
+        for _ in 0..4 {
+            let threads = threads.clone();
+            schedule.add_system((move |_: Query<(Read<Position>,)>| threads.lock().unwrap().push(thread::current().id())).non_send());
+            schedule.add_system(|_: Query<(Read<Velocity>,)>| thread::yield_now());
+        }
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        let threads = threads.lock().unwrap();
+        assert_eq!(threads.len(), 40);
+        assert!(threads.iter().all(|id| *id == thread::current().id()));
+    }
+
+    #[test]
+    fn from_world() {
+        struct Spawned(usize);
+
+        impl FromWorld for Spawned {
+            fn from_world(world: &mut World) -> Self {
+                Self(world.query::<(Read<Position>,)>().count())
+            }
+        }
+
+        let mut world = World::default();
+        world.spawn((Position::default(),));
+        world.spawn((Position::default(),));
+        let mut system = (|mut spawned: Local<Spawned>, query: Query<(Read<Position>,)>| {
+            let previous = spawned.0;
+            spawned.0 = query.count();
+            previous
+        }).into_system();
+        assert_eq!(system.run((), &mut world), 2);
+        world.spawn((Position::default(),));
+        assert_eq!(system.run((), &mut world), 2);
+        assert_eq!(system.run((), &mut world), 3);
+    }
+
+    #[test]
+    fn system_registry() {
+        fn movement(mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x;
+            }
+        }
+
+        let mut world = World::default();
+        let id = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }, Health { value: 0.0 }));
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(movement)
+            .add_system((|mut query: Query<(Write<Health>,)>| query.single_mut().unwrap().0.value += 1.0)
+                .named("regeneration")
+                .with_metadata("category", "gameplay")
+                .run_once());
+        assert_eq!(schedule.systems().map(|info| info.name()).collect::<Vec<_>>(), vec![std::any::type_name_of_val(&movement), "regeneration"]);
+        assert_eq!(schedule.system("regeneration").unwrap().metadata()["category"], "gameplay");
+        assert!(schedule.system("missing").is_none());
+
+        schedule.system_mut(std::any::type_name_of_val(&movement)).unwrap().set_enabled(false);
+        schedule.system_mut("regeneration").unwrap().set_enabled(false);
+        schedule.run(&mut world);
+        assert_eq!(world.entity(id).unwrap().get_component::<Position>().unwrap().x, 0.0);
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>().unwrap().value, 0.0);
+
+        // disabled systems don't count as ran once
+        schedule.system_mut("regeneration").unwrap().set_enabled(true);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert!(!schedule.system(std::any::type_name_of_val(&movement)).unwrap().is_enabled());
+        assert_eq!(world.entity(id).unwrap().get_component::<Health>().unwrap().value, 1.0);
+    }
+
+    #[test]
+    fn sync_points() {
+        use std::sync::{Arc, Mutex};
+
+        fn spawn(mut commands: Commands) {
+            commands.spawn((Health { value: 1.0 },));
+        }
+
+        fn run(schedule: &mut Schedule) -> Vec<usize> {
+            let counts = Arc::new(Mutex::new(vec![]));
+            let inner = counts.clone();
+            schedule.set_executor_kind(ExecutorKind::MultiThreaded)
+                .add_system(spawn)
+                .add_system((move |query: Query<(Read<Health>,)>| inner.lock().unwrap().push(query.count())).after(spawn));
+            let mut world = World::default();
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+            let counts = counts.lock().unwrap().clone();
+            counts
+        }
+
+        assert_eq!(run(&mut Schedule::new()), vec![1, 2]);
+        assert_eq!(run(Schedule::new().set_auto_sync(false)), vec![0, 1]);
+        assert_eq!(run(Schedule::new().set_auto_sync(false).add_system(apply_deferred.after(spawn))), vec![1, 2]);
+    }
+
+    #[test]
+    fn observers() {
+        use std::sync::{Arc, Mutex};
+
+        let mut world = World::default();
+        let log = Arc::new(Mutex::new(vec![]));
+        let (added, removed) = (log.clone(), log.clone());
+        world.add_observer(Observer::on_add(move |In(trigger): In<Trigger<Health>>, mut commands: Commands| {
+            added.lock().unwrap().push(("added", trigger.component.value));
+            commands.insert(trigger.entity, (Velocity::default(),));
+        }));
+        let observer = world.add_observer(Observer::on_remove(move |In(trigger): In<Trigger<Health>>| {
+            removed.lock().unwrap().push(("removed", trigger.component.value));
+        }).deferred());
+
+        // immediate observers run right away and their commands get applied afterwards
+        let id = world.spawn((Health { value: 1.0 },));
+        assert_eq!(*log.lock().unwrap(), vec![("added", 1.0)]);
+        assert!(world.entity(id).unwrap().get_component::<Velocity>().is_some());
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(move |query: Query<(EntityId, Read<Health>)>, mut commands: Commands| {
+            for (id, _) in query.iter() {
+                commands.despawn(id);
+            }
+        });
+        schedule.run(&mut world);
+        assert_eq!(*log.lock().unwrap(), vec![("added", 1.0), ("removed", 1.0)]);
+
+        // deferred observers only run once the world gets synced
+        let id = world.spawn((Health { value: 2.0 },));
+        world.despawn(id);
+        assert_eq!(log.lock().unwrap().len(), 3);
+        world.run_deferred_observers();
+        assert_eq!(log.lock().unwrap().last(), Some(&("removed", 2.0)));
+
+        assert!(world.remove_observer(observer));
+        assert!(!world.remove_observer(observer));
+        let id = world.spawn((Health { value: 3.0 },));
+        world.despawn(id);
+        world.run_deferred_observers();
+        assert_eq!(log.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn system_timing() {
+        use std::time::Duration;
+
+        fn slow(_: Query<(Read<Position>,)>) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut world = World::default();
+        let mut schedule = Schedule::new();
+        schedule.add_system(slow).add_system(|_: Query<(Read<Velocity>,)>| {});
+        schedule.run(&mut world);
+        assert!(schedule.last_run_report().is_none());
+
+        for kind in [ExecutorKind::SingleThreaded, ExecutorKind::MultiThreaded] {
+            schedule.set_executor_kind(kind).set_timing(true).run(&mut world);
+            let report = schedule.last_run_report().unwrap();
+            assert_eq!(report.systems.len(), 2);
+            assert_eq!(report.slowest().unwrap().name, std::any::type_name_of_val(&slow));
+            assert!(report.slowest().unwrap().duration >= Duration::from_millis(10));
+            assert!(report.total >= Duration::from_millis(10));
+        }
+
+        schedule.set_timing(false);
+        assert!(schedule.last_run_report().is_none());
+    }
+
+    #[test]
+    fn resources() {
+        #[derive(Debug, PartialEq)]
+        struct Time(f64);
+
+        let mut world = World::default();
+        assert!(!world.contains_resource::<Time>());
+        assert_eq!(world.get_resource::<Time>(), None);
+        assert_eq!(world.insert_resource(Time(0.0)), None);
+        world.get_resource_mut::<Time>().unwrap().0 += 0.5;
+        assert_eq!(world.get_resource::<Time>(), Some(&Time(0.5)));
+        assert_eq!(world.insert_resource(Time(1.0)), Some(Time(0.5)));
+        assert_eq!(world.remove_resource::<Time>(), Some(Time(1.0)));
+        assert!(!world.contains_resource::<Time>());
+        assert_eq!(world.remove_resource::<Time>(), None);
+    }
+
+    #[test]
+    fn resource_params() {
+        #[derive(Default)]
+        struct Time(f32);
+
+        fn advance(mut time: ResMut<Time>) {
+            time.0 += 1.0;
+        }
+
+        fn movement(time: Res<Time>, mut query: Query<(Write<Position>, Read<Velocity>)>) {
+            for (position, velocity) in query.iter_mut() {
+                position.x += velocity.x * time.0;
+            }
+        }
+
+        let mut world = World::default();
+        world.insert_resource(Time(0.0));
+        let id = world.spawn((Position::default(), Velocity { x: 1.0, y: 0.0 }));
+        let mut schedule = Schedule::new();
+        schedule.set_executor_kind(ExecutorKind::MultiThreaded)
+            .add_system(advance)
+            .add_system(movement)
+            .add_system(|time: Res<Time>, _: Query<(Read<Velocity>,)>| assert!(time.0 > 0.0));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.entity(id).unwrap().get_component::<Position>().unwrap().x, 3.0);
+        // the readers only conflict with the writer
+        let graph = schedule.graph(&mut world);
+        assert_eq!(graph.edges.iter().map(|edge| (edge.from, edge.to)).collect::<Vec<_>>(), vec![(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with a previous parameter")]
+    fn conflicting_resource_params() {
+        struct Time;
+
+        let mut world = World::default();
+        let mut system = (|_: Res<Time>, _: ResMut<Time>| {}).into_system();
+        system.initialize(&mut world);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't exist")]
+    fn missing_resource() {
+        struct Time;
+
+        let mut world = World::default();
+        let mut system = (|_: Res<Time>| {}).into_system();
+        system.run((), &mut world);
+    }
+
+    #[test]
+    fn init_resource() {
+        #[derive(Default, Debug, PartialEq)]
+        struct Gravity(f32);
+
+        #[derive(Debug, PartialEq)]
+        struct Config {
+            gravity: f32,
+        }
+
+        impl FromWorld for Config {
+            fn from_world(world: &mut World) -> Self {
+                world.init_resource::<Gravity>();
+                Self {
+                    gravity: world.get_resource::<Gravity>().unwrap().0,
+                }
+            }
+        }
+
+        let mut world = World::default();
+        world.insert_resource(Gravity(9.81));
+        world.init_resource::<Gravity>();
+        world.init_resource::<Config>();
+        assert_eq!(world.get_resource::<Gravity>(), Some(&Gravity(9.81)));
+        assert_eq!(world.get_resource::<Config>(), Some(&Config { gravity: 9.81 }));
+
+        let mut world = World::default();
+        world.init_resource::<Config>();
+        assert_eq!(world.get_resource::<Gravity>(), Some(&Gravity(0.0)));
+        assert_eq!(world.get_resource::<Config>(), Some(&Config { gravity: 0.0 }));
+    }
+
+    #[test]
+    fn non_send_resources() {
+        use std::rc::Rc;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+
+        // stands in for e.g. a window handle
+        struct Window(Rc<usize>);
+
+        let mut world = World::default();
+        assert!(world.insert_non_send_resource(Window(Rc::new(0))).is_none());
+        *Rc::get_mut(&mut world.get_non_send_resource_mut::<Window>().unwrap().0).unwrap() += 1;
+        assert_eq!(*world.get_non_send_resource::<Window>().unwrap().0, 1);
+        assert!(!world.contains_resource::<Window>());
+
+        // systems using non-send resources stay on the calling thread
+        let threads = Arc::new(Mutex::new(vec![]));
+        let inner = threads.clone();
+        let mut schedule = Schedule::new();
+        schedule.set_executor_kind(ExecutorKind::MultiThreaded);
+        for _ in 0..4 {
+            schedule.add_system(|_: Query<(Read<Position>,)>| thread::yield_now());
+        }
+        schedule.add_system(move |mut window: NonSendMut<Window>| {
+            inner.lock().unwrap().push(thread::current().id());
+            *Rc::get_mut(&mut window.0).unwrap() += 1;
+        });
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        assert!(threads.lock().unwrap().iter().all(|id| *id == thread::current().id()));
+        assert_eq!(*world.remove_non_send_resource::<Window>().unwrap().0, 11);
+    }
+
+    #[test]
+    fn resource_change_detection() {
+        use std::sync::{Arc, Mutex};
+
+        struct Settings {
+            volume: f32,
+        }
+
+        let mut world = World::default();
+        world.insert_resource(Settings { volume: 1.0 });
+        let log = Arc::new(Mutex::new(vec![]));
+        let inner = log.clone();
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(|mut settings: ResMut<Settings>| {
+                // reading doesn't count as a change
+                if settings.volume > 2.0 {
+                    settings.volume = 2.0;
+                }
+            })
+            .add_system(move |settings: Res<Settings>| inner.lock().unwrap().push((settings.is_added(), settings.is_changed())));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        world.get_resource_mut::<Settings>().unwrap().volume = 3.0;
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        world.insert_resource(Settings { volume: 0.5 });
+        schedule.run(&mut world);
+        assert_eq!(*log.lock().unwrap(), vec![(true, true), (false, false), (false, true), (false, false), (false, true)]);
+        assert_eq!(world.get_resource::<Settings>().unwrap().volume, 0.5);
+        assert!(world.resource_ticks::<Settings>().is_some());
+    }
+
+    #[test]
+    fn resource_scope() {
+        #[derive(Default)]
+        struct Spawned(Vec<EntityId>);
+
+        let mut world = World::default();
+        world.init_resource::<Spawned>();
+        let count = world.resource_scope(|world, spawned: &mut Spawned| {
+            assert!(!world.contains_resource::<Spawned>());
+            for _ in 0..3 {
+                spawned.0.push(world.spawn((Position::default(),)));
+            }
+            world.insert_resource(Spawned(vec![]));
+            spawned.0.len()
+        });
+        assert_eq!(count, 3);
+        assert_eq!(world.get_resource::<Spawned>().unwrap().0.len(), 3);
+        assert!(world.get_resource::<Spawned>().unwrap().0.iter().all(|id| world.entity(*id).is_some()));
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't exist")]
+    fn missing_resource_scope() {
+        World::default().resource_scope(|_, _: &mut u32| {});
+    }
+
+    #[test]
+    fn get_resource_or_insert_with() {
+        let mut world = World::default();
+        *world.get_resource_or_insert_with(|| 1_u32) += 1;
+        *world.get_resource_or_insert_with(|| -> u32 { unreachable!() }) += 1;
+        assert_eq!(world.get_resource::<u32>(), Some(&3));
+    }
+
+    #[test]
+    fn resource_ids() {
+        let mut world = World::default();
+        let id = world.resource_id::<u32>();
+        assert_eq!(world.get_resource_by_id(id), None);
+        world.insert_resource(1_u32);
+        for _ in 0..10 {
+            *world.get_resource_mut_by_id(id).unwrap() += 1;
+        }
+        assert_eq!(world.get_resource_by_id(id), Some(&11));
+        assert_eq!(world.resource_id::<u32>(), id);
+        assert_eq!(Some(id.id()), world.resource_types().get_id(std::any::TypeId::of::<u32>()));
+        assert!(world.components().get_id(std::any::TypeId::of::<u32>()).is_none());
+    }
+
+    #[test]
+    fn events() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Debug, PartialEq)]
+        struct Damage(u32);
+
+        let mut world = World::default();
+        world.add_event::<Damage>();
+        let log = Arc::new(Mutex::new(vec![]));
+        let (first, second) = (log.clone(), log.clone());
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(|mut writer: EventWriter<Damage>| writer.send_batch([Damage(1), Damage(2)]))
+            .add_system(move |mut reader: EventReader<Damage>| first.lock().unwrap().push(("first", reader.read().map(|damage| damage.0).sum::<u32>())))
+            .add_system(move |mut reader: EventReader<Damage>| {
+                // every reader has its own cursor
+                second.lock().unwrap().push(("second", reader.len() as u32));
+                reader.clear();
+                assert!(reader.is_empty());
+                assert_eq!(reader.read().count(), 0);
+            });
+        schedule.run(&mut world);
+        world.send_event(Damage(5));
+        schedule.run(&mut world);
+        assert_eq!(*log.lock().unwrap(), vec![("first", 3), ("second", 2), ("first", 8), ("second", 3)]);
+
+        // events stay around for two updates
+        let events = world.get_resource_mut::<Events<Damage>>().unwrap();
+        assert_eq!(events.len(), 5);
+        events.update();
+        assert_eq!(events.len(), 5);
+        events.send(Damage(0));
+        events.update();
+        assert_eq!(events.iter().collect::<Vec<_>>(), vec![&Damage(0)]);
+        events.update();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn event_update_policies() {
+        struct Tick;
+        struct Turn;
+        struct Log;
+
+        let mut world = World::default();
+        world.add_event::<Tick>();
+        world.add_event_with_policy::<Turn>(EventUpdatePolicy::Manual);
+        world.add_event_with_policy::<Log>(EventUpdatePolicy::Unbounded);
+        let mut schedule = Schedule::new();
+        schedule
+            .set_update_events(true)
+            .add_system(|mut ticks: EventWriter<Tick>, mut turns: EventWriter<Turn>, mut logs: EventWriter<Log>| {
+                ticks.send(Tick);
+                turns.send(Turn);
+                logs.send(Log);
+            });
+        for _ in 0..3 {
+            schedule.run(&mut world);
+        }
+        // the events sent during the last two runs are left
+        assert_eq!(world.get_resource::<Events<Tick>>().unwrap().len(), 2);
+        // schedules don't update the others
+        assert_eq!(world.get_resource::<Events<Turn>>().unwrap().len(), 3);
+        assert_eq!(world.get_resource::<Events<Log>>().unwrap().len(), 3);
+
+        world.get_resource_mut::<Events<Turn>>().unwrap().update();
+        world.get_resource_mut::<Events<Turn>>().unwrap().update();
+        assert!(world.get_resource::<Events<Turn>>().unwrap().is_empty());
+        let logs = world.get_resource_mut::<Events<Log>>().unwrap();
+        logs.update();
+        assert_eq!(logs.len(), 3);
+        logs.clear();
+        assert!(logs.is_empty());
+
+        // schedules don't update events unless enabled
+        schedule.set_update_events(false).run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.get_resource::<Events<Tick>>().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn lifecycle_events() {
+        let mut world = World::default();
+        world.add_lifecycle_events();
+        let entity = world.spawn((Position::default(),));
+        let reserved = world.reserve_entity();
+        let mut entity_mut = world.entity_mut(entity).unwrap();
+        entity_mut.add_component(Health { value: 1.0 });
+        entity_mut.replace_component(Health { value: 2.0 });
+        entity_mut.remove_component::<Position>();
+        world.despawn(entity);
+        world.flush();
+        let position = world.component_id::<Position>().unwrap();
+        let health = world.component_id::<Health>().unwrap();
+        let events = |world: &World| (
+            world.get_resource::<Events<EntitySpawned>>().unwrap().iter().map(|event| event.entity).collect::<Vec<_>>(),
+            world.get_resource::<Events<EntityDespawned>>().unwrap().iter().map(|event| event.entity).collect::<Vec<_>>(),
+            world.get_resource::<Events<ComponentAdded>>().unwrap().iter().map(|event| (event.entity, event.component)).collect::<Vec<_>>(),
+            world.get_resource::<Events<ComponentRemoved>>().unwrap().iter().map(|event| (event.entity, event.component)).collect::<Vec<_>>(),
+        );
+        // replacing a component doesn't count as adding it
+        assert_eq!(events(&world), (vec![entity, reserved], vec![entity], vec![(entity, position), (entity, health)],
+                                    vec![(entity, position), (entity, health)]));
+
+        let other = world.spawn((Health { value: 3.0 },));
+        world.clear_entities();
+        let (_, despawned, _, removed) = events(&world);
+        assert_eq!(&despawned[1..], &[reserved, other]);
+        assert_eq!(removed.last(), Some(&(other, health)));
+    }
+
+    #[test]
+    fn world_systems_stay_on_calling_thread() {
+        use std::sync::{Arc, Mutex};
+        use std::thread::{self, ThreadId};
+
+        let mut world = World::default();
+        world.spawn((Position::default(),));
+        let threads: Arc<Mutex<Vec<ThreadId>>> = Arc::new(Mutex::new(vec![]));
+        let mut schedule = Schedule::new();
+        schedule.set_executor_kind(ExecutorKind::MultiThreaded);
+        for _ in 0..4 {
+            let threads = threads.clone();
+            schedule.add_system(move |world: &World| {
+                assert_eq!(world.iter().count(), 1);
+                threads.lock().unwrap().push(thread::current().id());
+            });
+            schedule.add_system(|_: Query<(Read<Position>,)>| thread::yield_now());
+        }
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        let threads = threads.lock().unwrap();
+        assert_eq!(threads.len(), 40);
+        assert!(threads.iter().all(|id| *id == thread::current().id()));
+    }
+
+    #[test]
+    fn despawning_remove_hooks() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        struct Link(Option<EntityId>);
+
+        // despawning the linked entity while merging
+        let mut chunk = World::default();
+        chunk.on_remove::<Link>(|world, id| {
+            if let Some(linked) = world.entity(id).unwrap().get_component::<Link>().unwrap().0 {
+                world.despawn(linked);
+            }
+        });
+        let first = chunk.spawn((Link(None),));
+        let second = chunk.spawn((Link(None),));
+        chunk.entity_mut(first).unwrap().get_component_mut::<Link>().unwrap().0 = Some(second);
+        let mut world = World::default();
+        let mapping = world.merge(chunk);
+        assert_eq!(mapping.len(), 1);
+        assert!(mapping.contains_key(&first));
+        assert_eq!(world.iter().count(), 1);
+
+        // despawning the entity itself while transferring it
+        let mut source = World::default();
+        let despawning = Arc::new(AtomicBool::new(false));
+        source.on_remove::<Health>(move |world, id| {
+            if !despawning.swap(true, Ordering::Relaxed) {
+                assert!(world.despawn(id));
+            }
+        });
+        let id = source.spawn((Health {
+            value: 1.0,
+        },));
+        assert_eq!(source.transfer_entity(id, &mut world), None);
+        assert!(source.entity(id).is_none());
+        assert_eq!(world.iter().count(), 1);
+    }
+
+    #[test]
+    fn spawn_batch_reserves_component_storage() {
+        let mut world = World::with_capacity(100);
+        world.spawn_batch((0..100).map(|_| (Position::default(),)));
+        let position = world.component_id::<Position>().unwrap();
+        let archetype = world.archetypes().iter().find(|archetype| archetype.contains(position)).unwrap();
+        assert_eq!(archetype.capacity(), 100);
+        world.extend((0..50).map(|_| (Velocity::default(),)));
+        let velocity = world.component_id::<Velocity>().unwrap();
+        let archetype = world.archetypes().iter().find(|archetype| archetype.contains(velocity)).unwrap();
+        assert_eq!(archetype.capacity(), 50);
+    }
+
+    #[test]
+    fn resources_and_components_of_the_same_type() {
+        let mut world = World::default();
+        world.insert_resource(Position { x: 1.0, y: 2.0 });
+        world.spawn((Position::default(),));
+        // the resource and the components don't conflict
+        let mut system = (|mut position: ResMut<Position>, query: Query<(Read<Position>,)>| {
+            position.x += query.count() as f32;
+        }).into_system();
+        system.run((), &mut world);
+        assert_eq!(world.get_resource::<Position>().unwrap().x, 2.0);
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(|_: Res<Position>| {})
+            .add_system(|_: Query<(Write<Position>,)>| {})
+            .add_system(|_: ResMut<Velocity>| {});
+        assert!(schedule.ambiguities(&mut world).is_empty());
+        schedule.add_system(|_: &World| {});
+        let ambiguities = schedule.ambiguities(&mut world);
+        assert_eq!(ambiguities.len(), 2);
+        assert!(ambiguities[1].components[0].contains("Velocity"));
+    }
+
+    #[test]
+    fn over_aligned_zero_sized_components() {
+        #[derive(Debug, PartialEq)]
+        #[repr(align(32))]
+        struct Tag;
+
+        let mut world = World::default();
+        let id = world.spawn((Tag, Position::default()));
+        assert_eq!(world.entity(id).unwrap().get_component::<Tag>(), Some(&Tag));
+        world.entity_mut(id).unwrap().add_component(Tag);
+        assert_eq!(world.entity_mut(id).unwrap().remove_component::<Tag>().map(|tag| *tag), Some(Tag));
+        world.entity_mut(id).unwrap().add_component(Tag);
+        let components = world.remove_entity(id).unwrap();
+        assert!(components.iter().any(|(_, component)| component.is::<Tag>()));
+    }
+
+    #[test]
+    fn unconsumed_combinations_dont_mark_changes() {
+        let mut world = World::default();
+        for _ in 0..3 {
+            world.spawn((Velocity::default(),));
+        }
+        world.clear_trackers();
+        let mut query = world.query::<(Write<Velocity>,)>();
+        drop(query.iter_combinations_mut::<2>());
+        drop(query.iter_combinations_mut::<5>());
+        assert_eq!(world.query_filtered::<(), (Changed<Velocity>,)>().into_iter().count(), 0);
+        // only the entities of combinations which were handed out count as changed
+        let mut query = world.query::<(Write<Velocity>,)>();
+        query.iter_combinations_mut::<2>().fetch_next();
+        assert_eq!(world.query_filtered::<(), (Changed<Velocity>,)>().into_iter().count(), 2);
+    }
+
 }
@@ -0,0 +1,163 @@
+use std::any::Any;
+use crate::component::{ComponentId, HookKind};
+use crate::system::{AnySystem, BoxedSystem, IntoSystem};
+use crate::{EntityId, Tick, World};
+
+/// What observers get passed, the entity whose component got added or removed and a copy of the component.
+pub struct Trigger<CT> {
+    pub entity: EntityId,
+    pub component: CT,
+}
+
+/// Identifies an observer added to a world, see `World::add_observer`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ObserverId(usize);
+
+/// A system which runs whenever a component of type `CT` gets added to or removed from any entity,
+/// e.g. `Observer::on_add(|In(trigger): In<Trigger<Health>>, mut commands: Commands| ..)`.
+pub struct Observer<CT> {
+    kind: HookKind,
+    system: BoxedSystem<Trigger<CT>, ()>,
+    deferred: bool,
+}
+
+impl<CT: Clone + Send + 'static> Observer<CT> {
+
+    /// Runs `system` after a `CT` component got added to an entity which didn't have one before.
+    pub fn on_add<Marker, S: IntoSystem<Marker, System: AnySystem<In = Trigger<CT>, Out = ()>>>(system: S) -> Self {
+        Self::new(HookKind::Add, system)
+    }
+
+    /// Runs `system` before a `CT` component gets removed from an entity, this includes despawning the entity.
+    pub fn on_remove<Marker, S: IntoSystem<Marker, System: AnySystem<In = Trigger<CT>, Out = ()>>>(system: S) -> Self {
+        Self::new(HookKind::Remove, system)
+    }
+
+    fn new<Marker, S: IntoSystem<Marker, System: AnySystem<In = Trigger<CT>, Out = ()>>>(kind: HookKind, system: S) -> Self {
+        Self {
+            kind,
+            system: Box::new(system.into_system()),
+            deferred: false,
+        }
+    }
+
+    /// Runs the observer at the next sync point of the running schedule instead of right away,
+    /// or once `World::run_deferred_observers` gets called. The component still gets copied right away.
+    pub fn deferred(mut self) -> Self {
+        self.deferred = true;
+        self
+    }
+
+}
+
+pub(crate) type ErasedTrigger = Box<dyn Any + Send>;
+
+// copies the component out of the world while the entity still has it
+pub(crate) type CaptureFn = fn(&World, EntityId) -> Option<ErasedTrigger>;
+
+pub(crate) type ObserverSystem = Box<dyn ErasedObserverSystem>;
+
+/// The system of an observer with the type of its trigger erased.
+pub(crate) trait ErasedObserverSystem: Send {
+    fn run(&mut self, trigger: ErasedTrigger, world: &mut World);
+    fn check_change_tick(&mut self, tick: Tick);
+}
+
+impl<CT: 'static> ErasedObserverSystem for BoxedSystem<Trigger<CT>, ()> {
+    fn run(&mut self, trigger: ErasedTrigger, world: &mut World) {
+        AnySystem::run(&mut **self, *trigger.downcast::<Trigger<CT>>().unwrap(), world);
+    }
+
+    #[inline]
+    fn check_change_tick(&mut self, tick: Tick) {
+        AnySystem::check_change_tick(&mut **self, tick);
+    }
+}
+
+struct ObserverEntry {
+    component_id: ComponentId,
+    kind: HookKind,
+    deferred: bool,
+    capture: CaptureFn,
+    // taken out while it runs
+    system: Option<ObserverSystem>,
+}
+
+#[derive(Default)]
+pub(crate) struct Observers {
+    entries: Vec<Option<ObserverEntry>>,
+    // triggers of deferred observers in the order they happened
+    pending: Vec<(ObserverId, ErasedTrigger)>,
+}
+
+impl Observers {
+
+    pub fn add<CT: Clone + Send + 'static>(&mut self, component_id: ComponentId, observer: Observer<CT>) -> ObserverId {
+        self.entries.push(Some(ObserverEntry {
+            component_id,
+            kind: observer.kind,
+            deferred: observer.deferred,
+            capture: capture::<CT>,
+            system: Some(Box::new(observer.system)),
+        }));
+        ObserverId(self.entries.len() - 1)
+    }
+
+    pub fn remove(&mut self, id: ObserverId) -> bool {
+        self.pending.retain(|(observer, _)| *observer != id);
+        self.entries.get_mut(id.0).and_then(Option::take).is_some()
+    }
+
+    #[inline]
+    pub fn observes(&self, kind: HookKind) -> bool {
+        self.entries.iter().flatten().any(|entry| entry.kind == kind)
+    }
+
+    #[inline]
+    pub fn take_pending(&mut self) -> Vec<(ObserverId, ErasedTrigger)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// The observers of `kind` for the component together with whether they are deferred, in the order they were added.
+    pub fn matching(&self, component_id: ComponentId, kind: HookKind) -> Vec<(ObserverId, bool, CaptureFn)> {
+        self.entries.iter().enumerate()
+            .filter_map(|(idx, entry)| entry.as_ref().map(|entry| (idx, entry)))
+            .filter(|(_, entry)| entry.component_id == component_id && entry.kind == kind)
+            .map(|(idx, entry)| (ObserverId(idx), entry.deferred, entry.capture))
+            .collect()
+    }
+
+    #[inline]
+    pub fn push_pending(&mut self, id: ObserverId, trigger: ErasedTrigger) {
+        self.pending.push((id, trigger));
+    }
+
+    #[inline]
+    pub fn take_system(&mut self, id: ObserverId) -> Option<ObserverSystem> {
+        self.entries[id.0].as_mut().and_then(|entry| entry.system.take())
+    }
+
+    /// Does nothing if the observer got removed in the meantime.
+    #[inline]
+    pub fn restore_system(&mut self, id: ObserverId, system: ObserverSystem) {
+        if let Some(entry) = &mut self.entries[id.0] {
+            entry.system = Some(system);
+        }
+    }
+
+    /// Observers which are currently running aren't clamped.
+    pub fn check_change_ticks(&mut self, tick: Tick) {
+        self.entries.iter_mut().flatten()
+            .filter_map(|entry| entry.system.as_mut())
+            .for_each(|system| system.check_change_tick(tick));
+    }
+
+}
+
+fn capture<CT: Clone + Send + 'static>(world: &World, entity: EntityId) -> Option<ErasedTrigger> {
+    let component = world.entity(entity)?.get_component::<CT>()?.clone();
+    Some(Box::new(Trigger {
+        entity,
+        component,
+    }))
+}
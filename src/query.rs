@@ -0,0 +1,1385 @@
+use std::any::type_name;
+use std::borrow::{Borrow, Cow};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use bevy_utils::all_tuples;
+use crate::archetype::{Column, EntityLocation};
+use crate::access::Access;
+use crate::atomic_bit_set::AtomicBitSet;
+use crate::sparse_set::SparseSet;
+use crate::{Archetype, ArchetypeId, WorldId, ComponentId, ComponentTicks, EntityId, EntityRef, Read, StorageKind, Tick, World, Write};
+
+/// Describes what a query fetches for every entity it visits.
+///
+/// # Safety
+/// `fetch` may only hand out mutable references to components whose ids get reported as written.
+pub unsafe trait WorldQuery {
+
+    type Item<'w>;
+    /// Everything needed to fetch items from a single archetype.
+    type Fetch<'w>;
+    /// Resolved once per query, this usually holds component ids.
+    type State: Clone + Send + Sync + 'static;
+
+    fn init_state(world: &mut World) -> Self::State;
+
+    /// Whether entities of `archetype` can match at all, components stored in sparse sets are checked per entity.
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool;
+
+    /// Whether `matches_archetype` alone decides which entities match, meaning `fetch` never returns `None`
+    /// for entities of matching archetypes. This allows counting entities without visiting them.
+    fn is_archetypal(state: &Self::State) -> bool;
+
+    /// Adds the components this query accesses, panics if they conflict with accesses already in `access`.
+    fn update_access(state: &Self::State, access: &mut Access);
+
+    /// Components which changed after `last_run` count as changed, mutable accesses happen at `this_run`.
+    ///
+    /// # Safety
+    /// The world has to allow the accesses this query performs.
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'w>;
+
+    /// Returns `None` if the entity doesn't match.
+    ///
+    /// # Safety
+    /// `row` has to be in bounds of the archetype `fetch` was created for and `id` has to be the entity stored there.
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>>;
+
+    /// Whether `fetch` would return `Some`, queries whose `fetch` has side effects like marking components as changed
+    /// have to override this so it doesn't have them.
+    ///
+    /// # Safety
+    /// Same as for `fetch`.
+    #[inline]
+    unsafe fn matches<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> bool {
+        Self::fetch(fetch, id, row).is_some()
+    }
+
+}
+
+/// Queries which only ever read from the world.
+///
+/// # Safety
+/// The query may not hand out any mutable references.
+pub unsafe trait ReadOnlyWorldQuery: WorldQuery {}
+
+/// Where the values of a single component type live for the entities of one archetype.
+#[derive(Copy, Clone)]
+pub struct StorageFetch<'w>(Storage<'w>);
+
+#[derive(Copy, Clone)]
+enum Storage<'w> {
+    Table(&'w Column),
+    Sparse(&'w SparseSet),
+    Missing,
+}
+
+impl<'w> StorageFetch<'w> {
+
+    pub(crate) fn new(world: &'w World, archetype: &'w Archetype, component_id: ComponentId) -> Self {
+        let storage = match world.components.storage_kind(component_id) {
+            StorageKind::Table => archetype.column(component_id).map(Storage::Table),
+            StorageKind::SparseSet => world.sparse_sets.get(component_id).map(Storage::Sparse),
+        };
+        Self(storage.unwrap_or(Storage::Missing))
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, id: EntityId, row: usize) -> Option<*mut u8> {
+        match self.0 {
+            Storage::Table(column) => Some(column.get_ptr(row)),
+            Storage::Sparse(set) => set.get_ptr(id),
+            Storage::Missing => None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_ticks(&self, id: EntityId, row: usize) -> Option<*mut ComponentTicks> {
+        match self.0 {
+            Storage::Table(column) => Some(column.get_ticks_ptr(row)),
+            Storage::Sparse(set) => set.get_ticks_ptr(id),
+            Storage::Missing => None,
+        }
+    }
+
+}
+
+#[inline]
+pub(crate) fn matches_component(world_kind: StorageKind, component_id: ComponentId, archetype: &Archetype) -> bool {
+    world_kind == StorageKind::SparseSet || archetype.contains(component_id)
+}
+
+unsafe impl<'a, T: 'static> WorldQuery for Read<'a, T> {
+    type Item<'w> = &'w T;
+    type Fetch<'w> = StorageFetch<'w>;
+    type State = (ComponentId, StorageKind);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id))
+    }
+
+    #[inline]
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        matches_component(state.1, state.0, archetype)
+    }
+
+    #[inline]
+    fn is_archetypal(state: &Self::State) -> bool {
+        state.1 == StorageKind::Table
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        assert!(!access.has_write(state.0), "Read<{}> conflicts with a mutable access in the same query", type_name::<T>());
+        access.add_read(state.0);
+    }
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, _last_run: Tick, _this_run: Tick) -> Self::Fetch<'w> {
+        StorageFetch::new(world, archetype, state.0)
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        fetch.get(id, row).map(|ptr| &*ptr.cast::<T>())
+    }
+}
+
+unsafe impl<'a, T: 'static> ReadOnlyWorldQuery for Read<'a, T> {}
+
+unsafe impl<'a, T: 'static> WorldQuery for Write<'a, T> {
+    type Item<'w> = &'w mut T;
+    // the tick mutably fetched components get marked as changed at
+    type Fetch<'w> = (StorageFetch<'w>, Tick);
+    type State = (ComponentId, StorageKind);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id))
+    }
+
+    #[inline]
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        matches_component(state.1, state.0, archetype)
+    }
+
+    #[inline]
+    fn is_archetypal(state: &Self::State) -> bool {
+        state.1 == StorageKind::Table
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        assert!(!access.has_read(state.0), "Write<{}> conflicts with another access in the same query", type_name::<T>());
+        access.add_write(state.0);
+    }
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, _last_run: Tick, this_run: Tick) -> Self::Fetch<'w> {
+        (StorageFetch::new(world, archetype, state.0), this_run)
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        let ptr = fetch.0.get(id, row)?;
+        (*fetch.0.get_ticks(id, row).unwrap()).changed = fetch.1;
+        Some(&mut *ptr.cast::<T>())
+    }
+
+    #[inline]
+    unsafe fn matches<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> bool {
+        fetch.0.get(id, row).is_some()
+    }
+}
+
+/// Fetches the id of the matched entity.
+unsafe impl WorldQuery for EntityId {
+    type Item<'w> = EntityId;
+    type Fetch<'w> = ();
+    type State = ();
+
+    fn init_state(_world: &mut World) -> Self::State {}
+
+    #[inline]
+    fn matches_archetype(_state: &Self::State, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    #[inline]
+    fn is_archetypal(_state: &Self::State) -> bool {
+        true
+    }
+
+    fn update_access(_state: &Self::State, _access: &mut Access) {}
+
+    #[inline]
+    unsafe fn init_fetch<'w>(_world: &'w World, _state: &Self::State, _archetype: &'w Archetype, _last_run: Tick, _this_run: Tick) -> Self::Fetch<'w> {}
+
+    #[inline]
+    unsafe fn fetch<'w>(_fetch: &Self::Fetch<'w>, id: EntityId, _row: usize) -> Option<Self::Item<'w>> {
+        Some(id)
+    }
+}
+
+unsafe impl ReadOnlyWorldQuery for EntityId {}
+
+/// Fetches a read-only view of the matched entity which can access all of its components.
+unsafe impl<'a> WorldQuery for EntityRef<'a> {
+    type Item<'w> = EntityRef<'w>;
+    type Fetch<'w> = (&'w World, ArchetypeId);
+    type State = ();
+
+    fn init_state(_world: &mut World) -> Self::State {}
+
+    #[inline]
+    fn matches_archetype(_state: &Self::State, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    #[inline]
+    fn is_archetypal(_state: &Self::State) -> bool {
+        true
+    }
+
+    fn update_access(_state: &Self::State, access: &mut Access) {
+        assert!(access.writes().is_empty(), "EntityRef conflicts with a mutable access in the same query");
+        access.add_read_all();
+    }
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, _state: &Self::State, archetype: &'w Archetype, _last_run: Tick, _this_run: Tick) -> Self::Fetch<'w> {
+        (world, archetype.id())
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        Some(EntityRef::new(fetch.0, id, EntityLocation { archetype: fetch.1, row }))
+    }
+}
+
+unsafe impl<'a> ReadOnlyWorldQuery for EntityRef<'a> {}
+
+/// Fetches `None` for entities which don't match `Q` instead of skipping them.
+unsafe impl<Q: WorldQuery> WorldQuery for Option<Q> {
+    type Item<'w> = Option<Q::Item<'w>>;
+    // `None` if no entity of the archetype can match
+    type Fetch<'w> = Option<Q::Fetch<'w>>;
+    type State = Q::State;
+
+    fn init_state(world: &mut World) -> Self::State {
+        Q::init_state(world)
+    }
+
+    #[inline]
+    fn matches_archetype(_state: &Self::State, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    #[inline]
+    fn is_archetypal(_state: &Self::State) -> bool {
+        true
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        Q::update_access(state, access);
+    }
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'w> {
+        Q::matches_archetype(state, archetype).then(|| Q::init_fetch(world, state, archetype, last_run, this_run))
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        Some(fetch.as_ref().and_then(|fetch| Q::fetch(fetch, id, row)))
+    }
+
+    #[inline(always)]
+    unsafe fn matches<'w>(_fetch: &Self::Fetch<'w>, _id: EntityId, _row: usize) -> bool {
+        true
+    }
+}
+
+unsafe impl<Q: ReadOnlyWorldQuery> ReadOnlyWorldQuery for Option<Q> {}
+
+/// Fetches whether the entity has a `T` component, without filtering out entities which don't.
+pub struct Has<T>(PhantomData<fn() -> T>);
+
+unsafe impl<T: 'static> WorldQuery for Has<T> {
+    type Item<'w> = bool;
+    type Fetch<'w> = StorageFetch<'w>;
+    type State = (ComponentId, StorageKind);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id))
+    }
+
+    #[inline]
+    fn matches_archetype(_state: &Self::State, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    #[inline]
+    fn is_archetypal(_state: &Self::State) -> bool {
+        true
+    }
+
+    // only the presence of the component is checked
+    fn update_access(_state: &Self::State, _access: &mut Access) {}
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, _last_run: Tick, _this_run: Tick) -> Self::Fetch<'w> {
+        StorageFetch::new(world, archetype, state.0)
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        Some(fetch.get(id, row).is_some())
+    }
+}
+
+unsafe impl<T: 'static> ReadOnlyWorldQuery for Has<T> {}
+
+/// Filters for entities which have a `T` component without fetching it.
+pub struct With<T>(PhantomData<fn() -> T>);
+
+unsafe impl<T: 'static> WorldQuery for With<T> {
+    type Item<'w> = ();
+    type Fetch<'w> = StorageFetch<'w>;
+    type State = (ComponentId, StorageKind);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id))
+    }
+
+    #[inline]
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        matches_component(state.1, state.0, archetype)
+    }
+
+    #[inline]
+    fn is_archetypal(state: &Self::State) -> bool {
+        state.1 == StorageKind::Table
+    }
+
+    // only the presence of the component is checked
+    fn update_access(_state: &Self::State, _access: &mut Access) {}
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, _last_run: Tick, _this_run: Tick) -> Self::Fetch<'w> {
+        StorageFetch::new(world, archetype, state.0)
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        fetch.get(id, row).map(|_| ())
+    }
+}
+
+unsafe impl<T: 'static> ReadOnlyWorldQuery for With<T> {}
+
+/// Filters for entities which don't have a `T` component.
+pub struct Without<T>(PhantomData<fn() -> T>);
+
+unsafe impl<T: 'static> WorldQuery for Without<T> {
+    type Item<'w> = ();
+    type Fetch<'w> = StorageFetch<'w>;
+    type State = (ComponentId, StorageKind);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id))
+    }
+
+    #[inline]
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        state.1 == StorageKind::SparseSet || !archetype.contains(state.0)
+    }
+
+    #[inline]
+    fn is_archetypal(state: &Self::State) -> bool {
+        state.1 == StorageKind::Table
+    }
+
+    fn update_access(_state: &Self::State, _access: &mut Access) {}
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, _last_run: Tick, _this_run: Tick) -> Self::Fetch<'w> {
+        StorageFetch::new(world, archetype, state.0)
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        match fetch.get(id, row) {
+            Some(_) => None,
+            None => Some(()),
+        }
+    }
+}
+
+unsafe impl<T: 'static> ReadOnlyWorldQuery for Without<T> {}
+
+/// Filters for entities whose `T` component was added or mutably accessed since the querying system last ran.
+pub struct Changed<T>(PhantomData<fn() -> T>);
+
+unsafe impl<T: 'static> WorldQuery for Changed<T> {
+    type Item<'w> = ();
+    type Fetch<'w> = (StorageFetch<'w>, Tick, Tick);
+    type State = (ComponentId, StorageKind);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id))
+    }
+
+    #[inline]
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        matches_component(state.1, state.0, archetype)
+    }
+
+    #[inline]
+    fn is_archetypal(_state: &Self::State) -> bool {
+        false
+    }
+
+    // the ticks get read, but filters are combined with their query without checking for conflicts
+    fn update_access(state: &Self::State, access: &mut Access) {
+        access.add_read(state.0);
+    }
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'w> {
+        (StorageFetch::new(world, archetype, state.0), last_run, this_run)
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        (*fetch.0.get_ticks(id, row)?).is_changed(fetch.1, fetch.2).then_some(())
+    }
+}
+
+unsafe impl<T: 'static> ReadOnlyWorldQuery for Changed<T> {}
+
+/// Filters for entities which received their `T` component since the querying system last ran.
+pub struct Added<T>(PhantomData<fn() -> T>);
+
+unsafe impl<T: 'static> WorldQuery for Added<T> {
+    type Item<'w> = ();
+    type Fetch<'w> = (StorageFetch<'w>, Tick, Tick);
+    type State = (ComponentId, StorageKind);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id))
+    }
+
+    #[inline]
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        matches_component(state.1, state.0, archetype)
+    }
+
+    #[inline]
+    fn is_archetypal(_state: &Self::State) -> bool {
+        false
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        access.add_read(state.0);
+    }
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'w> {
+        (StorageFetch::new(world, archetype, state.0), last_run, this_run)
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        (*fetch.0.get_ticks(id, row)?).is_added(fetch.1, fetch.2).then_some(())
+    }
+}
+
+unsafe impl<T: 'static> ReadOnlyWorldQuery for Added<T> {}
+
+/// A user supplied test of a component's value.
+pub type Predicate<T> = Arc<dyn Fn(&T) -> bool + Send + Sync>;
+
+/// Filters for entities whose `T` component passes a predicate, which gets evaluated during iteration before anything
+/// gets fetched. The predicate gets set on the `QueryState`, e.g. via `World::query_where`, until then this acts like `With<T>`.
+// unlike the other filters, this accesses the values, so it's only `Send` and `Sync` if they are
+pub struct Filter<T>(PhantomData<T>);
+
+unsafe impl<T: 'static> WorldQuery for Filter<T> {
+    type Item<'w> = ();
+    type Fetch<'w> = (StorageFetch<'w>, Option<Predicate<T>>);
+    type State = (ComponentId, StorageKind, Option<Predicate<T>>);
+
+    fn init_state(world: &mut World) -> Self::State {
+        let component_id = world.components.init::<T>();
+        (component_id, world.components.storage_kind(component_id), None)
+    }
+
+    #[inline]
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        matches_component(state.1, state.0, archetype)
+    }
+
+    #[inline]
+    fn is_archetypal(_state: &Self::State) -> bool {
+        false
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        access.add_read(state.0);
+    }
+
+    #[inline]
+    unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, _last_run: Tick, _this_run: Tick) -> Self::Fetch<'w> {
+        (StorageFetch::new(world, archetype, state.0), state.2.clone())
+    }
+
+    #[inline]
+    unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+        let value = &*fetch.0.get(id, row)?.cast::<T>();
+        fetch.1.as_ref().is_none_or(|predicate| predicate(value)).then_some(())
+    }
+}
+
+unsafe impl<T: 'static> ReadOnlyWorldQuery for Filter<T> {}
+
+macro_rules! impl_world_query {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: WorldQuery),*> WorldQuery for ($($name,)*) {
+            type Item<'w> = ($($name::Item<'w>,)*);
+            type Fetch<'w> = ($($name::Fetch<'w>,)*);
+            type State = ($($name::State,)*);
+
+            fn init_state(world: &mut World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            #[inline]
+            fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::matches_archetype($name, archetype))*
+            }
+
+            #[inline]
+            fn is_archetypal(state: &Self::State) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::is_archetypal($name))*
+            }
+
+            fn update_access(state: &Self::State, access: &mut Access) {
+                let ($($name,)*) = state;
+                $($name::update_access($name, access);)*
+            }
+
+            #[inline]
+            unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'w> {
+                let ($($name,)*) = state;
+                ($($name::init_fetch(world, $name, archetype, last_run, this_run),)*)
+            }
+
+            #[inline]
+            unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+                let ($($name,)*) = fetch;
+                Some(($($name::fetch($name, id, row)?,)*))
+            }
+
+            #[inline]
+            unsafe fn matches<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> bool {
+                let ($($name,)*) = fetch;
+                true $(&& $name::matches($name, id, row))*
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyWorldQuery),*> ReadOnlyWorldQuery for ($($name,)*) {}
+    };
+}
+
+all_tuples!(impl_world_query, 0, 15, Q);
+
+/// Matches entities which pass any of the filters in the tuple `T`, e.g. `Or<(With<Burning>, With<Poisoned>)>`.
+pub struct Or<T>(PhantomData<T>);
+
+macro_rules! impl_or {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: ReadOnlyWorldQuery),*> WorldQuery for Or<($($name,)*)> {
+            type Item<'w> = ();
+            // the filters which can't match any entity of the archetype don't get fetched
+            type Fetch<'w> = ($(Option<$name::Fetch<'w>>,)*);
+            type State = ($($name::State,)*);
+
+            fn init_state(world: &mut World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            #[inline]
+            fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+                let ($($name,)*) = state;
+                false $(|| $name::matches_archetype($name, archetype))*
+            }
+
+            #[inline]
+            fn is_archetypal(state: &Self::State) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::is_archetypal($name))*
+            }
+
+            fn update_access(state: &Self::State, access: &mut Access) {
+                let ($($name,)*) = state;
+                $($name::update_access($name, access);)*
+            }
+
+            #[inline]
+            unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'w> {
+                let ($($name,)*) = state;
+                ($($name::matches_archetype($name, archetype).then(|| $name::init_fetch(world, $name, archetype, last_run, this_run)),)*)
+            }
+
+            #[inline]
+            unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+                let ($($name,)*) = fetch;
+                (false $(|| $name.as_ref().is_some_and(|fetch| $name::fetch(fetch, id, row).is_some()))*).then_some(())
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyWorldQuery),*> ReadOnlyWorldQuery for Or<($($name,)*)> {}
+    };
+}
+
+all_tuples!(impl_or, 1, 15, F);
+
+/// Fetches the items of all queries in the tuple `T` as `Option`s and matches entities for which at least one of them matches,
+/// e.g. `AnyOf<(Read<Circle>, Read<Rectangle>)>`.
+pub struct AnyOf<T>(PhantomData<T>);
+
+macro_rules! impl_any_of {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: WorldQuery),*> WorldQuery for AnyOf<($($name,)*)> {
+            type Item<'w> = ($(Option<$name::Item<'w>>,)*);
+            // the queries which can't match any entity of the archetype don't get fetched
+            type Fetch<'w> = ($(Option<$name::Fetch<'w>>,)*);
+            type State = ($($name::State,)*);
+
+            fn init_state(world: &mut World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            #[inline]
+            fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+                let ($($name,)*) = state;
+                false $(|| $name::matches_archetype($name, archetype))*
+            }
+
+            #[inline]
+            fn is_archetypal(state: &Self::State) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::is_archetypal($name))*
+            }
+
+            fn update_access(state: &Self::State, access: &mut Access) {
+                let ($($name,)*) = state;
+                $($name::update_access($name, access);)*
+            }
+
+            #[inline]
+            unsafe fn init_fetch<'w>(world: &'w World, state: &Self::State, archetype: &'w Archetype, last_run: Tick, this_run: Tick) -> Self::Fetch<'w> {
+                let ($($name,)*) = state;
+                ($($name::matches_archetype($name, archetype).then(|| $name::init_fetch(world, $name, archetype, last_run, this_run)),)*)
+            }
+
+            #[inline]
+            unsafe fn fetch<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> Option<Self::Item<'w>> {
+                let ($($name,)*) = fetch;
+                let item = ($($name.as_ref().and_then(|fetch| $name::fetch(fetch, id, row)),)*);
+                let ($($name,)*) = &item;
+                (false $(|| $name.is_some())*).then_some(item)
+            }
+
+            #[inline]
+            unsafe fn matches<'w>(fetch: &Self::Fetch<'w>, id: EntityId, row: usize) -> bool {
+                let ($($name,)*) = fetch;
+                false $(|| $name.as_ref().is_some_and(|fetch| $name::matches(fetch, id, row)))*
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyWorldQuery),*> ReadOnlyWorldQuery for AnyOf<($($name,)*)> {}
+    };
+}
+
+all_tuples!(impl_any_of, 1, 15, Q);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum QueryEntityError {
+    NoSuchEntity(EntityId),
+    /// The entity lacks components the query asks for or doesn't pass its filter.
+    QueryDoesNotMatch(EntityId),
+}
+
+impl fmt::Display for QueryEntityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryEntityError::NoSuchEntity(id) => write!(f, "the entity {id:?} doesn't exist"),
+            QueryEntityError::QueryDoesNotMatch(id) => write!(f, "the entity {id:?} doesn't match the query"),
+        }
+    }
+}
+
+impl Error for QueryEntityError {}
+
+/// Why `Query::single` failed, this holds the type name of the query.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum QuerySingleError {
+    NoEntities(&'static str),
+    MultipleEntities(&'static str),
+}
+
+impl fmt::Display for QuerySingleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuerySingleError::NoEntities(query) => write!(f, "no entity matches the query {query}"),
+            QuerySingleError::MultipleEntities(query) => write!(f, "multiple entities match the query {query}"),
+        }
+    }
+}
+
+impl Error for QuerySingleError {}
+
+/// The resolved state of a query, this includes the archetypes it matched. It can be created once and stored,
+/// archetypes which got created in the meantime get matched incrementally whenever the state gets used again.
+pub struct QueryState<Q: WorldQuery, F: WorldQuery = ()> {
+    world_id: WorldId,
+    query: Q::State,
+    filter: F::State,
+    access: Access,
+    // whether the matched archetypes contain matching entities only
+    archetypal: bool,
+    archetypes: Vec<ArchetypeId>,
+    // the number of archetypes of the world which were already matched
+    seen_archetypes: usize,
+}
+
+impl<Q: WorldQuery, F: WorldQuery> QueryState<Q, F> {
+
+    /// Panics if the query accesses a component mutably and also accesses it in any other way,
+    /// as that would hand out aliasing references.
+    pub fn new(world: &mut World) -> Self {
+        let query = Q::init_state(world);
+        let filter = F::init_state(world);
+        let mut access = Access::default();
+        Q::update_access(&query, &mut access);
+        let mut filter_access = Access::default();
+        F::update_access(&filter, &mut filter_access);
+        access.extend(&filter_access);
+        let mut state = Self {
+            world_id: world.id(),
+            archetypal: Q::is_archetypal(&query) && F::is_archetypal(&filter),
+            query,
+            filter,
+            access,
+            archetypes: vec![],
+            seen_archetypes: 0,
+        };
+        state.update_archetypes(world);
+        state
+    }
+
+    /// Matches the archetypes which were created since the last update, panics if `world` isn't the world the state was created for.
+    pub fn update_archetypes(&mut self, world: &World) {
+        assert_eq!(self.world_id, world.id(), "the query state was created for a different world");
+        for archetype in world.archetypes.iter().skip(self.seen_archetypes) {
+            if self.matches_archetype(archetype) {
+                self.archetypes.push(archetype.id());
+            }
+        }
+        self.seen_archetypes = world.archetypes.len();
+    }
+
+    /// Narrows the query to `NewQ` while keeping the filter. The result matches the entities `QueryState<NewQ, F>::new` would,
+    /// including ones lacking components only the original query asked for, use the filter to require those. Panics if `NewQ`
+    /// accesses anything the query doesn't or writes what the query only reads.
+    pub fn transmute<NewQ: WorldQuery>(&self, world: &mut World) -> QueryState<NewQ, F> {
+        assert_eq!(self.world_id, world.id(), "the query state was created for a different world");
+        let query = NewQ::init_state(world);
+        let mut access = Access::default();
+        NewQ::update_access(&query, &mut access);
+        let mut filter_access = Access::default();
+        F::update_access(&self.filter, &mut filter_access);
+        assert!(access.is_subset(&self.access), "{} accesses components {} doesn't", type_name::<NewQ>(), type_name::<Q>());
+        access.extend(&filter_access);
+        let mut state = QueryState {
+            world_id: self.world_id,
+            archetypal: NewQ::is_archetypal(&query) && F::is_archetypal(&self.filter),
+            query,
+            filter: self.filter.clone(),
+            access,
+            archetypes: vec![],
+            seen_archetypes: 0,
+        };
+        // archetypes created later get matched against `NewQ` only, so the existing ones have to be as well
+        state.update_archetypes(world);
+        state
+    }
+
+    /// The components the query and its filter access.
+    #[inline(always)]
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// The archetypes which matched as of the last update.
+    #[inline]
+    pub fn matched_archetypes(&self) -> &[ArchetypeId] {
+        &self.archetypes
+    }
+
+    pub fn query<'w>(&mut self, world: &'w mut World) -> Query<'w, '_, Q, F> {
+        world.flush();
+        self.update_archetypes(world);
+        let (last_run, this_run) = (world.last_change_tick(), world.change_tick());
+        Query::new(world, Cow::Borrowed(self), last_run, this_run)
+    }
+
+    /// Like `query_ref`, but for queries which may write.
+    ///
+    /// # Safety
+    /// Nothing else may access the components the query accesses for `'w` in a conflicting way.
+    pub unsafe fn query_unchecked<'w>(&mut self, world: &'w World) -> Query<'w, '_, Q, F> {
+        self.update_archetypes(world);
+        Query::new(world, Cow::Borrowed(self), world.last_change_tick(), world.change_tick())
+    }
+
+    /// Only needs shared access to the world as the query doesn't write anything,
+    /// entities which were reserved but not flushed yet are skipped.
+    pub fn query_ref<'w>(&mut self, world: &'w World) -> Query<'w, '_, Q, F> where Q: ReadOnlyWorldQuery {
+        self.update_archetypes(world);
+        Query::new(world, Cow::Borrowed(self), world.last_change_tick(), world.change_tick())
+    }
+
+    #[inline]
+    fn matches_archetype(&self, archetype: &Archetype) -> bool {
+        Q::matches_archetype(&self.query, archetype) && F::matches_archetype(&self.filter, archetype)
+    }
+
+    /// # Safety
+    /// The world has to allow the accesses of the query for `'w`.
+    unsafe fn for_each_unchecked<'w>(&self, world: &'w World, last_run: Tick, this_run: Tick, mut f: impl FnMut(Q::Item<'w>)) {
+        for archetype in &self.archetypes {
+            let archetype = world.archetypes.get(*archetype).unwrap();
+            let query = Q::init_fetch(world, &self.query, archetype, last_run, this_run);
+            let filter = F::init_fetch(world, &self.filter, archetype, last_run, this_run);
+            for (row, id) in archetype.entities().iter().enumerate() {
+                if F::fetch(&filter, *id, row).is_none() {
+                    continue;
+                }
+                if let Some(item) = Q::fetch(&query, *id, row) {
+                    f(item);
+                }
+            }
+        }
+    }
+
+    /// # Safety
+    /// The world has to allow the accesses of the query and no other item of the entity may be alive if `Q` fetches mutably.
+    unsafe fn get_unchecked<'w>(&self, world: &'w World, id: EntityId, last_run: Tick, this_run: Tick) -> Result<Q::Item<'w>, QueryEntityError> {
+        let location = world.entities.get_location(id).ok_or(QueryEntityError::NoSuchEntity(id))?;
+        let archetype = world.archetypes.get(location.archetype).unwrap();
+        if !self.matches_archetype(archetype) {
+            return Err(QueryEntityError::QueryDoesNotMatch(id));
+        }
+        let filter = F::init_fetch(world, &self.filter, archetype, last_run, this_run);
+        F::fetch(&filter, id, location.row).ok_or(QueryEntityError::QueryDoesNotMatch(id))?;
+        let query = Q::init_fetch(world, &self.query, archetype, last_run, this_run);
+        Q::fetch(&query, id, location.row).ok_or(QueryEntityError::QueryDoesNotMatch(id))
+    }
+
+}
+
+impl<Q: WorldQuery, T: 'static> QueryState<Q, Filter<T>> {
+
+    /// Only entities whose `T` component passes `predicate` match from now on.
+    pub fn set_predicate(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static) {
+        self.filter.2 = Some(Arc::new(predicate));
+    }
+
+}
+
+impl<Q: WorldQuery, F: WorldQuery> Clone for QueryState<Q, F> {
+    fn clone(&self) -> Self {
+        Self {
+            world_id: self.world_id,
+            query: self.query.clone(),
+            filter: self.filter.clone(),
+            access: self.access.clone(),
+            archetypal: self.archetypal,
+            archetypes: self.archetypes.clone(),
+            seen_archetypes: self.seen_archetypes,
+        }
+    }
+}
+
+/// Visits all entities which have the components `Q` asks for and pass the filter `F`.
+pub struct Query<'w, 's, Q: WorldQuery, F: WorldQuery = ()> {
+    world: &'w World,
+    state: Cow<'s, QueryState<Q, F>>,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'w, 's, Q: WorldQuery, F: WorldQuery> Query<'w, 's, Q, F> {
+
+    /// `world` has to be borrowed mutably for `'w` or at least allow all accesses of `Q`.
+    #[inline]
+    pub(crate) fn new(world: &'w World, state: Cow<'s, QueryState<Q, F>>, last_run: Tick, this_run: Tick) -> Self {
+        Self {
+            world,
+            state,
+            last_run,
+            this_run,
+        }
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> QueryIter<'_, '_, Q, F> {
+        QueryIter::new(self.world, Cow::Borrowed(&self.state), self.last_run, self.this_run)
+    }
+
+    /// Only visits the entities whose index is in `mask`, see `iter_masked`.
+    #[inline]
+    pub fn iter_masked_mut<'a>(&'a mut self, mask: &'a AtomicBitSet) -> QueryIter<'a, 'a, Q, F> {
+        let mut iter = self.iter_mut();
+        iter.mask = Some(mask);
+        iter
+    }
+
+    /// Calls `f` for every matching item, this is the fastest way to visit all items, see `for_each`.
+    #[inline]
+    pub fn for_each_mut(&mut self, f: impl FnMut(Q::Item<'_>)) {
+        // SAFETY: the query is borrowed mutably and every entity gets visited once
+        unsafe { self.state.for_each_unchecked(self.world, self.last_run, self.this_run, f) }
+    }
+
+    /// Visits all unique combinations of `K` distinct matching entities, as the items of a combination can be mutable
+    /// and the same entity shows up in many combinations, this can't be an `Iterator`. Use `fetch_next` instead.
+    #[inline]
+    pub fn iter_combinations_mut<const K: usize>(&mut self) -> QueryCombinationIter<'_, '_, Q, F, K> {
+        QueryCombinationIter::new(self.world, &self.state, self.last_run, self.this_run)
+    }
+
+    /// Returns the item of the only matching entity, errors if there are none or several of them.
+    pub fn single_mut(&mut self) -> Result<Q::Item<'_>, QuerySingleError> {
+        single(self.iter_mut())
+    }
+
+    /// Fetches the item of a single entity.
+    pub fn get(&mut self, id: EntityId) -> Result<Q::Item<'_>, QueryEntityError> {
+        // SAFETY: the query is borrowed mutably, so no other item can be alive
+        unsafe { self.state.get_unchecked(self.world, id, self.last_run, self.this_run) }
+    }
+
+    /// Pairs the items of this query with the items `other` fetches for the same entities, entities which
+    /// only one of the queries matches get skipped. Panics if the accesses of the queries conflict.
+    pub fn join<'a, Q2: WorldQuery, F2: WorldQuery>(&'a mut self, other: &'a mut Query<'_, '_, Q2, F2>) -> QueryJoinIter<'a, Q, F, Q2, F2> {
+        let right = QueryJoinRight::new(self.world, &self.state, other);
+        QueryJoinIter {
+            left: self.iter_mut(),
+            right,
+        }
+    }
+
+    /// Like `join`, but keeps the entities `other` doesn't match and yields `None` for them.
+    pub fn left_join<'a, Q2: WorldQuery, F2: WorldQuery>(&'a mut self, other: &'a mut Query<'_, '_, Q2, F2>) -> QueryLeftJoinIter<'a, Q, F, Q2, F2> {
+        let right = QueryJoinRight::new(self.world, &self.state, other);
+        QueryLeftJoinIter {
+            left: self.iter_mut(),
+            right,
+        }
+    }
+
+    /// Visits the items of the listed entities which match the query, as the list may contain
+    /// the same entity multiple times this can't be an `Iterator`. Use `fetch_next` instead.
+    #[inline]
+    pub fn iter_many_mut<I: IntoIterator>(&mut self, entities: I) -> QueryManyIter<'_, '_, Q, F, I::IntoIter>
+        where I::Item: Borrow<EntityId> {
+        QueryManyIter::new(self.world, &self.state, entities.into_iter(), self.last_run, self.this_run)
+    }
+
+}
+
+impl<'w, 's, Q: ReadOnlyWorldQuery, F: WorldQuery> Query<'w, 's, Q, F> {
+
+    #[inline]
+    pub fn iter(&self) -> QueryIter<'_, '_, Q, F> {
+        QueryIter::new(self.world, Cow::Borrowed(&self.state), self.last_run, self.this_run)
+    }
+
+    /// Only visits the entities whose index is in `mask`, e.g. a user maintained set of dirty entities or the
+    /// entities in a cell of a spatial grid. Other entities get skipped before any of their components get fetched.
+    #[inline]
+    pub fn iter_masked<'a>(&'a self, mask: &'a AtomicBitSet) -> QueryIter<'a, 'a, Q, F> {
+        let mut iter = self.iter();
+        iter.mask = Some(mask);
+        iter
+    }
+
+    /// Calls `f` for every matching item. This loops over the archetypes and their rows directly
+    /// instead of going through the state machine of an iterator, which makes it the preferred way
+    /// to visit all items in hot code.
+    #[inline]
+    pub fn for_each(&self, f: impl FnMut(Q::Item<'_>)) {
+        // SAFETY: the query only reads
+        unsafe { self.state.for_each_unchecked(self.world, self.last_run, self.this_run, f) }
+    }
+
+    /// Visits all unique combinations of `K` distinct matching entities, e.g. all pairs for `K = 2`.
+    #[inline]
+    pub fn iter_combinations<const K: usize>(&self) -> QueryCombinationIter<'_, '_, Q, F, K> {
+        QueryCombinationIter::new(self.world, &self.state, self.last_run, self.this_run)
+    }
+
+    /// Visits the items of the listed entities which match the query, other entities get skipped.
+    #[inline]
+    pub fn iter_many<I: IntoIterator>(&self, entities: I) -> QueryManyIter<'_, '_, Q, F, I::IntoIter>
+        where I::Item: Borrow<EntityId> {
+        QueryManyIter::new(self.world, &self.state, entities.into_iter(), self.last_run, self.this_run)
+    }
+
+    #[inline]
+    pub fn single(&self) -> Result<Q::Item<'_>, QuerySingleError> {
+        single(self.iter())
+    }
+
+    /// The number of matching entities, this only sums up the lengths of the matched archetypes
+    /// unless some of the components are stored in sparse sets or the filter checks change ticks.
+    pub fn count(&self) -> usize {
+        match self.state.archetypal {
+            true => self.state.archetypes.iter().map(|archetype| self.world.archetypes.get(*archetype).unwrap().len()).sum(),
+            false => self.iter().count(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        match self.state.archetypal {
+            true => self.state.archetypes.iter().all(|archetype| self.world.archetypes.get(*archetype).unwrap().len() == 0),
+            false => self.iter().next().is_none(),
+        }
+    }
+
+}
+
+fn single<'w, Q: WorldQuery, F: WorldQuery>(mut iter: QueryIter<'w, '_, Q, F>) -> Result<Q::Item<'w>, QuerySingleError> {
+    let item = iter.next().ok_or(QuerySingleError::NoEntities(type_name::<Q>()))?;
+    match iter.next() {
+        Some(_) => Err(QuerySingleError::MultipleEntities(type_name::<Q>())),
+        None => Ok(item),
+    }
+}
+
+impl<'w, 's, Q: WorldQuery, F: WorldQuery> IntoIterator for Query<'w, 's, Q, F> {
+    type Item = Q::Item<'w>;
+    type IntoIter = QueryIter<'w, 's, Q, F>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        QueryIter::new(self.world, self.state, self.last_run, self.this_run)
+    }
+}
+
+impl<'a, 'w, 's, Q: WorldQuery, F: WorldQuery> IntoIterator for &'a mut Query<'w, 's, Q, F> {
+    type Item = Q::Item<'a>;
+    type IntoIter = QueryIter<'a, 'a, Q, F>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct QueryIter<'w, 's, Q: WorldQuery, F: WorldQuery> {
+    world: &'w World,
+    state: Cow<'s, QueryState<Q, F>>,
+    // index into the matched archetypes of the next archetype to visit
+    next_archetype: usize,
+    current: Option<ArchetypeFetch<'w, Q, F>>,
+    // only entities whose index is in the mask get visited
+    mask: Option<&'w AtomicBitSet>,
+    last_run: Tick,
+    this_run: Tick,
+    _marker: PhantomData<Q::Item<'w>>,
+}
+
+struct ArchetypeFetch<'w, Q: WorldQuery, F: WorldQuery> {
+    archetype: &'w Archetype,
+    query: Q::Fetch<'w>,
+    filter: F::Fetch<'w>,
+    row: usize,
+}
+
+impl<'w, 's, Q: WorldQuery, F: WorldQuery> QueryIter<'w, 's, Q, F> {
+
+    #[inline]
+    fn new(world: &'w World, state: Cow<'s, QueryState<Q, F>>, last_run: Tick, this_run: Tick) -> Self {
+        Self {
+            world,
+            state,
+            next_archetype: 0,
+            current: None,
+            mask: None,
+            last_run,
+            this_run,
+            _marker: PhantomData,
+        }
+    }
+
+    fn next_with_id(&mut self) -> Option<(EntityId, Q::Item<'w>)> {
+        loop {
+            if let Some(current) = &mut self.current {
+                while current.row < current.archetype.len() {
+                    let row = current.row;
+                    current.row += 1;
+                    let id = current.archetype.entities()[row];
+                    if self.mask.is_some_and(|mask| !mask.contains(id.index() as usize)) {
+                        continue;
+                    }
+                    // SAFETY: every row is visited only once, so no item gets handed out twice
+                    unsafe {
+                        if F::fetch(&current.filter, id, row).is_none() {
+                            continue;
+                        }
+                        if let Some(item) = Q::fetch(&current.query, id, row) {
+                            return Some((id, item));
+                        }
+                    }
+                }
+            }
+            let archetype = *self.state.archetypes.get(self.next_archetype)?;
+            self.next_archetype += 1;
+            let archetype = self.world.archetypes.get(archetype).unwrap();
+            self.current = Some(ArchetypeFetch {
+                archetype,
+                query: unsafe { Q::init_fetch(self.world, &self.state.query, archetype, self.last_run, self.this_run) },
+                filter: unsafe { F::init_fetch(self.world, &self.state.filter, archetype, self.last_run, self.this_run) },
+                row: 0,
+            });
+        }
+    }
+
+}
+
+impl<'w, 's, Q: WorldQuery, F: WorldQuery> Iterator for QueryIter<'w, 's, Q, F> {
+    type Item = Q::Item<'w>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_id().map(|(_, item)| item)
+    }
+}
+
+pub struct QueryCombinationIter<'w, 's, Q: WorldQuery, F: WorldQuery, const K: usize> {
+    world: &'w World,
+    state: &'s QueryState<Q, F>,
+    last_run: Tick,
+    this_run: Tick,
+    // all matching entities, combinations are made up of indices into this
+    entities: Vec<(EntityId, EntityLocation)>,
+    // `None` before the first combination
+    indices: Option<[usize; K]>,
+}
+
+impl<'w, 's, Q: WorldQuery, F: WorldQuery, const K: usize> QueryCombinationIter<'w, 's, Q, F, K> {
+
+    fn new(world: &'w World, state: &'s QueryState<Q, F>, last_run: Tick, this_run: Tick) -> Self {
+        let mut entities = vec![];
+        for archetype in state.archetypes.iter().map(|archetype| world.archetypes.get(*archetype).unwrap()) {
+            // SAFETY: the fetches only get used to test which entities match, which doesn't touch their components
+            let filter = unsafe { F::init_fetch(world, &state.filter, archetype, last_run, this_run) };
+            let query = unsafe { Q::init_fetch(world, &state.query, archetype, last_run, this_run) };
+            for (row, id) in archetype.entities().iter().enumerate() {
+                if unsafe { F::matches(&filter, *id, row) && Q::matches(&query, *id, row) } {
+                    entities.push((*id, EntityLocation {
+                        archetype: archetype.id(),
+                        row,
+                    }));
+                }
+            }
+        }
+        Self {
+            world,
+            state,
+            last_run,
+            this_run,
+            entities,
+            indices: None,
+        }
+    }
+
+    /// Moves on to the next combination, returns `false` once all of them were visited.
+    fn advance(&mut self) -> bool {
+        let len = self.entities.len();
+        if K == 0 || K > len {
+            return false;
+        }
+        let Some(indices) = &mut self.indices else {
+            self.indices = Some(std::array::from_fn(|idx| idx));
+            return true;
+        };
+        // find the last index which can still move forward and reset the ones after it right behind it
+        let Some(pos) = (0..K).rev().find(|pos| indices[*pos] < len - K + *pos) else {
+            return false;
+        };
+        indices[pos] += 1;
+        for next in pos + 1..K {
+            indices[next] = indices[next - 1] + 1;
+        }
+        true
+    }
+
+    /// # Safety
+    /// No items of the same entities may be alive if `Q` fetches mutably.
+    unsafe fn fetch<'a>(&self) -> [Q::Item<'a>; K] where 'w: 'a {
+        let indices = self.indices.unwrap();
+        std::array::from_fn(|idx| {
+            let (id, location) = self.entities[indices[idx]];
+            let archetype = self.world.archetypes.get(location.archetype).unwrap();
+            let fetch = Q::init_fetch(self.world, &self.state.query, archetype, self.last_run, self.this_run);
+            Q::fetch(&fetch, id, location.row).unwrap()
+        })
+    }
+
+    /// The items of a combination belong to distinct entities, so they can be mutated all at once.
+    pub fn fetch_next(&mut self) -> Option<[Q::Item<'_>; K]> {
+        if !self.advance() {
+            return None;
+        }
+        // SAFETY: the previous combination can't be alive anymore as it borrowed `self` mutably
+        Some(unsafe { self.fetch() })
+    }
+
+}
+
+impl<'w, 's, Q: ReadOnlyWorldQuery, F: WorldQuery, const K: usize> Iterator for QueryCombinationIter<'w, 's, Q, F, K> {
+    type Item = [Q::Item<'w>; K];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.advance() {
+            return None;
+        }
+        // SAFETY: read only items can't alias
+        Some(unsafe { self.fetch() })
+    }
+}
+
+pub struct QueryManyIter<'w, 's, Q: WorldQuery, F: WorldQuery, I: Iterator> {
+    world: &'w World,
+    state: &'s QueryState<Q, F>,
+    entities: I,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'w, 's, Q: WorldQuery, F: WorldQuery, I: Iterator> QueryManyIter<'w, 's, Q, F, I> where I::Item: Borrow<EntityId> {
+
+    #[inline]
+    fn new(world: &'w World, state: &'s QueryState<Q, F>, entities: I, last_run: Tick, this_run: Tick) -> Self {
+        Self {
+            world,
+            state,
+            entities,
+            last_run,
+            this_run,
+        }
+    }
+
+    /// # Safety
+    /// No items of the same entities may be alive if `Q` fetches mutably.
+    unsafe fn next_unchecked<'a>(&mut self) -> Option<Q::Item<'a>> where 'w: 'a {
+        self.entities.by_ref().find_map(|id| self.state.get_unchecked(self.world, *id.borrow(), self.last_run, self.this_run).ok())
+    }
+
+    pub fn fetch_next(&mut self) -> Option<Q::Item<'_>> {
+        // SAFETY: the previous item can't be alive anymore as it borrowed `self` mutably
+        unsafe { self.next_unchecked() }
+    }
+
+}
+
+impl<'w, 's, Q: ReadOnlyWorldQuery, F: WorldQuery, I: Iterator> Iterator for QueryManyIter<'w, 's, Q, F, I> where I::Item: Borrow<EntityId> {
+    type Item = Q::Item<'w>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        // SAFETY: read only items can't alias
+        unsafe { self.next_unchecked() }
+    }
+}
+
+struct QueryJoinRight<'a, Q: WorldQuery, F: WorldQuery> {
+    world: &'a World,
+    state: &'a QueryState<Q, F>,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'a, Q: WorldQuery, F: WorldQuery> QueryJoinRight<'a, Q, F> {
+
+    fn new<LQ: WorldQuery, LF: WorldQuery>(world: &World, left: &QueryState<LQ, LF>, right: &'a Query<'_, '_, Q, F>) -> Self {
+        assert_eq!(world.id(), right.world.id(), "joined queries have to belong to the same world");
+        let conflicts = left.access().conflicts(right.state.access());
+        assert!(conflicts.is_empty(), "the accesses of {} and {} conflict", type_name::<LQ>(), type_name::<Q>());
+        Self {
+            world: right.world,
+            state: &right.state,
+            last_run: right.last_run,
+            this_run: right.this_run,
+        }
+    }
+
+    #[inline]
+    fn get(&self, id: EntityId) -> Option<Q::Item<'a>> {
+        // SAFETY: the right query is borrowed mutably, its accesses don't conflict with the left one
+        // and every entity gets visited only once by the left query
+        unsafe { self.state.get_unchecked(self.world, id, self.last_run, self.this_run).ok() }
+    }
+
+}
+
+pub struct QueryJoinIter<'a, Q: WorldQuery, F: WorldQuery, Q2: WorldQuery, F2: WorldQuery> {
+    left: QueryIter<'a, 'a, Q, F>,
+    right: QueryJoinRight<'a, Q2, F2>,
+}
+
+impl<'a, Q: WorldQuery, F: WorldQuery, Q2: WorldQuery, F2: WorldQuery> Iterator for QueryJoinIter<'a, Q, F, Q2, F2> {
+    type Item = (Q::Item<'a>, Q2::Item<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (id, item) = self.left.next_with_id()?;
+            if let Some(other) = self.right.get(id) {
+                return Some((item, other));
+            }
+        }
+    }
+}
+
+pub struct QueryLeftJoinIter<'a, Q: WorldQuery, F: WorldQuery, Q2: WorldQuery, F2: WorldQuery> {
+    left: QueryIter<'a, 'a, Q, F>,
+    right: QueryJoinRight<'a, Q2, F2>,
+}
+
+impl<'a, Q: WorldQuery, F: WorldQuery, Q2: WorldQuery, F2: WorldQuery> Iterator for QueryLeftJoinIter<'a, Q, F, Q2, F2> {
+    type Item = (Q::Item<'a>, Option<Q2::Item<'a>>);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, item) = self.left.next_with_id()?;
+        Some((item, self.right.get(id)))
+    }
+}
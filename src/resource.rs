@@ -0,0 +1,225 @@
+use std::any::Any;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use std::thread::{self, ThreadId};
+use crate::component::ComponentId;
+use crate::tick::{ComponentTicks, Tick};
+
+/// A typed handle to the resource of type `R`, accessing resources through it skips looking up their type.
+/// Handles are only meaningful within the world which created them, see `World::resource_id`.
+pub struct ResourceId<R> {
+    id: ComponentId,
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<R> ResourceId<R> {
+
+    #[inline(always)]
+    pub(crate) fn new(id: ComponentId) -> Self {
+        Self {
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The id of the resource type within `World::resource_types`, resource ids are separate from component ids.
+    #[inline(always)]
+    pub fn id(self) -> ComponentId {
+        self.id
+    }
+
+}
+
+impl<R> Copy for ResourceId<R> {}
+
+impl<R> Clone for ResourceId<R> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> PartialEq for ResourceId<R> {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<R> Eq for ResourceId<R> {}
+
+impl<R> Hash for ResourceId<R> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<R> fmt::Debug for ResourceId<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ResourceId").field(&self.id).finish()
+    }
+}
+
+type ResourceValue = Box<dyn Any + Send + Sync>;
+
+type NonSendValue = Box<dyn Any>;
+
+/// The resources of a world together with their ticks, indexed by the id their type has as a component.
+/// Systems access them through a shared world, the accesses of the systems make sure this doesn't alias.
+pub(crate) struct Resources<V = ResourceValue> {
+    values: Vec<Option<UnsafeCell<(V, ComponentTicks)>>>,
+}
+
+impl<V> Default for Resources<V> {
+    fn default() -> Self {
+        Self {
+            values: vec![],
+        }
+    }
+}
+
+unsafe impl Sync for Resources {}
+
+impl<V> Resources<V> {
+
+    #[inline]
+    pub fn get(&self, id: ComponentId) -> Option<(&V, &ComponentTicks)> {
+        // SAFETY: mutable accesses through a shared reference can't happen at the same time, see `get_unchecked_mut`
+        let (value, ticks) = unsafe { &*self.values.get(id.index())?.as_ref()?.get() };
+        Some((value, ticks))
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, id: ComponentId) -> Option<(&mut V, &mut ComponentTicks)> {
+        let (value, ticks) = self.values.get_mut(id.index())?.as_mut()?.get_mut();
+        Some((value, ticks))
+    }
+
+    /// # Safety
+    /// The resource may not be accessed otherwise while the returned references are alive.
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    pub unsafe fn get_unchecked_mut(&self, id: ComponentId) -> Option<(&mut V, &mut ComponentTicks)> {
+        let (value, ticks) = &mut *self.values.get(id.index())?.as_ref()?.get();
+        Some((value, ticks))
+    }
+
+    /// Returns the previous value, replacing a value counts as changing it.
+    pub fn insert(&mut self, id: ComponentId, value: V, tick: Tick) -> Option<V> {
+        if self.values.len() <= id.index() {
+            self.values.resize_with(id.index() + 1, || None);
+        }
+        match &mut self.values[id.index()] {
+            Some(stored) => {
+                let (stored, ticks) = stored.get_mut();
+                ticks.changed = tick;
+                Some(mem::replace(stored, value))
+            }
+            empty => {
+                *empty = Some(UnsafeCell::new((value, ComponentTicks::new(tick))));
+                None
+            }
+        }
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: ComponentId) -> Option<V> {
+        self.take(id).map(|(value, _)| value)
+    }
+
+    #[inline]
+    pub fn take(&mut self, id: ComponentId) -> Option<(V, ComponentTicks)> {
+        self.values.get_mut(id.index())?.take().map(UnsafeCell::into_inner)
+    }
+
+    /// Puts back what `take` returned, replacing the current value if there is one.
+    pub fn restore(&mut self, id: ComponentId, value: V, ticks: ComponentTicks) {
+        if self.values.len() <= id.index() {
+            self.values.resize_with(id.index() + 1, || None);
+        }
+        self.values[id.index()] = Some(UnsafeCell::new((value, ticks)));
+    }
+
+    /// Drops all values, the slots are kept around for reuse.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.values.iter_mut().for_each(|slot| *slot = None);
+    }
+
+    pub fn check_ticks(&mut self, tick: Tick) {
+        self.values.iter_mut().flatten().for_each(|stored| stored.get_mut().1.check_ticks(tick));
+    }
+
+}
+
+/// Resources which aren't `Send`, they can only be accessed on the thread which created the world.
+/// Worlds aren't `Send`, so this only stops systems which share the world from accessing them on other threads.
+pub(crate) struct NonSendResources {
+    thread: ThreadId,
+    values: Resources<NonSendValue>,
+}
+
+impl Default for NonSendResources {
+    fn default() -> Self {
+        Self {
+            thread: thread::current().id(),
+            values: Resources::default(),
+        }
+    }
+}
+
+impl NonSendResources {
+
+    #[inline]
+    fn validate(&self) {
+        assert_eq!(thread::current().id(), self.thread, "non-send resources can only be accessed on the thread which created the world");
+    }
+
+    #[inline]
+    pub fn get(&self, id: ComponentId) -> Option<(&NonSendValue, &ComponentTicks)> {
+        self.validate();
+        self.values.get(id)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, id: ComponentId) -> Option<(&mut NonSendValue, &mut ComponentTicks)> {
+        self.validate();
+        self.values.get_mut(id)
+    }
+
+    /// # Safety
+    /// See `Resources::get_unchecked_mut`.
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    pub unsafe fn get_unchecked_mut(&self, id: ComponentId) -> Option<(&mut NonSendValue, &mut ComponentTicks)> {
+        self.validate();
+        self.values.get_unchecked_mut(id)
+    }
+
+    #[inline]
+    pub fn insert(&mut self, id: ComponentId, value: NonSendValue, tick: Tick) -> Option<NonSendValue> {
+        self.validate();
+        self.values.insert(id, value, tick)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: ComponentId) -> Option<NonSendValue> {
+        self.validate();
+        self.values.remove(id)
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Only touches the ticks, so this doesn't have to happen on the thread which created the world.
+    #[inline]
+    pub fn check_ticks(&mut self, tick: Tick) {
+        self.values.check_ticks(tick);
+    }
+
+}
@@ -0,0 +1,727 @@
+use std::any::TypeId;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use crate::component::{ComponentId, Components};
+use crate::condition::{BoxedCondition, Condition, ConditionalSystem};
+use crate::error::{self, ErrorHandler, SystemError, SystemOutput, SystemResult};
+use crate::executor::{self, ExecutorKind, SystemGraph};
+use crate::graph::{EdgeKind, ScheduleGraph, SystemEdge, SystemNode};
+use crate::system::{AnySystem, BoxedSystem, IntoSystem, NonSendSystem, ResultSystem};
+use crate::tick::{Tick, CHECK_TICK_THRESHOLD};
+use crate::World;
+
+/// A system together with the systems it has to run before or after and the conditions under which it runs.
+pub struct SystemConfig {
+    system: BoxedSystem<(), SystemResult>,
+    constraints: Constraints,
+    conditions: Vec<BoxedCondition>,
+    once: bool,
+    send: bool,
+    // the name of the system if it isn't the default one
+    name: Option<Cow<'static, str>>,
+    metadata: HashMap<String, String>,
+}
+
+// systems and sets get identified by their type, so all instances of a function system are affected by constraints on it
+struct Constraints {
+    // the type of the system followed by the sets it is in
+    labels: Vec<TypeId>,
+    before: Vec<TypeId>,
+    after: Vec<TypeId>,
+}
+
+/// A named group of systems which can be ordered as a unit, e.g. `schedule.configure_set(PhysicsSet.before(RenderSet))`.
+/// Sets are identified by their type.
+pub trait SystemSet: 'static {}
+
+/// Something systems can be ordered relative to, either a `SystemSet` or a system, which stands for all its instances.
+pub trait SystemLabel<Marker> {
+
+    fn label(&self) -> TypeId;
+
+}
+
+impl<S: SystemSet> SystemLabel<()> for S {
+    #[inline]
+    fn label(&self) -> TypeId {
+        TypeId::of::<S>()
+    }
+}
+
+impl<Marker, S: IntoSystem<Marker>> SystemLabel<(Marker,)> for S {
+    #[inline]
+    fn label(&self) -> TypeId {
+        TypeId::of::<S::System>()
+    }
+}
+
+/// Conversion into a `SystemConfig`, implemented for everything which can be turned into a system,
+/// e.g. `apply_damage.after(collision)`.
+pub trait IntoSystemConfig<Marker>: Sized {
+
+    fn into_config(self) -> SystemConfig;
+
+    /// Runs the system before all systems `other` stands for in the same schedule, does nothing if there are none.
+    fn before<M>(self, other: impl SystemLabel<M>) -> SystemConfig {
+        let mut config = self.into_config();
+        config.constraints.before.push(other.label());
+        config
+    }
+
+    /// Runs the system after all systems `other` stands for in the same schedule, does nothing if there are none.
+    fn after<M>(self, other: impl SystemLabel<M>) -> SystemConfig {
+        let mut config = self.into_config();
+        config.constraints.after.push(other.label());
+        config
+    }
+
+    fn in_set(self, set: impl SystemSet) -> SystemConfig {
+        let mut config = self.into_config();
+        config.constraints.labels.push(set.label());
+        config
+    }
+
+    /// Only runs the system if `condition` returns true, the condition gets evaluated right before the system would run.
+    fn run_if<M>(self, condition: impl Condition<M>) -> SystemConfig {
+        let mut config = self.into_config();
+        config.conditions.push(condition.into_condition());
+        config
+    }
+
+    /// Only runs the system the first time its conditions hold after the schedule got activated, e.g. for initialization logic.
+    /// See `Schedule::reactivate`.
+    fn run_once(self) -> SystemConfig {
+        let mut config = self.into_config();
+        config.once = true;
+        config
+    }
+
+    /// Always runs the system on the thread which runs the schedule, e.g. for systems accessing thread-local data
+    /// like window handles or GPU contexts. Systems with `NonSend` or `NonSendMut` parameters always run there.
+    fn non_send(self) -> SystemConfig {
+        let mut config = self.into_config();
+        config.send = false;
+        config
+    }
+
+    /// Names the system in the registry of the schedule and wherever its name gets reported, by default this is its type name.
+    fn named(self, name: impl Into<Cow<'static, str>>) -> SystemConfig {
+        let mut config = self.into_config();
+        config.name = Some(name.into());
+        config
+    }
+
+    /// Attaches a value to the system's entry in the registry of the schedule, see `Schedule::system`.
+    fn with_metadata(self, key: impl Into<String>, value: impl Into<String>) -> SystemConfig {
+        let mut config = self.into_config();
+        config.metadata.insert(key.into(), value.into());
+        config
+    }
+
+}
+
+impl<Marker, S: IntoSystem<Marker>> IntoSystemConfig<Marker> for S where S::System: AnySystem<In = (), Out: SystemOutput> {
+    fn into_config(self) -> SystemConfig {
+        SystemConfig {
+            constraints: Constraints {
+                labels: vec![self.label()],
+                before: vec![],
+                after: vec![],
+            },
+            system: Box::new(ResultSystem(self.into_system())),
+            conditions: vec![],
+            once: false,
+            send: true,
+            name: None,
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+impl IntoSystemConfig<SystemConfig> for SystemConfig {
+    #[inline(always)]
+    fn into_config(self) -> SystemConfig {
+        self
+    }
+}
+
+/// The ordering of a `SystemSet` relative to other sets and systems.
+pub struct SetConfig {
+    set: TypeId,
+    before: Vec<TypeId>,
+    after: Vec<TypeId>,
+}
+
+/// Conversion into a `SetConfig`, implemented for all `SystemSet`s.
+pub trait IntoSetConfig: Sized {
+
+    fn into_config(self) -> SetConfig;
+
+    /// Runs the systems in the set before all systems `other` stands for.
+    fn before<M>(self, other: impl SystemLabel<M>) -> SetConfig {
+        let mut config = self.into_config();
+        config.before.push(other.label());
+        config
+    }
+
+    /// Runs the systems in the set after all systems `other` stands for.
+    fn after<M>(self, other: impl SystemLabel<M>) -> SetConfig {
+        let mut config = self.into_config();
+        config.after.push(other.label());
+        config
+    }
+
+}
+
+impl<S: SystemSet> IntoSetConfig for S {
+    fn into_config(self) -> SetConfig {
+        SetConfig {
+            set: self.label(),
+            before: vec![],
+            after: vec![],
+        }
+    }
+}
+
+impl IntoSetConfig for SetConfig {
+    #[inline(always)]
+    fn into_config(self) -> SetConfig {
+        self
+    }
+}
+
+/// Two systems which access the same components, at least one of them mutably, without being ordered explicitly.
+/// They still run in the order they were added, but that order is easily changed by accident.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Ambiguity {
+    pub first: Cow<'static, str>,
+    pub second: Cow<'static, str>,
+    /// The names of the components and resources the systems conflict on.
+    pub components: Vec<String>,
+}
+
+impl fmt::Display for Ambiguity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} and {} conflict on {}", self.first, self.second, self.components.join(", "))
+    }
+}
+
+/// How long the systems of a schedule took during its last run, see `Schedule::set_timing`.
+#[derive(Clone, Debug, Default)]
+pub struct RunReport {
+    /// The systems which ran in their resolved order.
+    pub systems: Vec<SystemTiming>,
+    /// The duration of the whole run, including sync points.
+    pub total: Duration,
+}
+
+impl RunReport {
+
+    pub fn slowest(&self) -> Option<&SystemTiming> {
+        self.systems.iter().max_by_key(|timing| timing.duration)
+    }
+
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for timing in &self.systems {
+            writeln!(f, "{}: {:?}", timing.name, timing.duration)?;
+        }
+        write!(f, "total: {:?}", self.total)
+    }
+}
+
+/// The wall-clock duration of a single system, excluding the application of its deferred changes in multi-threaded schedules.
+#[derive(Clone, Debug)]
+pub struct SystemTiming {
+    pub name: Cow<'static, str>,
+    pub duration: Duration,
+}
+
+/// Names a `Schedule` stored in a world, e.g. `world.run_schedule(Update)`. Labels are identified by their type.
+pub trait ScheduleLabel: 'static {}
+
+// where a schedule in stepping mode is and what the next run should do
+struct Stepping {
+    // the next system to run
+    cursor: usize,
+    action: StepAction,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum StepAction {
+    Wait,
+    Step,
+    Continue,
+}
+
+/// The entry of a system in the registry of a `Schedule`, e.g. to toggle systems from a dev console.
+pub struct SystemInfo {
+    name: Cow<'static, str>,
+    enabled: Arc<AtomicBool>,
+    metadata: HashMap<String, String>,
+}
+
+impl SystemInfo {
+
+    #[inline(always)]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Disabled systems get skipped as if one of their conditions didn't hold.
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    #[inline(always)]
+    pub fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+        &mut self.metadata
+    }
+
+}
+
+/// A list of systems which run on a world in the order they were added unless they are ordered explicitly,
+/// systems which don't conflict may run at the same time depending on the `ExecutorKind`.
+/// Systems may return a `SystemResult`, errors get passed to the error handler of the schedule.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<BoxedSystem<(), SystemResult>>,
+    constraints: Vec<Constraints>,
+    // pairs of labels whose systems have to run before the ones of the other label
+    set_orders: Vec<(TypeId, TypeId)>,
+    executor: ExecutorKind,
+    // gets rebuilt after systems got added
+    graph: Option<SystemGraph>,
+    // the explicit ordering constraints between the sorted systems as `(earlier, later)` pairs
+    dependencies: Vec<(usize, usize)>,
+    stepping: Option<Stepping>,
+    // panics if unset
+    error_handler: Option<ErrorHandler>,
+    // gets incremented to make systems which run once run again
+    activation: Arc<AtomicUsize>,
+    // in the order the systems were added
+    registry: Vec<SystemInfo>,
+    // whether sync points get added before systems ordered after systems with deferred changes
+    manual_sync: bool,
+    timing: bool,
+    report: Option<RunReport>,
+    update_events: bool,
+    // the tick the ticks of the world were last checked at
+    last_check_tick: Tick,
+}
+
+impl Schedule {
+
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system<Marker>(&mut self, system: impl IntoSystemConfig<Marker>) -> &mut Self {
+        let mut config = system.into_config();
+        let info = SystemInfo {
+            name: config.name.take().unwrap_or_else(|| config.system.name()),
+            enabled: Arc::new(AtomicBool::new(true)),
+            metadata: mem::take(&mut config.metadata),
+        };
+        // evaluated first, so disabled systems don't count as ran once
+        let enabled = info.enabled.clone();
+        config.conditions.insert(0, (move || enabled.load(Ordering::Relaxed)).into_condition());
+        if config.once {
+            // evaluated last, so it only counts as ran once the other conditions held
+            let activation = self.activation.clone();
+            let mut ran = None;
+            config.conditions.push((move || {
+                let current = activation.load(Ordering::Relaxed);
+                ran.replace(current) != Some(current)
+            }).into_condition());
+        }
+        if !config.send {
+            config.system = Box::new(NonSendSystem(config.system));
+        }
+        self.systems.push(Box::new(ConditionalSystem::new(info.name.clone(), config.system, config.conditions)));
+        self.constraints.push(config.constraints);
+        self.registry.push(info);
+        self.graph = None;
+        self
+    }
+
+    pub fn configure_set(&mut self, set: impl IntoSetConfig) -> &mut Self {
+        let config = set.into_config();
+        self.set_orders.extend(config.before.into_iter().map(|label| (config.set, label)));
+        self.set_orders.extend(config.after.into_iter().map(|label| (label, config.set)));
+        self.graph = None;
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn executor_kind(&self) -> ExecutorKind {
+        self.executor
+    }
+
+    pub fn set_executor_kind(&mut self, executor: ExecutorKind) -> &mut Self {
+        self.executor = executor;
+        self
+    }
+
+    #[inline(always)]
+    pub fn auto_sync(&self) -> bool {
+        !self.manual_sync
+    }
+
+    /// Whether the multi-threaded executor applies the deferred changes of systems before the systems ordered after them run,
+    /// instead of only at exclusive systems like `apply_deferred` and at the end. Enabled by default.
+    pub fn set_auto_sync(&mut self, auto_sync: bool) -> &mut Self {
+        self.manual_sync = !auto_sync;
+        self.graph = None;
+        self
+    }
+
+    /// Records how long each system takes while running the schedule, see `last_run_report`.
+    pub fn set_timing(&mut self, timing: bool) -> &mut Self {
+        self.timing = timing;
+        if !timing {
+            self.report = None;
+        }
+        self
+    }
+
+    /// Whether `World::update_events` gets called at the start of every run, usually enabled for the schedule which runs once per frame.
+    /// Disabled by default.
+    #[inline]
+    pub fn set_update_events(&mut self, update_events: bool) -> &mut Self {
+        self.update_events = update_events;
+        self
+    }
+
+    /// The durations of the systems during the last run, only available if timing was enabled during that run.
+    #[inline]
+    pub fn last_run_report(&self) -> Option<&RunReport> {
+        self.report.as_ref()
+    }
+
+    /// Gets called with the name of the system and its error whenever a system fails, possibly from multiple threads at once.
+    /// By default this is `panic_on_error`.
+    pub fn set_error_handler(&mut self, handler: impl Fn(&str, SystemError) + Send + Sync + 'static) -> &mut Self {
+        self.error_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// The entries of all systems in the order they were added.
+    #[inline]
+    pub fn systems(&self) -> impl Iterator<Item = &SystemInfo> {
+        self.registry.iter()
+    }
+
+    /// The entry of the first system added with `name`.
+    pub fn system(&self, name: &str) -> Option<&SystemInfo> {
+        self.registry.iter().find(|info| info.name == name)
+    }
+
+    pub fn system_mut(&mut self, name: &str) -> Option<&mut SystemInfo> {
+        self.registry.iter_mut().find(|info| info.name == name)
+    }
+
+    /// Makes systems which run once run again, on the next run their conditions hold.
+    pub fn reactivate(&mut self) -> &mut Self {
+        self.activation.fetch_add(1, Ordering::Relaxed);
+        self
+    }
+
+    /// In stepping mode, running the schedule only runs the systems `step` and `continue_frame` asked for, one after another
+    /// in the resolved order. Systems which don't get run in one call get run by the next calls, so a frame can span several.
+    pub fn set_stepping(&mut self, stepping: bool) -> &mut Self {
+        self.stepping = stepping.then_some(Stepping {
+            cursor: 0,
+            action: StepAction::Wait,
+        });
+        self
+    }
+
+    #[inline]
+    pub fn is_stepping(&self) -> bool {
+        self.stepping.is_some()
+    }
+
+    /// Makes the next run of the schedule run the next system, does nothing outside of stepping mode.
+    pub fn step(&mut self) -> &mut Self {
+        if let Some(stepping) = &mut self.stepping {
+            stepping.action = StepAction::Step;
+        }
+        self
+    }
+
+    /// Makes the next run of the schedule run all systems which didn't run in the current frame yet,
+    /// does nothing outside of stepping mode.
+    pub fn continue_frame(&mut self) -> &mut Self {
+        if let Some(stepping) = &mut self.stepping {
+            stepping.action = StepAction::Continue;
+        }
+        self
+    }
+
+    /// The name of the system the next step runs, only available in stepping mode once the schedule got initialized.
+    pub fn next_system(&self) -> Option<Cow<'static, str>> {
+        let stepping = self.stepping.as_ref()?;
+        self.graph.as_ref()?;
+        self.systems.get(stepping.cursor).map(|system| system.name())
+    }
+
+    /// Initializes all systems which weren't initialized yet and resolves the order of the systems,
+    /// running the schedule does this as well. Panics if the ordering constraints form a cycle.
+    pub fn initialize(&mut self, world: &mut World) {
+        for system in &mut self.systems {
+            system.initialize(world);
+        }
+        if self.graph.is_none() {
+            self.dependencies = self.sort();
+            self.graph = Some(SystemGraph::new(&self.systems, &self.dependencies, !self.manual_sync));
+            // the order might have changed, so the current frame starts over
+            if let Some(stepping) = &mut self.stepping {
+                stepping.cursor = 0;
+            }
+        }
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        self.initialize(world);
+        let tick = world.change_tick();
+        if tick.get().wrapping_sub(self.last_check_tick.get()) >= CHECK_TICK_THRESHOLD {
+            world.check_change_ticks();
+            self.check_change_ticks(tick);
+        }
+        let handler = self.error_handler.as_deref().unwrap_or(&error::panic_on_error);
+        let mut timings = self.timing.then(|| vec![None; self.systems.len()]);
+        let started = Instant::now();
+        if let Some(stepping) = &mut self.stepping {
+            let end = match stepping.action {
+                StepAction::Wait => return,
+                StepAction::Step => (stepping.cursor + 1).min(self.systems.len()),
+                StepAction::Continue => self.systems.len(),
+            };
+            stepping.action = StepAction::Wait;
+            let start = stepping.cursor;
+            if start == 0 && self.update_events {
+                world.update_events();
+            }
+            stepping.cursor = if end == self.systems.len() { 0 } else { end };
+            let timings = timings.as_deref_mut().map(|timings| &mut timings[start..end]);
+            executor::run_single_threaded(&mut self.systems[start..end], world, handler, timings);
+        } else {
+            if self.update_events {
+                world.update_events();
+            }
+            match self.executor {
+                ExecutorKind::SingleThreaded => executor::run_single_threaded(&mut self.systems, world, handler, timings.as_deref_mut()),
+                ExecutorKind::MultiThreaded => {
+                    executor::run_multi_threaded(&mut self.systems, self.graph.as_ref().unwrap(), world, handler, timings.as_deref_mut());
+                }
+            }
+        }
+        if let Some(timings) = timings {
+            self.report = Some(RunReport {
+                systems: self.systems.iter().zip(timings)
+                    .filter_map(|(system, duration)| Some(SystemTiming {
+                        name: system.name(),
+                        duration: duration?,
+                    }))
+                    .collect(),
+                total: started.elapsed(),
+            });
+        }
+    }
+
+    /// Clamps the ticks the systems of the schedule remember, see `World::check_change_ticks`.
+    /// `run` takes care of this, together with checking the ticks of the world.
+    pub fn check_change_ticks(&mut self, tick: Tick) {
+        self.systems.iter_mut().for_each(|system| system.check_change_tick(tick));
+        self.last_check_tick = tick;
+    }
+
+    /// The systems in their resolved order together with their accesses and what they wait for, this initializes the schedule first.
+    pub fn graph(&mut self, world: &mut World) -> ScheduleGraph {
+        self.initialize(world);
+        let name = |id| name_of(world.components(), id);
+        let resource_name = |id| name_of(world.resource_types(), id);
+        let nodes = self.systems.iter().map(|system| {
+            let access = system.access();
+            SystemNode {
+                name: system.name(),
+                reads: access.reads().iter().filter(|id| !access.has_write(*id)).map(name)
+                    .chain(access.resource_reads().iter().filter(|id| !access.has_resource_write(*id)).map(resource_name))
+                    .collect(),
+                writes: access.writes().iter().map(name).chain(access.resource_writes().iter().map(resource_name)).collect(),
+                reads_all: access.reads_all(),
+                exclusive: system.is_exclusive(),
+            }
+        }).collect();
+        let mut edges = vec![];
+        for (later, system) in self.systems.iter().enumerate() {
+            for (earlier, other) in self.systems[..later].iter().enumerate() {
+                let kind = if self.dependencies.contains(&(earlier, later)) {
+                    EdgeKind::Ordering
+                } else if !system.access().is_compatible(other.access()) {
+                    EdgeKind::Conflict
+                } else {
+                    continue;
+                };
+                edges.push(SystemEdge {
+                    from: earlier,
+                    to: later,
+                    kind,
+                });
+            }
+        }
+        ScheduleGraph {
+            nodes,
+            edges,
+        }
+    }
+
+    /// Finds all pairs of systems which conflict without being ordered through constraints, this initializes the schedule first.
+    pub fn ambiguities(&mut self, world: &mut World) -> Vec<Ambiguity> {
+        self.initialize(world);
+        // the systems are sorted, so following the dependencies from a system only ever reaches later ones
+        let len = self.systems.len();
+        let mut ordered = vec![vec![false; len]; len];
+        for system in (0..len).rev() {
+            for (_, dependent) in self.dependencies.iter().filter(|(earlier, _)| *earlier == system) {
+                let (before, after) = ordered.split_at_mut(*dependent);
+                before[system][*dependent] = true;
+                before[system].iter_mut().zip(&after[0]).for_each(|(ordered, reachable)| *ordered |= reachable);
+            }
+        }
+        let mut ambiguities = vec![];
+        for (later, system) in self.systems.iter().enumerate() {
+            for (earlier, other) in self.systems[..later].iter().enumerate() {
+                let conflicts = other.access().conflicts(system.access());
+                let resource_conflicts = other.access().resource_conflicts(system.access());
+                if (conflicts.is_empty() && resource_conflicts.is_empty()) || ordered[earlier][later] {
+                    continue;
+                }
+                ambiguities.push(Ambiguity {
+                    first: other.name(),
+                    second: system.name(),
+                    components: conflicts.into_iter().map(|id| name_of(world.components(), id))
+                        .chain(resource_conflicts.into_iter().map(|id| name_of(world.resource_types(), id)))
+                        .collect(),
+                });
+            }
+        }
+        ambiguities
+    }
+
+    /// Brings the systems into an order which satisfies the constraints while keeping systems which aren't
+    /// ordered explicitly in the order they were added, returns the explicit dependencies as `(earlier, later)` pairs.
+    fn sort(&mut self) -> Vec<(usize, usize)> {
+        let len = self.systems.len();
+        let mut dependents = vec![vec![]; len];
+        for (system, constraints) in self.constraints.iter().enumerate() {
+            for (other, other_constraints) in self.constraints.iter().enumerate() {
+                let before = constraints.before.iter().any(|label| other_constraints.labels.contains(label))
+                    || other_constraints.after.iter().any(|label| constraints.labels.contains(label))
+                    || self.set_orders.iter().any(|(first, then)| constraints.labels.contains(first) && other_constraints.labels.contains(then));
+                if before {
+                    dependents[system].push(other);
+                }
+            }
+        }
+        let mut remaining = vec![0; len];
+        for dependent in dependents.iter().flatten() {
+            remaining[*dependent] += 1;
+        }
+
+        // always pick the earliest added system which is ready
+        let mut order = Vec::with_capacity(len);
+        let mut ready = (0..len).filter(|system| remaining[*system] == 0).collect::<Vec<_>>();
+        while let Some(idx) = ready.iter().enumerate().min_by_key(|(_, system)| **system).map(|(idx, _)| idx) {
+            let system = ready.swap_remove(idx);
+            order.push(system);
+            for dependent in &dependents[system] {
+                remaining[*dependent] -= 1;
+                if remaining[*dependent] == 0 {
+                    ready.push(*dependent);
+                }
+            }
+        }
+        if order.len() != len {
+            panic!("the ordering constraints of the systems form a cycle: {}", self.find_cycle(&dependents, &remaining));
+        }
+
+        let mut positions = vec![0; len];
+        for (position, system) in order.iter().enumerate() {
+            positions[*system] = position;
+        }
+        let mut systems = self.systems.drain(..).map(Some).collect::<Vec<_>>();
+        let mut constraints = self.constraints.drain(..).map(Some).collect::<Vec<_>>();
+        for system in &order {
+            self.systems.push(systems[*system].take().unwrap());
+            self.constraints.push(constraints[*system].take().unwrap());
+        }
+        dependents.iter().enumerate()
+            .flat_map(|(system, dependents)| dependents.iter().map(move |dependent| (system, *dependent)))
+            .map(|(system, dependent)| (positions[system], positions[dependent]))
+            .collect()
+    }
+
+    // every system which couldn't be sorted waits for another one which couldn't be sorted,
+    // so following these dependencies backwards has to run into a cycle eventually
+    fn find_cycle(&self, dependents: &[Vec<usize>], remaining: &[usize]) -> String {
+        let mut chain = vec![remaining.iter().position(|remaining| *remaining != 0).unwrap()];
+        let start = loop {
+            let current = *chain.last().unwrap();
+            let prev = (0..dependents.len()).find(|system| remaining[*system] != 0 && dependents[*system].contains(&current)).unwrap();
+            if let Some(start) = chain.iter().position(|system| *system == prev) {
+                break start;
+            }
+            chain.push(prev);
+        };
+        // start with the earliest added system of the cycle
+        let mut cycle = chain[start..].iter().rev().copied().collect::<Vec<_>>();
+        let first = cycle.iter().enumerate().min_by_key(|(_, system)| **system).unwrap().0;
+        cycle.rotate_left(first);
+        cycle.iter().chain(cycle.first())
+            .map(|system| self.systems[*system].name())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+}
+
+/// An exclusive system which does nothing, it only serves as a sync point for the deferred changes of the systems
+/// before it, e.g. `apply_deferred.after(spawn_enemies).before(target_enemies)`.
+#[inline(always)]
+pub fn apply_deferred(_world: &mut World) {}
+
+// the ids of dynamic components and resources which were never registered don't have a name
+fn name_of(types: &Components, id: ComponentId) -> String {
+    types.get_info(id).map_or_else(|| format!("{:?}", id), |info| info.name().to_owned())
+}
@@ -0,0 +1,198 @@
+use std::any::{Any, TypeId};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use crate::value::ComponentValue;
+
+/// A handle to a value of type `T` which is stored only once in the world, entities hold the
+/// handle as a regular component. All entities holding the same handle form a batch.
+pub struct Shared<T> {
+    index: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Shared<T> {
+
+    #[inline(always)]
+    fn new(index: u32) -> Self {
+        Self {
+            index,
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+}
+
+impl<T> Copy for Shared<T> {}
+
+impl<T> Clone for Shared<T> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> PartialEq for Shared<T> {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Shared<T> {}
+
+impl<T> Hash for Shared<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Shared").field(&self.index).finish()
+    }
+}
+
+/// The values of one shared type, slots of removed values are never reused so stale handles stay invalid.
+struct SharedStore<T> {
+    // each value together with the hash it is indexed by
+    values: Vec<Option<(u64, T)>>,
+    // the indices of the values by their hash
+    index: HashMap<u64, Vec<u32>>,
+    // values which might have been modified through `get_mut` since they were indexed
+    dirty: Vec<u32>,
+}
+
+impl<T: Hash> SharedStore<T> {
+
+    fn reindex(&mut self) {
+        for idx in mem::take(&mut self.dirty) {
+            let Some((indexed, value)) = &mut self.values[idx as usize] else {
+                continue;
+            };
+            let hash = hash(value);
+            if hash != *indexed {
+                unindex(&mut self.index, *indexed, idx);
+                self.index.entry(hash).or_default().push(idx);
+                *indexed = hash;
+            }
+        }
+    }
+
+}
+
+// rewrites a `Shared<T>` component of the first world to the handle of an equal value in the second one
+type RemapFn = fn(&SharedValues, &mut SharedValues, &mut ComponentValue);
+
+#[derive(Default)]
+pub(crate) struct SharedValues {
+    stores: HashMap<TypeId, Box<dyn Any>>,
+    // indexed by the type id of `Shared<T>`
+    remaps: HashMap<TypeId, RemapFn>,
+}
+
+impl SharedValues {
+
+    fn store<T: 'static>(&self) -> Option<&SharedStore<T>> {
+        self.stores.get(&TypeId::of::<T>()).map(|store| store.downcast_ref::<SharedStore<T>>().unwrap())
+    }
+
+    fn store_mut<T: 'static>(&mut self) -> &mut SharedStore<T> {
+        self.stores.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(SharedStore::<T> {
+                values: vec![],
+                index: HashMap::new(),
+                dirty: vec![],
+            }))
+            .downcast_mut::<SharedStore<T>>()
+            .unwrap()
+    }
+
+    #[inline]
+    pub fn get<T: 'static>(&self, handle: Shared<T>) -> Option<&T> {
+        self.store::<T>()?.values.get(handle.index as usize)?.as_ref().map(|(_, value)| value)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, handle: Shared<T>) -> Option<&mut T> {
+        let store = self.store_mut::<T>();
+        let (_, value) = store.values.get_mut(handle.index as usize)?.as_mut()?;
+        store.dirty.push(handle.index);
+        Some(value)
+    }
+
+    /// Returns the handle of a stored value equal to `value`, other than `except`.
+    pub fn find<T: Hash + Eq + 'static>(&mut self, value: &T, except: Option<Shared<T>>) -> Option<Shared<T>> {
+        let store = self.store_mut::<T>();
+        store.reindex();
+        store.index.get(&hash(value))?.iter()
+            .copied()
+            .filter(|idx| except.is_none_or(|except| except.index != *idx))
+            .find(|idx| store.values[*idx as usize].as_ref().is_some_and(|(_, stored)| stored == value))
+            .map(Shared::new)
+    }
+
+    /// Returns the handle of a stored value equal to `value`, or stores `value` if there is none.
+    pub fn add<T: Hash + Eq + Clone + 'static>(&mut self, value: T) -> Shared<T> {
+        if let Some(handle) = self.find(&value, None) {
+            return handle;
+        }
+        self.remaps.entry(TypeId::of::<Shared<T>>()).or_insert(remap::<T>);
+        let store = self.store_mut::<T>();
+        let idx = u32::try_from(store.values.len()).ok().filter(|idx| *idx != INVALID).expect("too many shared values");
+        let hash = hash(&value);
+        store.values.push(Some((hash, value)));
+        store.index.entry(hash).or_default().push(idx);
+        Shared::new(idx)
+    }
+
+    pub fn remove<T: 'static>(&mut self, handle: Shared<T>) -> Option<T> {
+        let store = self.store_mut::<T>();
+        let (hash, value) = store.values.get_mut(handle.index as usize)?.take()?;
+        unindex(&mut store.index, hash, handle.index);
+        Some(value)
+    }
+
+    /// Makes `component` refer to an equal value in `target` if it is a `Shared<T>` handle, values which can't be resolved
+    /// stay unresolvable.
+    pub fn remap(&self, target: &mut SharedValues, type_id: TypeId, component: &mut ComponentValue) {
+        if let Some(remap) = self.remaps.get(&type_id) {
+            remap(self, target, component);
+        }
+    }
+
+}
+
+// the index of handles which never resolve
+const INVALID: u32 = u32::MAX;
+
+fn remap<T: Hash + Eq + Clone + 'static>(src: &SharedValues, dst: &mut SharedValues, component: &mut ComponentValue) {
+    let handle = component.as_any_mut().unwrap().downcast_mut::<Shared<T>>().unwrap();
+    *handle = match src.get(*handle) {
+        Some(value) => dst.add(value.clone()),
+        None => Shared::new(INVALID),
+    };
+}
+
+fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn unindex(index: &mut HashMap<u64, Vec<u32>>, hash: u64, idx: u32) {
+    let Entry::Occupied(mut entry) = index.entry(hash) else {
+        return;
+    };
+    entry.get_mut().retain(|other| *other != idx);
+    if entry.get().is_empty() {
+        entry.remove();
+    }
+}
@@ -0,0 +1,257 @@
+use std::cell::UnsafeCell;
+use std::ptr::NonNull;
+use crate::archetype::Column;
+use crate::atomic_bit_set::AtomicBitSet;
+use crate::blob_vec;
+use crate::tick::{ComponentTicks, Tick};
+use crate::value::{ComponentValue, ValueVTable};
+use crate::{ComponentId, ComponentInfo, EntityId};
+
+const INVALID: u32 = u32::MAX;
+
+/// Stores the values of a single component type, indexed by entity.
+pub(crate) enum SparseSet {
+    Values(ValueSet),
+    // the bit set is rather large, so it doesn't get stored inline
+    Tags(Box<TagSet>),
+}
+
+impl SparseSet {
+
+    fn new(info: &ComponentInfo) -> Self {
+        // tag sets don't remember which entities they belong to, but the entity of a singleton has to be found
+        if info.is_zero_sized() && !info.is_singleton() {
+            return Self::Tags(Box::new(TagSet {
+                present: AtomicBitSet::new(),
+                len: 0,
+                dangling: blob_vec::dangling(info.layout()),
+                vtable: info.vtable(),
+                ticks: vec![],
+            }));
+        }
+        Self::Values(ValueSet {
+            sparse: vec![],
+            dense: Column::new(info),
+            entities: vec![],
+        })
+    }
+
+    #[inline]
+    pub fn get_ptr(&self, id: EntityId) -> Option<*mut u8> {
+        match self {
+            Self::Values(set) => set.get_ptr(id),
+            // every well aligned pointer is a valid pointer to a zero sized value
+            Self::Tags(set) => set.contains(id).then_some(set.dangling.as_ptr()),
+        }
+    }
+
+    /// Tag sets only know the indices of their entities, so they return `None`.
+    #[inline]
+    pub fn entities(&self) -> Option<&[EntityId]> {
+        match self {
+            Self::Values(set) => Some(&set.entities),
+            Self::Tags(_) => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_ticks_ptr(&self, id: EntityId) -> Option<*mut ComponentTicks> {
+        match self {
+            Self::Values(set) => set.dense_index(id).map(|idx| set.dense.get_ticks_ptr(idx)),
+            Self::Tags(set) => set.contains(id).then(|| set.ticks[id.index() as usize].get()),
+        }
+    }
+
+    /// Returns the previous value stored for the entity.
+    pub fn insert(&mut self, id: EntityId, component: ComponentValue, tick: Tick) -> Option<ComponentValue> {
+        match self {
+            Self::Values(set) => set.insert(id, component, tick),
+            Self::Tags(set) => set.insert(id, component, tick),
+        }
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<ComponentValue> {
+        match self {
+            Self::Values(set) => set.remove(id),
+            Self::Tags(set) => set.remove(id),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Values(set) => set.dense.len(),
+            Self::Tags(set) => set.len,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            Self::Values(set) => set.clear(),
+            Self::Tags(set) => set.clear(),
+        }
+    }
+
+    pub fn check_ticks(&mut self, tick: Tick) {
+        match self {
+            Self::Values(set) => set.dense.check_ticks(tick),
+            // stale entries get clamped as well, they are overwritten before they are read again anyway
+            Self::Tags(set) => set.ticks.iter_mut().for_each(|ticks| ticks.get_mut().check_ticks(tick)),
+        }
+    }
+
+}
+
+pub(crate) struct ValueSet {
+    // indexed by entity index, points into `dense`
+    sparse: Vec<u32>,
+    dense: Column,
+    entities: Vec<EntityId>,
+}
+
+impl ValueSet {
+
+    #[inline]
+    fn dense_index(&self, id: EntityId) -> Option<usize> {
+        let idx = *self.sparse.get(id.index() as usize)?;
+        // the slot might be occupied by a different generation of the entity
+        (idx != INVALID && self.entities[idx as usize] == id).then_some(idx as usize)
+    }
+
+    #[inline]
+    pub fn get_ptr(&self, id: EntityId) -> Option<*mut u8> {
+        self.dense_index(id).map(|idx| self.dense.get_ptr(idx))
+    }
+
+    /// Returns the previous value stored for the entity.
+    pub fn insert(&mut self, id: EntityId, component: ComponentValue, tick: Tick) -> Option<ComponentValue> {
+        if let Some(idx) = self.dense_index(id) {
+            return Some(self.dense.replace(idx, component, tick));
+        }
+        let index = id.index() as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, INVALID);
+        }
+        self.sparse[index] = self.entities.len() as u32;
+        self.dense.push(component, tick);
+        self.entities.push(id);
+        None
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<ComponentValue> {
+        let idx = self.dense_index(id)?;
+        self.sparse[id.index() as usize] = INVALID;
+        self.entities.swap_remove(idx);
+        if let Some(moved) = self.entities.get(idx) {
+            self.sparse[moved.index() as usize] = idx as u32;
+        }
+        Some(self.dense.swap_remove(idx))
+    }
+
+    pub fn clear(&mut self) {
+        self.sparse.iter_mut().for_each(|idx| *idx = INVALID);
+        self.dense.clear();
+        self.entities.clear();
+    }
+
+}
+
+/// Zero sized components are tracked as presence bits only, there's nothing else worth storing.
+pub(crate) struct TagSet {
+    // indexed by entity index, this relies on components getting removed when their entity gets despawned
+    present: AtomicBitSet,
+    len: usize,
+    dangling: NonNull<u8>,
+    vtable: ValueVTable,
+    // indexed by entity index as well, entries of absent entities are stale
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+}
+
+impl TagSet {
+
+    #[inline]
+    fn contains(&self, id: EntityId) -> bool {
+        self.present.contains(id.index() as usize)
+    }
+
+    /// The values are forgotten when they get stored and conjured again when they get taken out,
+    /// so every stored value still gets dropped exactly once.
+    #[inline]
+    fn conjure(&self) -> ComponentValue {
+        unsafe { ComponentValue::read(self.dangling.as_ptr(), self.vtable) }
+    }
+
+    fn insert(&mut self, id: EntityId, component: ComponentValue, tick: Tick) -> Option<ComponentValue> {
+        component.into_raw(|_| {});
+        let index = id.index() as usize;
+        if index >= self.ticks.len() {
+            self.ticks.resize_with(index + 1, || UnsafeCell::new(ComponentTicks::new(tick)));
+        }
+        if self.present.add(index) {
+            self.ticks[index].get_mut().changed = tick;
+            return Some(self.conjure());
+        }
+        *self.ticks[index].get_mut() = ComponentTicks::new(tick);
+        self.len += 1;
+        None
+    }
+
+    fn remove(&mut self, id: EntityId) -> Option<ComponentValue> {
+        if !self.present.remove(id.index() as usize) {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.conjure())
+    }
+
+    fn clear(&mut self) {
+        (0..self.len).for_each(|_| drop(self.conjure()));
+        self.len = 0;
+        self.present.clear();
+    }
+
+}
+
+impl Drop for TagSet {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SparseSets {
+    // indexed by component id
+    sets: Vec<Option<SparseSet>>,
+}
+
+impl SparseSets {
+
+    #[inline]
+    pub fn get(&self, component_id: ComponentId) -> Option<&SparseSet> {
+        self.sets.get(component_id.index())?.as_ref()
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, component_id: ComponentId) -> Option<&mut SparseSet> {
+        self.sets.get_mut(component_id.index())?.as_mut()
+    }
+
+    pub fn get_or_insert(&mut self, info: &ComponentInfo) -> &mut SparseSet {
+        let component_id = info.id();
+        if component_id.index() >= self.sets.len() {
+            self.sets.resize_with(component_id.index() + 1, || None);
+        }
+        self.sets[component_id.index()].get_or_insert_with(|| SparseSet::new(info))
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (ComponentId, &SparseSet)> {
+        self.sets.iter().enumerate().filter_map(|(idx, set)| Some((ComponentId::new(idx), set.as_ref()?)))
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ComponentId, &mut SparseSet)> {
+        self.sets.iter_mut().enumerate().filter_map(|(idx, set)| Some((ComponentId::new(idx), set.as_mut()?)))
+    }
+
+}
@@ -0,0 +1,701 @@
+use std::any::{type_name, TypeId};
+use std::borrow::Cow;
+use std::future::Future;
+use std::marker::{PhantomData, Tuple};
+use std::sync::{Arc, Mutex};
+use bevy_utils::all_tuples;
+use crate::access::Access;
+use crate::commands::CommandQueue;
+use crate::error::{SystemOutput, SystemResult};
+use crate::query::{QueryState, WorldQuery};
+use crate::system_param::{In, ReadOnlySystemParam, SystemParam, SystemParamItem};
+use crate::task_pool::TaskPool;
+use crate::{InnerId, Read, Tick, World, WorldId, Write};
+
+/// How a system accesses one of the components it runs on.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SystemArg {
+    Read(TypeId),
+    Write(TypeId),
+}
+
+/// Logic which runs once for every entity having the components in `Args`, e.g. `(Write<Position>, Read<Velocity>)`.
+/// Closures and functions taking up to 15 `Read` and `Write` arguments are systems.
+pub trait System<Args: Tuple> {
+
+    fn run(&mut self, args: Args);
+
+    /// Runs the system for every entity which has all components it accesses.
+    /// Panics if the arguments access the same component mutably more than once.
+    fn execute<'w>(&mut self, world: &'w mut World) where Args: SystemArgs<'w>, Self: Sized {
+        for item in world.query::<Args::Query>() {
+            self.run(Args::from_item(item));
+        }
+    }
+
+}
+
+/// A single argument of a `System`.
+pub trait Argument<'a>: InnerId {
+
+    /// Fetches the component the argument gets created from.
+    type Query: WorldQuery + 'static;
+
+    fn describe() -> SystemArg;
+
+    fn from_item(item: <Self::Query as WorldQuery>::Item<'a>) -> Self;
+
+}
+
+impl<'a, T: 'static> Argument<'a> for Read<'a, T> {
+    type Query = Read<'static, T>;
+
+    #[inline]
+    fn describe() -> SystemArg {
+        SystemArg::Read(Self::inner_id())
+    }
+
+    #[inline(always)]
+    fn from_item(item: &'a T) -> Self {
+        Read(item)
+    }
+}
+
+impl<'a, T: 'static> Argument<'a> for Write<'a, T> {
+    type Query = Write<'static, T>;
+
+    #[inline]
+    fn describe() -> SystemArg {
+        SystemArg::Write(Self::inner_id())
+    }
+
+    #[inline(always)]
+    fn from_item(item: &'a mut T) -> Self {
+        Write(item)
+    }
+}
+
+/// The arguments of a `System`, a tuple of `Argument`s.
+pub trait SystemArgs<'a>: Tuple + Sized {
+
+    type Query: WorldQuery + 'static;
+
+    fn describe() -> Vec<SystemArg>;
+
+    fn from_item(item: <Self::Query as WorldQuery>::Item<'a>) -> Self;
+
+}
+
+/// Lists the accesses of a system's arguments, e.g. to find out which systems can run at the same time.
+#[inline]
+pub fn deconstruct_params<'a, Args: SystemArgs<'a>>() -> Vec<SystemArg> {
+    Args::describe()
+}
+
+/// Like `deconstruct_params`, but as component id sets of `world`, registering the components if needed.
+/// Panics if the arguments access the same component mutably more than once.
+pub fn deconstruct_access<'a, Args: SystemArgs<'a>>(world: &mut World) -> Access {
+    QueryState::<Args::Query>::new(world).access().clone()
+}
+
+macro_rules! impl_system {
+    ($($name: ident),*) => {
+        impl<Func: FnMut($($name),*), $($name),*> System<($($name,)*)> for Func {
+            #[allow(non_snake_case)]
+            #[inline]
+            fn run(&mut self, args: ($($name,)*)) {
+                let ($($name,)*) = args;
+                self($($name),*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<'a, $($name: Argument<'a>),*> SystemArgs<'a> for ($($name,)*) {
+            type Query = ($($name::Query,)*);
+
+            fn describe() -> Vec<SystemArg> {
+                vec![$($name::describe()),*]
+            }
+
+            #[inline]
+            fn from_item(item: <Self::Query as WorldQuery>::Item<'a>) -> Self {
+                let ($($name,)*) = item;
+                ($($name::from_item($name),)*)
+            }
+        }
+    };
+}
+
+all_tuples!(impl_system, 1, 15, A);
+
+/// A system which can be stored and run on a world, e.g. a function whose parameters are `SystemParam`s.
+pub trait AnySystem: Send + 'static {
+
+    /// What the system gets passed when it runs, `()` for the systems a `Schedule` runs.
+    type In;
+    /// What the system returns, `()` for the systems a `Schedule` runs.
+    type Out;
+
+    fn name(&self) -> Cow<'static, str>;
+
+    /// Resolves the state of the parameters, this has to happen before the system runs for the first time.
+    fn initialize(&mut self, world: &mut World);
+
+    /// What the system accesses, only complete once the system got initialized.
+    fn access(&self) -> &Access;
+
+    /// Whether the system needs the whole world mutably, these systems can only be run with `run`.
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        false
+    }
+
+    /// Whether the system may run on any thread, the multi-threaded executor runs the other ones on the thread which runs the schedule,
+    /// e.g. for systems accessing thread-local data.
+    #[inline(always)]
+    fn is_send(&self) -> bool {
+        true
+    }
+
+    /// # Safety
+    /// The system has to be initialized and the world has to allow the accesses of the system.
+    unsafe fn run_unsafe(&mut self, input: Self::In, world: &World) -> Self::Out;
+
+    /// Whether `apply_deferred` may change the world, the multi-threaded executor only adds sync points after systems for which this holds.
+    #[inline(always)]
+    fn has_deferred(&self) -> bool {
+        false
+    }
+
+    /// Applies the changes the system deferred, e.g. through `Commands`.
+    #[inline(always)]
+    fn apply_deferred(&mut self, _world: &mut World) {}
+
+    /// Initializes the system first if needed and applies its deferred changes afterwards.
+    fn run(&mut self, input: Self::In, world: &mut World) -> Self::Out;
+
+    /// Clamps the ticks the system remembers, see `World::check_change_ticks`.
+    #[inline(always)]
+    fn check_change_tick(&mut self, _tick: Tick) {}
+
+}
+
+pub type BoxedSystem<In = (), Out = ()> = Box<dyn AnySystem<In = In, Out = Out>>;
+
+/// Adapts the output of a system to the `SystemResult` schedules expect.
+pub(crate) struct ResultSystem<S>(pub S);
+
+impl<S: AnySystem<Out: SystemOutput>> AnySystem for ResultSystem<S> {
+    type In = S::In;
+    type Out = SystemResult;
+
+    #[inline]
+    fn name(&self) -> Cow<'static, str> {
+        self.0.name()
+    }
+
+    #[inline]
+    fn initialize(&mut self, world: &mut World) {
+        self.0.initialize(world);
+    }
+
+    #[inline]
+    fn access(&self) -> &Access {
+        self.0.access()
+    }
+
+    #[inline]
+    fn is_exclusive(&self) -> bool {
+        self.0.is_exclusive()
+    }
+
+    #[inline]
+    fn is_send(&self) -> bool {
+        self.0.is_send()
+    }
+
+    #[inline]
+    unsafe fn run_unsafe(&mut self, input: Self::In, world: &World) -> Self::Out {
+        self.0.run_unsafe(input, world).into_result()
+    }
+
+    #[inline]
+    fn has_deferred(&self) -> bool {
+        self.0.has_deferred()
+    }
+
+    #[inline]
+    fn apply_deferred(&mut self, world: &mut World) {
+        self.0.apply_deferred(world);
+    }
+
+    #[inline]
+    fn run(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        self.0.run(input, world).into_result()
+    }
+
+    #[inline]
+    fn check_change_tick(&mut self, tick: Tick) {
+        self.0.check_change_tick(tick);
+    }
+}
+
+/// Pins a system to the thread running the schedule, see `IntoSystemConfig::non_send`.
+pub(crate) struct NonSendSystem(pub BoxedSystem<(), SystemResult>);
+
+impl AnySystem for NonSendSystem {
+    type In = ();
+    type Out = SystemResult;
+
+    #[inline]
+    fn name(&self) -> Cow<'static, str> {
+        self.0.name()
+    }
+
+    #[inline]
+    fn initialize(&mut self, world: &mut World) {
+        self.0.initialize(world);
+    }
+
+    #[inline]
+    fn access(&self) -> &Access {
+        self.0.access()
+    }
+
+    #[inline]
+    fn is_exclusive(&self) -> bool {
+        self.0.is_exclusive()
+    }
+
+    #[inline(always)]
+    fn is_send(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    unsafe fn run_unsafe(&mut self, input: Self::In, world: &World) -> Self::Out {
+        self.0.run_unsafe(input, world)
+    }
+
+    #[inline]
+    fn has_deferred(&self) -> bool {
+        self.0.has_deferred()
+    }
+
+    #[inline]
+    fn apply_deferred(&mut self, world: &mut World) {
+        self.0.apply_deferred(world);
+    }
+
+    #[inline]
+    fn run(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        self.0.run(input, world)
+    }
+
+    #[inline]
+    fn check_change_tick(&mut self, tick: Tick) {
+        self.0.check_change_tick(tick);
+    }
+}
+
+/// Systems which only ever read from the world.
+///
+/// # Safety
+/// The system may not register or perform any mutable accesses.
+pub unsafe trait ReadOnlySystem: AnySystem {}
+
+/// Conversion into an `AnySystem`, implemented for functions and closures taking up to 16 `SystemParam`s.
+pub trait IntoSystem<Marker>: Sized {
+
+    type System: AnySystem;
+
+    fn into_system(self) -> Self::System;
+
+    /// Passes the output of this system to `system` as its input, e.g. `parse_input.pipe(handle_errors)`.
+    fn pipe<BMarker, B: IntoSystem<BMarker>>(self, system: B) -> PipeSystem<Self::System, B::System>
+        where B::System: AnySystem<In = <Self::System as AnySystem>::Out> {
+        PipeSystem {
+            first: self.into_system(),
+            second: system.into_system(),
+            access: Access::default(),
+        }
+    }
+
+    /// Turns a system returning a future into one which drives the future on the global `TaskPool`, e.g. to await IO.
+    /// The future resolves to a command, which gets applied to the world at the first sync point after it completed.
+    fn into_async(self) -> AsyncSystem<Self::System> where Self::System: AnySystem<Out: Future<Output: FnOnce(&mut World) + Send + 'static> + Send + 'static> {
+        AsyncSystem {
+            system: self.into_system(),
+            completed: Arc::default(),
+        }
+    }
+
+}
+
+impl<S: AnySystem> IntoSystem<()> for S {
+    type System = S;
+
+    #[inline(always)]
+    fn into_system(self) -> Self::System {
+        self
+    }
+}
+
+/// A function which can be called with the items of its `SystemParam`s and optionally an `In` as its first parameter,
+/// `Marker` tells the implementations for different arities apart.
+pub trait SystemParamFunction<Marker>: Send + 'static {
+
+    type In;
+    type Param: SystemParam;
+    type Out;
+
+    fn run(&mut self, input: Self::In, param: SystemParamItem<'_, '_, Self::Param>) -> Self::Out;
+
+}
+
+/// Runs a function whose parameters get fetched from the world.
+pub struct FunctionSystem<Marker, F: SystemParamFunction<Marker>> {
+    func: F,
+    state: Option<<F::Param as SystemParam>::State>,
+    access: Access,
+    world_id: Option<WorldId>,
+    // the tick of the previous run, changes which happened after it count as changed for the queries of the system
+    last_run: Tick,
+    _marker: PhantomData<fn() -> Marker>,
+}
+
+impl<Marker: 'static, F: SystemParamFunction<Marker>> AnySystem for FunctionSystem<Marker, F> {
+    type In = F::In;
+    type Out = F::Out;
+
+    #[inline]
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(type_name::<F>())
+    }
+
+    fn initialize(&mut self, world: &mut World) {
+        if let Some(world_id) = self.world_id {
+            assert_eq!(world_id, world.id(), "the system was initialized for a different world");
+            return;
+        }
+        let mut access = Access::default();
+        self.state = Some(F::Param::init_state(world, &mut access));
+        self.access = access;
+        self.world_id = Some(world.id());
+        // everything counts as changed on the first run
+        self.last_run = Tick::new(world.change_tick().get().wrapping_sub(u32::MAX / 2));
+    }
+
+    #[inline(always)]
+    fn access(&self) -> &Access {
+        &self.access
+    }
+
+    unsafe fn run_unsafe(&mut self, input: Self::In, world: &World) -> Self::Out {
+        assert_eq!(self.world_id, Some(world.id()), "the system wasn't initialized for this world");
+        let state = self.state.as_mut().unwrap();
+        let this_run = world.increment_change_tick();
+        let param = F::Param::get_param(state, world, self.last_run, this_run);
+        self.last_run = this_run;
+        self.func.run(input, param)
+    }
+
+    #[inline(always)]
+    fn is_send(&self) -> bool {
+        F::Param::SEND
+    }
+
+    #[inline(always)]
+    fn has_deferred(&self) -> bool {
+        F::Param::DEFERRED
+    }
+
+    #[inline]
+    fn apply_deferred(&mut self, world: &mut World) {
+        if let Some(state) = &mut self.state {
+            F::Param::apply(state, world);
+        }
+    }
+
+    fn run(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        self.initialize(world);
+        world.flush();
+        // SAFETY: the world is borrowed mutably
+        let out = unsafe { self.run_unsafe(input, world) };
+        self.apply_deferred(world);
+        out
+    }
+
+    #[inline]
+    fn check_change_tick(&mut self, tick: Tick) {
+        self.last_run.check_tick(tick);
+    }
+}
+
+unsafe impl<Marker: 'static, F: SystemParamFunction<Marker>> ReadOnlySystem for FunctionSystem<Marker, F> where F::Param: ReadOnlySystemParam {}
+
+impl<Marker: 'static, F: SystemParamFunction<Marker>> IntoSystem<(FunctionSystem<Marker, F>,)> for F {
+    type System = FunctionSystem<Marker, F>;
+
+    fn into_system(self) -> Self::System {
+        FunctionSystem {
+            func: self,
+            state: None,
+            access: Access::default(),
+            world_id: None,
+            last_run: Tick::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+macro_rules! impl_system_param_function {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case)]
+        impl<Out, Func: Send + 'static, $($name: SystemParam),*> SystemParamFunction<fn($($name,)*) -> Out> for Func
+            where for<'a> &'a mut Func: FnMut($($name),*) -> Out + FnMut($(SystemParamItem<$name>),*) -> Out {
+            type In = ();
+            type Param = ($($name,)*);
+            type Out = Out;
+
+            #[inline]
+            fn run(&mut self, _input: (), param: SystemParamItem<'_, '_, Self::Param>) -> Out {
+                // calling through a separate function makes the compiler pick the `FnMut` impl with the items
+                #[allow(clippy::too_many_arguments)]
+                fn call_inner<Out, $($name),*>(mut f: impl FnMut($($name),*) -> Out, $($name: $name),*) -> Out {
+                    f($($name),*)
+                }
+                let ($($name,)*) = param;
+                call_inner(self, $($name),*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<Input, Out, Func: Send + 'static, $($name: SystemParam),*> SystemParamFunction<fn(In<Input>, $($name,)*) -> Out> for Func
+            where for<'a> &'a mut Func: FnMut(In<Input>, $($name),*) -> Out + FnMut(In<Input>, $(SystemParamItem<$name>),*) -> Out {
+            type In = Input;
+            type Param = ($($name,)*);
+            type Out = Out;
+
+            #[inline]
+            fn run(&mut self, input: Input, param: SystemParamItem<'_, '_, Self::Param>) -> Out {
+                #[allow(clippy::too_many_arguments)]
+                fn call_inner<Input, Out, $($name),*>(mut f: impl FnMut(In<Input>, $($name),*) -> Out, input: In<Input>, $($name: $name),*) -> Out {
+                    f(input, $($name),*)
+                }
+                let ($($name,)*) = param;
+                call_inner(self, In(input), $($name),*)
+            }
+        }
+    };
+}
+
+all_tuples!(impl_system_param_function, 0, 16, P);
+
+/// Runs a function taking the whole world mutably, e.g. to spawn entities or load scenes.
+/// A `Schedule` runs it at a point where no other system runs.
+pub struct ExclusiveFunctionSystem<Out, F> {
+    func: F,
+    // exclusive systems don't declare accesses
+    access: Access,
+    _marker: PhantomData<fn() -> Out>,
+}
+
+impl<Out: 'static, F: FnMut(&mut World) -> Out + Send + 'static> AnySystem for ExclusiveFunctionSystem<Out, F> {
+    type In = ();
+    type Out = Out;
+
+    #[inline]
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(type_name::<F>())
+    }
+
+    #[inline(always)]
+    fn initialize(&mut self, _world: &mut World) {}
+
+    #[inline(always)]
+    fn access(&self) -> &Access {
+        &self.access
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    unsafe fn run_unsafe(&mut self, _input: (), _world: &World) -> Self::Out {
+        panic!("{} is exclusive, so it can't run on a shared world", self.name());
+    }
+
+    fn run(&mut self, _input: (), world: &mut World) -> Self::Out {
+        world.flush();
+        (self.func)(world)
+    }
+}
+
+impl<Out: 'static, F: FnMut(&mut World) -> Out + Send + 'static> IntoSystem<(ExclusiveFunctionSystem<Out, F>,)> for F {
+    type System = ExclusiveFunctionSystem<Out, F>;
+
+    fn into_system(self) -> Self::System {
+        ExclusiveFunctionSystem {
+            func: self,
+            access: Access::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Runs a system and passes its output to a second one, see `IntoSystem::pipe`.
+pub struct PipeSystem<A, B> {
+    first: A,
+    second: B,
+    access: Access,
+}
+
+impl<A: AnySystem, B: AnySystem<In = A::Out>> AnySystem for PipeSystem<A, B> {
+    type In = A::In;
+    type Out = B::Out;
+
+    #[inline]
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("{} | {}", self.first.name(), self.second.name()))
+    }
+
+    fn initialize(&mut self, world: &mut World) {
+        self.first.initialize(world);
+        self.second.initialize(world);
+        // the systems run one after the other, so their accesses may conflict with each other
+        let mut access = self.first.access().clone();
+        access.extend(self.second.access());
+        self.access = access;
+    }
+
+    #[inline(always)]
+    fn access(&self) -> &Access {
+        &self.access
+    }
+
+    #[inline]
+    fn is_exclusive(&self) -> bool {
+        self.first.is_exclusive() || self.second.is_exclusive()
+    }
+
+    #[inline]
+    fn is_send(&self) -> bool {
+        self.first.is_send() && self.second.is_send()
+    }
+
+    unsafe fn run_unsafe(&mut self, input: Self::In, world: &World) -> Self::Out {
+        let out = self.first.run_unsafe(input, world);
+        self.second.run_unsafe(out, world)
+    }
+
+    #[inline]
+    fn has_deferred(&self) -> bool {
+        self.first.has_deferred() || self.second.has_deferred()
+    }
+
+    fn apply_deferred(&mut self, world: &mut World) {
+        self.first.apply_deferred(world);
+        self.second.apply_deferred(world);
+    }
+
+    fn run(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        // exclusive systems can't run on a shared world, the executors run exclusive pipes through this as well
+        if self.is_exclusive() {
+            let out = self.first.run(input, world);
+            return self.second.run(out, world);
+        }
+        self.initialize(world);
+        world.flush();
+        // SAFETY: the world is borrowed mutably, the deferred changes of both systems get applied afterwards
+        // just like the executors do, so the second system doesn't see the ones of the first
+        let out = unsafe { self.run_unsafe(input, world) };
+        self.apply_deferred(world);
+        out
+    }
+
+    fn check_change_tick(&mut self, tick: Tick) {
+        self.first.check_change_tick(tick);
+        self.second.check_change_tick(tick);
+    }
+}
+
+unsafe impl<A: ReadOnlySystem, B: ReadOnlySystem<In = A::Out>> ReadOnlySystem for PipeSystem<A, B> {}
+
+/// Runs a system returning a future and applies what the future resolves to once it completed, see `IntoSystem::into_async`.
+pub struct AsyncSystem<S> {
+    system: S,
+    // the commands of the futures which completed since the last sync point
+    completed: Arc<Mutex<CommandQueue>>,
+}
+
+impl<S: AnySystem<Out: Future<Output: FnOnce(&mut World) + Send + 'static> + Send + 'static>> AnySystem for AsyncSystem<S> {
+    type In = S::In;
+    type Out = ();
+
+    #[inline]
+    fn name(&self) -> Cow<'static, str> {
+        self.system.name()
+    }
+
+    #[inline]
+    fn initialize(&mut self, world: &mut World) {
+        self.system.initialize(world);
+    }
+
+    #[inline]
+    fn access(&self) -> &Access {
+        self.system.access()
+    }
+
+    #[inline]
+    fn is_exclusive(&self) -> bool {
+        self.system.is_exclusive()
+    }
+
+    #[inline]
+    fn is_send(&self) -> bool {
+        self.system.is_send()
+    }
+
+    unsafe fn run_unsafe(&mut self, input: Self::In, world: &World) {
+        let future = self.system.run_unsafe(input, world);
+        self.spawn(future);
+    }
+
+    // futures may complete at any time
+    #[inline(always)]
+    fn has_deferred(&self) -> bool {
+        true
+    }
+
+    fn apply_deferred(&mut self, world: &mut World) {
+        self.system.apply_deferred(world);
+        let mut completed = std::mem::take(&mut *self.completed.lock().unwrap());
+        completed.apply(world);
+    }
+
+    fn run(&mut self, input: Self::In, world: &mut World) {
+        let future = self.system.run(input, world);
+        self.spawn(future);
+        self.apply_deferred(world);
+    }
+
+    #[inline]
+    fn check_change_tick(&mut self, tick: Tick) {
+        self.system.check_change_tick(tick);
+    }
+}
+
+impl<S: AnySystem<Out: Future<Output: FnOnce(&mut World) + Send + 'static> + Send + 'static>> AsyncSystem<S> {
+
+    fn spawn(&self, future: S::Out) {
+        let completed = self.completed.clone();
+        TaskPool::global().spawn(async move {
+            let command = future.await;
+            completed.lock().unwrap().push(command);
+        });
+    }
+
+}
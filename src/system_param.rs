@@ -0,0 +1,481 @@
+use std::any::type_name;
+use std::borrow::Cow;
+use std::ops::{Deref, DerefMut};
+use bevy_utils::all_tuples;
+use crate::access::Access;
+use crate::commands::{CommandQueue, Commands};
+use crate::component::ComponentId;
+use crate::event::{EventReader, EventWriter, Events};
+use crate::resource::ResourceId;
+use crate::query::{Query, QueryState, ReadOnlyWorldQuery, WorldQuery};
+use crate::{ComponentTicks, FromWorld, Tick, World};
+
+/// Something a function system takes as a parameter, it gets fetched from the world every time the system runs.
+/// Custom parameters can be built by delegating to the implementations of existing parameters, e.g. a tuple of them.
+///
+/// # Safety
+/// `get_param` may only access what `init_state` added to the access. Parameters are fetched on other threads unless
+/// `SEND` is `false`, so parameters handing out `&World` or anything else which isn't `Sync` have to set it to `false`.
+pub unsafe trait SystemParam: Sized {
+
+    /// Stored with the system between runs.
+    type State: Send + 'static;
+    /// The parameter with the lifetimes of a single run.
+    type Item<'w, 's>: SystemParam<State = Self::State>;
+
+    /// Adds the accesses of the parameter, panics if they conflict with the ones of the previous parameters in `access`.
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State;
+
+    /// Components which changed after `last_run` count as changed, mutable accesses happen at `this_run`.
+    ///
+    /// # Safety
+    /// The world has to allow the accesses the parameter registered for `'w`.
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's>;
+
+    /// Whether `apply` may change the world.
+    const DEFERRED: bool = false;
+    /// Whether the parameter may be fetched on any thread, systems with parameters which can't only run on the thread running the schedule.
+    const SEND: bool = true;
+
+    /// Applies the changes the parameter deferred, e.g. the ones recorded through `Commands`.
+    #[inline(always)]
+    fn apply(_state: &mut Self::State, _world: &mut World) {}
+
+}
+
+/// The type a parameter has while its system runs.
+pub type SystemParamItem<'w, 's, P> = <P as SystemParam>::Item<'w, 's>;
+
+/// Parameters which only ever read from the world.
+///
+/// # Safety
+/// The parameter may not register or perform any mutable accesses.
+pub unsafe trait ReadOnlySystemParam: SystemParam {}
+
+// the bounds make sure the components the query accesses can be accessed from other threads
+unsafe impl<Q: WorldQuery + Send + Sync + 'static, F: ReadOnlyWorldQuery + Send + Sync + 'static> SystemParam for Query<'_, '_, Q, F> {
+    type State = QueryState<Q, F>;
+    type Item<'w, 's> = Query<'w, 's, Q, F>;
+
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+        let state = QueryState::new(world);
+        assert!(access.is_compatible(state.access()), "{} conflicts with a previous parameter of the system", type_name::<Self>());
+        access.extend(state.access());
+        state
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+        state.update_archetypes(world);
+        Query::new(world, Cow::Borrowed(state), last_run, this_run)
+    }
+}
+
+unsafe impl<Q: ReadOnlyWorldQuery + Send + Sync + 'static, F: ReadOnlyWorldQuery + Send + Sync + 'static> ReadOnlySystemParam for Query<'_, '_, Q, F> {}
+
+/// Gives shared access to the whole world, so this conflicts with all parameters which write.
+/// The world isn't `Sync`, so systems with this parameter only run on the thread running the schedule.
+unsafe impl SystemParam for &World {
+    type State = ();
+    type Item<'w, 's> = &'w World;
+    const SEND: bool = false;
+
+    fn init_state(_world: &mut World, access: &mut Access) -> Self::State {
+        assert!(!access.writes_any(), "&World conflicts with a previous parameter of the system");
+        access.add_read_all();
+    }
+
+    #[inline(always)]
+    unsafe fn get_param<'w, 's>(_state: &'s mut Self::State, world: &'w World, _last_run: Tick, _this_run: Tick) -> Self::Item<'w, 's> {
+        world
+    }
+}
+
+unsafe impl ReadOnlySystemParam for &World {}
+
+unsafe impl SystemParam for Commands<'_, '_> {
+    type State = CommandQueue;
+    type Item<'w, 's> = Commands<'w, 's>;
+    const DEFERRED: bool = true;
+
+    #[inline]
+    fn init_state(_world: &mut World, _access: &mut Access) -> Self::State {
+        CommandQueue::default()
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, _last_run: Tick, _this_run: Tick) -> Self::Item<'w, 's> {
+        Commands::new(state, world)
+    }
+
+    #[inline]
+    fn apply(state: &mut Self::State, world: &mut World) {
+        state.apply(world);
+    }
+}
+
+// the changes only get applied with exclusive access to the world
+unsafe impl ReadOnlySystemParam for Commands<'_, '_> {}
+
+unsafe impl<T: Send + Sync + 'static> SystemParam for EventReader<'_, '_, T> {
+    type State = (ResourceId<Events<T>>, usize);
+    type Item<'w, 's> = EventReader<'w, 's, T>;
+
+    #[inline]
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+        (Res::<Events<T>>::init_state(world, access), 0)
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+        let (events, cursor) = state;
+        EventReader::new(Res::get_param(events, world, last_run, this_run), cursor)
+    }
+}
+
+unsafe impl<T: Send + Sync + 'static> ReadOnlySystemParam for EventReader<'_, '_, T> {}
+
+unsafe impl<T: Send + Sync + 'static> SystemParam for EventWriter<'_, T> {
+    type State = ResourceId<Events<T>>;
+    type Item<'w, 's> = EventWriter<'w, T>;
+
+    #[inline]
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+        ResMut::<Events<T>>::init_state(world, access)
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+        EventWriter::new(ResMut::get_param(state, world, last_run, this_run))
+    }
+}
+
+/// The input of a system, it has to be the first parameter of the system's function, e.g. `fn handle_errors(In(result): In<Result<(), String>>)`.
+/// Systems with an input can be run directly through `AnySystem::run` or piped into with `IntoSystem::pipe`.
+pub struct In<T>(pub T);
+
+impl<T> Deref for In<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for In<T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Shared access to the resource of type `R`, the system panics if the resource doesn't exist when it runs.
+pub struct Res<'w, R> {
+    value: &'w R,
+    ticks: &'w ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<R> Res<'_, R> {
+
+    /// Whether the resource got inserted since the system last ran.
+    #[inline]
+    pub fn is_added(&self) -> bool {
+        self.ticks.is_added(self.last_run, self.this_run)
+    }
+
+    /// Whether the resource got inserted or changed since the system last ran.
+    #[inline]
+    pub fn is_changed(&self) -> bool {
+        self.ticks.is_changed(self.last_run, self.this_run)
+    }
+
+}
+
+impl<R> Deref for Res<'_, R> {
+    type Target = R;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+unsafe impl<R: Send + Sync + 'static> SystemParam for Res<'_, R> {
+    type State = ResourceId<R>;
+    type Item<'w, 's> = Res<'w, R>;
+
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+        let id = world.resource_id::<R>();
+        assert!(!access.has_resource_write(id.id()), "{} conflicts with a previous parameter of the system", type_name::<Self>());
+        access.add_resource_read(id.id());
+        id
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+        let (value, ticks) = world.resources.get(state.id()).unwrap_or_else(|| panic!("resource {} doesn't exist", type_name::<R>()));
+        Res {
+            value: value.downcast_ref().unwrap(),
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+}
+
+unsafe impl<R: Send + Sync + 'static> ReadOnlySystemParam for Res<'_, R> {}
+
+/// Mutable access to the resource of type `R`, the system panics if the resource doesn't exist when it runs.
+/// Only accessing it mutably marks it as changed.
+pub struct ResMut<'w, R> {
+    value: &'w mut R,
+    ticks: &'w mut ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<R> ResMut<'_, R> {
+
+    #[inline]
+    pub fn is_added(&self) -> bool {
+        self.ticks.is_added(self.last_run, self.this_run)
+    }
+
+    #[inline]
+    pub fn is_changed(&self) -> bool {
+        self.ticks.is_changed(self.last_run, self.this_run)
+    }
+
+}
+
+impl<R> Deref for ResMut<'_, R> {
+    type Target = R;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<R> DerefMut for ResMut<'_, R> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed = self.this_run;
+        self.value
+    }
+}
+
+unsafe impl<R: Send + Sync + 'static> SystemParam for ResMut<'_, R> {
+    type State = ResourceId<R>;
+    type Item<'w, 's> = ResMut<'w, R>;
+
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+        let id = world.resource_id::<R>();
+        assert!(!access.has_resource_read(id.id()), "{} conflicts with a previous parameter of the system", type_name::<Self>());
+        access.add_resource_write(id.id());
+        id
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+        let (value, ticks) = world.resources.get_unchecked_mut(state.id()).unwrap_or_else(|| panic!("resource {} doesn't exist", type_name::<R>()));
+        ResMut {
+            value: value.downcast_mut().unwrap(),
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+}
+
+/// Shared access to the non-send resource of type `R`, systems using it only run on the thread which runs the schedule.
+/// That thread has to be the one which created the world, the system panics otherwise or if the resource doesn't exist.
+pub struct NonSend<'w, R> {
+    value: &'w R,
+    ticks: &'w ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<R> NonSend<'_, R> {
+
+    #[inline]
+    pub fn is_added(&self) -> bool {
+        self.ticks.is_added(self.last_run, self.this_run)
+    }
+
+    #[inline]
+    pub fn is_changed(&self) -> bool {
+        self.ticks.is_changed(self.last_run, self.this_run)
+    }
+
+}
+
+impl<R> Deref for NonSend<'_, R> {
+    type Target = R;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+unsafe impl<R: 'static> SystemParam for NonSend<'_, R> {
+    type State = ComponentId;
+    type Item<'w, 's> = NonSend<'w, R>;
+    const SEND: bool = false;
+
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+        let id = world.resource_types.init::<R>();
+        assert!(!access.has_resource_write(id), "{} conflicts with a previous parameter of the system", type_name::<Self>());
+        access.add_resource_read(id);
+        id
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+        let (value, ticks) = world.non_send_resources.get(*state).unwrap_or_else(|| panic!("resource {} doesn't exist", type_name::<R>()));
+        NonSend {
+            value: value.downcast_ref().unwrap(),
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+}
+
+unsafe impl<R: 'static> ReadOnlySystemParam for NonSend<'_, R> {}
+
+/// Mutable access to the non-send resource of type `R`, see `NonSend`.
+pub struct NonSendMut<'w, R> {
+    value: &'w mut R,
+    ticks: &'w mut ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<R> NonSendMut<'_, R> {
+
+    #[inline]
+    pub fn is_added(&self) -> bool {
+        self.ticks.is_added(self.last_run, self.this_run)
+    }
+
+    #[inline]
+    pub fn is_changed(&self) -> bool {
+        self.ticks.is_changed(self.last_run, self.this_run)
+    }
+
+}
+
+impl<R> Deref for NonSendMut<'_, R> {
+    type Target = R;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<R> DerefMut for NonSendMut<'_, R> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed = self.this_run;
+        self.value
+    }
+}
+
+unsafe impl<R: 'static> SystemParam for NonSendMut<'_, R> {
+    type State = ComponentId;
+    type Item<'w, 's> = NonSendMut<'w, R>;
+    const SEND: bool = false;
+
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+        let id = world.resource_types.init::<R>();
+        assert!(!access.has_resource_read(id), "{} conflicts with a previous parameter of the system", type_name::<Self>());
+        access.add_resource_write(id);
+        id
+    }
+
+    #[inline]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+        let (value, ticks) = world.non_send_resources.get_unchecked_mut(*state).unwrap_or_else(|| panic!("resource {} doesn't exist", type_name::<R>()));
+        NonSendMut {
+            value: value.downcast_mut().unwrap(),
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+}
+
+/// State which belongs to a single system and persists between its runs, e.g. a counter or a cache.
+/// It gets created through `FromWorld` when the system gets initialized.
+pub struct Local<'s, T>(&'s mut T);
+
+impl<T> Deref for Local<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<T> DerefMut for Local<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+unsafe impl<T: FromWorld + Send + 'static> SystemParam for Local<'_, T> {
+    type State = T;
+    type Item<'w, 's> = Local<'s, T>;
+
+    #[inline]
+    fn init_state(world: &mut World, _access: &mut Access) -> Self::State {
+        T::from_world(world)
+    }
+
+    #[inline(always)]
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, _world: &'w World, _last_run: Tick, _this_run: Tick) -> Self::Item<'w, 's> {
+        Local(state)
+    }
+}
+
+unsafe impl<T: FromWorld + Send + 'static> ReadOnlySystemParam for Local<'_, T> {}
+
+macro_rules! impl_system_param {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: SystemParam),*> SystemParam for ($($name,)*) {
+            type State = ($($name::State,)*);
+            type Item<'w, 's> = ($($name::Item<'w, 's>,)*);
+            const DEFERRED: bool = false $(|| $name::DEFERRED)*;
+            const SEND: bool = true $(&& $name::SEND)*;
+
+            fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+                ($($name::init_state(world, access),)*)
+            }
+
+            #[inline]
+            unsafe fn get_param<'w, 's>(state: &'s mut Self::State, world: &'w World, last_run: Tick, this_run: Tick) -> Self::Item<'w, 's> {
+                let ($($name,)*) = state;
+                ($($name::get_param($name, world, last_run, this_run),)*)
+            }
+
+            fn apply(state: &mut Self::State, world: &mut World) {
+                let ($($name,)*) = state;
+                $($name::apply($name, world);)*
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlySystemParam),*> ReadOnlySystemParam for ($($name,)*) {}
+    };
+}
+
+all_tuples!(impl_system_param, 0, 16, P);
@@ -0,0 +1,73 @@
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Wake, Waker};
+use std::thread;
+
+type BoxedFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Threads which drive futures to completion, e.g. the ones returned by async systems.
+pub struct TaskPool {
+    sender: Sender<Arc<Task>>,
+}
+
+struct Task {
+    // `None` once the future completed
+    future: Mutex<Option<BoxedFuture>>,
+    sender: Sender<Arc<Task>>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        // the workers only stop once all senders are gone, including the one of this task
+        let _ = self.sender.send(self.clone());
+    }
+}
+
+impl TaskPool {
+
+    /// The threads keep running until the pool and all of its tasks got dropped.
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Arc<Task>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            thread::spawn(move || Self::work(&receiver));
+        }
+        Self {
+            sender,
+        }
+    }
+
+    /// The pool async systems use, it has a thread per core.
+    pub fn global() -> &'static TaskPool {
+        static POOL: OnceLock<TaskPool> = OnceLock::new();
+        POOL.get_or_init(|| TaskPool::new(thread::available_parallelism().map_or(1, NonZeroUsize::get)))
+    }
+
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            sender: self.sender.clone(),
+        });
+        task.wake();
+    }
+
+    fn work(receiver: &Mutex<Receiver<Arc<Task>>>) {
+        loop {
+            let Ok(task) = receiver.lock().unwrap().recv() else {
+                return;
+            };
+            let mut future = task.future.lock().unwrap();
+            if let Some(mut inner) = future.take() {
+                let waker = Waker::from(task.clone());
+                if inner.as_mut().poll(&mut Context::from_waker(&waker)).is_pending() {
+                    *future = Some(inner);
+                }
+            }
+        }
+    }
+
+}
@@ -0,0 +1,78 @@
+/// How often the ticks stored in a world have to be checked, schedules call
+/// `World::check_change_ticks` whenever this many ticks passed since their last check.
+pub const CHECK_TICK_THRESHOLD: u32 = 518_400_000;
+
+/// The maximum age a tick can have before it gets clamped, ticks that are older than this would
+/// otherwise look new again once the tick counter wrapped around.
+pub const MAX_CHANGE_AGE: u32 = u32::MAX - (2 * CHECK_TICK_THRESHOLD - 1);
+
+/// A point in time of the world, components remember the ticks they were added and last changed at.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Tick(u32);
+
+impl Tick {
+
+    #[inline(always)]
+    pub const fn new(tick: u32) -> Self {
+        Self(tick)
+    }
+
+    #[inline(always)]
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    /// Whether this tick happened after `last_run`, as seen from `this_run`. This keeps working
+    /// when the tick counter wraps around as long as the ticks aren't more than `u32::MAX` apart.
+    #[inline]
+    pub fn is_newer_than(self, last_run: Tick, this_run: Tick) -> bool {
+        this_run.0.wrapping_sub(last_run.0) > this_run.0.wrapping_sub(self.0)
+    }
+
+    /// Clamps this tick to be at most `MAX_CHANGE_AGE` ticks older than `tick`,
+    /// returns whether it was clamped.
+    #[inline]
+    pub fn check_tick(&mut self, tick: Tick) -> bool {
+        if tick.0.wrapping_sub(self.0) > MAX_CHANGE_AGE {
+            self.0 = tick.0.wrapping_sub(MAX_CHANGE_AGE);
+            return true;
+        }
+        false
+    }
+
+}
+
+/// When a component was added to its entity and when it was last changed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+
+    #[inline]
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    #[inline]
+    pub fn is_added(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.added.is_newer_than(last_run, this_run)
+    }
+
+    #[inline]
+    pub fn is_changed(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.changed.is_newer_than(last_run, this_run)
+    }
+
+    #[inline]
+    pub fn check_ticks(&mut self, tick: Tick) {
+        self.added.check_tick(tick);
+        self.changed.check_tick(tick);
+    }
+
+}
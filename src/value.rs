@@ -0,0 +1,277 @@
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::any::Any;
+use std::fmt;
+use std::mem::{self, MaybeUninit};
+use std::ptr::{self, DynMetadata, NonNull};
+
+const INLINE_SIZE: usize = mem::size_of::<[usize; 2]>();
+const INLINE_ALIGN: usize = mem::align_of::<[usize; 2]>();
+
+#[inline]
+fn fits_inline(layout: Layout) -> bool {
+    layout.size() <= INLINE_SIZE && layout.align() <= INLINE_ALIGN
+}
+
+/// How to handle values of a component type which is only known at runtime.
+#[derive(Copy, Clone)]
+pub(crate) struct ValueVTable {
+    pub layout: Layout,
+    pub drop: Option<unsafe fn(*mut u8)>,
+    // dynamic components don't have a rust type, so they can't be accessed as `Any`
+    pub any: Option<DynMetadata<dyn Any>>,
+}
+
+impl ValueVTable {
+
+    pub fn of<T: 'static>() -> Self {
+        unsafe fn drop_ptr<T>(ptr: *mut u8) {
+            ptr::drop_in_place(ptr.cast::<T>());
+        }
+
+        Self {
+            layout: Layout::new::<T>(),
+            drop: mem::needs_drop::<T>().then_some(drop_ptr::<T> as unsafe fn(*mut u8)),
+            any: Some(ptr::metadata(ptr::null::<T>() as *const dyn Any)),
+        }
+    }
+
+}
+
+union Data {
+    inline: MaybeUninit<[usize; 2]>,
+    heap: NonNull<u8>,
+}
+
+/// A type erased component value, values which are at most two words large are stored inline
+/// so common tiny components (flags, counters, handles) don't need an allocation of their own.
+pub struct ComponentValue {
+    data: Data,
+    vtable: ValueVTable,
+}
+
+impl ComponentValue {
+
+    pub fn new<T: 'static>(value: T) -> Self {
+        let mut value = mem::ManuallyDrop::new(value);
+        unsafe { Self::read((&mut *value as *mut T).cast::<u8>(), ValueVTable::of::<T>()) }
+    }
+
+    /// Moves the value `ptr` points to into a new `ComponentValue`.
+    ///
+    /// # Safety
+    /// `ptr` has to point to a valid value of the type `vtable` belongs to, which the caller gives up ownership of.
+    pub(crate) unsafe fn read(ptr: *const u8, vtable: ValueVTable) -> Self {
+        let layout = vtable.layout;
+        let data = if fits_inline(layout) {
+            let mut data = Data {
+                inline: MaybeUninit::uninit(),
+            };
+            ptr::copy_nonoverlapping(ptr, data.inline.as_mut_ptr().cast::<u8>(), layout.size());
+            data
+        } else if layout.size() == 0 {
+            // zero sized values which are too aligned to be stored inline don't need an allocation either
+            Data {
+                heap: NonNull::new_unchecked(ptr::without_provenance_mut(layout.align())),
+            }
+        } else {
+            let heap = alloc(layout);
+            if heap.is_null() {
+                handle_alloc_error(layout);
+            }
+            ptr::copy_nonoverlapping(ptr, heap, layout.size());
+            Data {
+                heap: NonNull::new_unchecked(heap),
+            }
+        };
+        Self {
+            data,
+            vtable,
+        }
+    }
+
+    /// Passes a pointer to the value to `take`, which has to take ownership of it.
+    pub(crate) fn into_raw(mut self, take: impl FnOnce(*mut u8)) {
+        take(self.ptr_mut());
+        self.forget();
+    }
+
+    #[inline]
+    fn is_inline(&self) -> bool {
+        fits_inline(self.vtable.layout)
+    }
+
+    #[inline]
+    fn is_allocated(&self) -> bool {
+        !self.is_inline() && self.vtable.layout.size() != 0
+    }
+
+    #[inline]
+    fn ptr(&self) -> *const u8 {
+        if self.is_inline() {
+            unsafe { self.data.inline.as_ptr().cast::<u8>() }
+        } else {
+            unsafe { self.data.heap.as_ptr() }
+        }
+    }
+
+    #[inline]
+    fn ptr_mut(&mut self) -> *mut u8 {
+        if self.is_inline() {
+            unsafe { self.data.inline.as_mut_ptr().cast::<u8>() }
+        } else {
+            unsafe { self.data.heap.as_ptr() }
+        }
+    }
+
+    /// Returns `None` for values of dynamic components.
+    #[inline]
+    pub fn as_any(&self) -> Option<&dyn Any> {
+        let metadata = self.vtable.any?;
+        Some(unsafe { &*ptr::from_raw_parts::<dyn Any>(self.ptr(), metadata) })
+    }
+
+    /// Returns `None` for values of dynamic components.
+    #[inline]
+    pub fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        let metadata = self.vtable.any?;
+        Some(unsafe { &mut *ptr::from_raw_parts_mut::<dyn Any>(self.ptr_mut(), metadata) })
+    }
+
+    /// Moves the value out if it is of type `T`.
+    pub fn downcast<T: 'static>(self) -> Result<T, Self> {
+        if !self.as_any().is_some_and(|value| value.is::<T>()) {
+            return Err(self);
+        }
+        let value = unsafe { ptr::read(self.ptr().cast::<T>()) };
+        self.forget();
+        Ok(value)
+    }
+
+    /// Fails for values of dynamic components.
+    pub fn into_box(self) -> Result<Box<dyn Any>, Self> {
+        let Some(metadata) = self.vtable.any else {
+            return Err(self);
+        };
+        let layout = self.vtable.layout;
+        let ptr = if self.is_inline() {
+            let ptr = if layout.size() == 0 {
+                ptr::without_provenance_mut::<u8>(layout.align())
+            } else {
+                let ptr = unsafe { alloc(layout) };
+                if ptr.is_null() {
+                    handle_alloc_error(layout);
+                }
+                ptr
+            };
+            unsafe { ptr::copy_nonoverlapping(self.ptr(), ptr, layout.size()); }
+            ptr
+        } else {
+            unsafe { self.data.heap.as_ptr() }
+        };
+        mem::forget(self);
+        Ok(unsafe { Box::from_raw(ptr::from_raw_parts_mut::<dyn Any>(ptr, metadata)) })
+    }
+
+    /// Frees the value's allocation (if any) without dropping the value itself.
+    fn forget(self) {
+        if self.is_allocated() {
+            unsafe { dealloc(self.data.heap.as_ptr(), self.vtable.layout); }
+        }
+        mem::forget(self);
+    }
+
+}
+
+impl From<Box<dyn Any>> for ComponentValue {
+    fn from(value: Box<dyn Any>) -> Self {
+        let layout = Layout::for_value(&*value);
+        let raw = Box::into_raw(value);
+        let vtable = ValueVTable {
+            layout,
+            // `drop_in_place` of the value can only be called through the fat pointer, so this
+            // goes through the `Any` metadata instead
+            drop: None,
+            any: Some(ptr::metadata(raw)),
+        };
+        if !fits_inline(layout) {
+            return Self {
+                data: Data {
+                    heap: unsafe { NonNull::new_unchecked(raw.cast::<u8>()) },
+                },
+                vtable,
+            };
+        }
+        let mut data = Data {
+            inline: MaybeUninit::uninit(),
+        };
+        unsafe {
+            ptr::copy_nonoverlapping(raw.cast::<u8>(), data.inline.as_mut_ptr().cast::<u8>(), layout.size());
+            if layout.size() != 0 {
+                dealloc(raw.cast::<u8>(), layout);
+            }
+        }
+        Self {
+            data,
+            vtable,
+        }
+    }
+}
+
+impl Drop for ComponentValue {
+    fn drop(&mut self) {
+        let ptr = self.ptr_mut();
+        match (self.vtable.drop, self.vtable.any) {
+            (Some(drop), _) => unsafe { drop(ptr) },
+            (None, Some(metadata)) => unsafe { ptr::drop_in_place(ptr::from_raw_parts_mut::<dyn Any>(ptr, metadata)) },
+            (None, None) => {}
+        }
+        if self.is_allocated() {
+            unsafe { dealloc(self.data.heap.as_ptr(), self.vtable.layout); }
+        }
+    }
+}
+
+impl fmt::Debug for ComponentValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentValue").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+
+    #[test]
+    fn inline_and_heap_values() {
+        let counter = Rc::new(());
+        let small = ComponentValue::new((counter.clone(), 1u8));
+        let large = ComponentValue::new((counter.clone(), [7u64; 4]));
+        assert!(small.is_inline());
+        assert!(!large.is_inline());
+        assert_eq!(Rc::strong_count(&counter), 3);
+        assert_eq!(small.as_any().unwrap().downcast_ref::<(Rc<()>, u8)>().unwrap().1, 1);
+        let large = ComponentValue::from(large.into_box().unwrap());
+        assert_eq!(large.downcast::<(Rc<()>, [u64; 4])>().unwrap().1, [7; 4]);
+        let small = ComponentValue::from(small.into_box().unwrap());
+        assert!(small.is_inline());
+        let small = small.downcast::<u32>().unwrap_err();
+        drop(small);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn over_aligned_zero_sized_values() {
+        #[derive(Debug, PartialEq)]
+        #[repr(align(64))]
+        struct Aligned;
+
+        let value = ComponentValue::new(Aligned);
+        assert!(!value.is_inline() && !value.is_allocated());
+        assert!(value.as_any().unwrap().is::<Aligned>());
+        let value = ComponentValue::from(value.into_box().unwrap());
+        assert_eq!(value.downcast::<Aligned>().unwrap(), Aligned);
+        drop(ComponentValue::new(Aligned));
+    }
+
+}
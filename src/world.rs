@@ -0,0 +1,1268 @@
+use std::alloc::Layout;
+use std::any::{type_name, Any, TypeId};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::archetype::{ArchetypeId, Archetypes, EntityLocation};
+use crate::component::{ComponentId, Components, HookKind, StorageKind};
+use crate::resource::{NonSendResources, ResourceId, Resources};
+use crate::observer::{ErasedTrigger, Observer, ObserverId, Observers};
+use crate::entity::Entities;
+use crate::event::{ComponentAdded, ComponentRemoved, EntityDespawned, EntitySpawned, EventUpdatePolicy, Events};
+use crate::query::{Filter, Query, QueryState, ReadOnlyWorldQuery, StorageFetch, WorldQuery};
+use crate::shared::SharedValues;
+use crate::schedule::{Schedule, ScheduleLabel};
+use crate::system::{AnySystem, IntoSystem};
+use crate::tick::{ComponentTicks, Tick};
+use crate::sparse_set::{SparseSet, SparseSets};
+use crate::bundle;
+use crate::{Bundle, ComponentValue, Shared, EntityBuilder, EntityId, EntityIdMap, EntityMut, EntityRef};
+
+/// Tells worlds apart, so state which belongs to one world doesn't get used with another.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct WorldId(u32);
+
+impl WorldId {
+
+    fn next() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        assert_ne!(id, u32::MAX, "too many worlds");
+        Self(id)
+    }
+
+}
+
+type EventUpdater = fn(&mut World);
+
+pub struct World {
+    id: WorldId,
+    pub(crate) entities: Entities,
+    pub(crate) archetypes: Archetypes,
+    pub(crate) components: Components,
+    pub(crate) sparse_sets: SparseSets,
+    pub(crate) shared: SharedValues,
+    // resources get their own ids, separate from the ones of components
+    pub(crate) resource_types: Components,
+    pub(crate) resources: Resources,
+    pub(crate) non_send_resources: NonSendResources,
+    deferred_despawns: Mutex<Vec<EntityId>>,
+    schedules: HashMap<TypeId, Schedule>,
+    pub(crate) observers: Observers,
+    // update the events added through `add_event` whose policy is `Automatic`
+    event_updaters: Vec<(TypeId, EventUpdater)>,
+    lifecycle_events: bool,
+    // systems advance it while sharing the world
+    pub(crate) change_tick: AtomicU32,
+    last_change_tick: Tick,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            id: WorldId::next(),
+            entities: Entities::default(),
+            archetypes: Archetypes::default(),
+            components: Components::default(),
+            sparse_sets: SparseSets::default(),
+            shared: SharedValues::default(),
+            resource_types: Components::default(),
+            resources: Resources::default(),
+            non_send_resources: NonSendResources::default(),
+            deferred_despawns: Mutex::default(),
+            schedules: HashMap::new(),
+            observers: Observers::default(),
+            event_updaters: vec![],
+            lifecycle_events: false,
+            // changes which happened before the first call to `clear_trackers` are newer than `last_change_tick`
+            change_tick: AtomicU32::new(1),
+            last_change_tick: Tick::new(0),
+        }
+    }
+}
+
+impl World {
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut world = Self::default();
+        world.reserve(capacity);
+        world
+    }
+
+    /// Reserves capacity for at least `additional` more entities. Which archetype the entities end up in isn't known yet,
+    /// so component storage only gets reserved by `spawn_batch` and `extend`.
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+    }
+
+    #[inline(always)]
+    pub fn archetypes(&self) -> &Archetypes {
+        &self.archetypes
+    }
+
+    /// Chooses how components of type `CT` get stored, this has to happen before the first component of the type gets added
+    /// to any entity, afterwards the storage kind is fixed. Types which don't get registered are stored in tables.
+    pub fn register<CT: 'static>(&mut self, kind: StorageKind) -> ComponentId {
+        self.components.register::<CT>(kind)
+    }
+
+    /// Registers a component whose layout is only known at runtime, e.g. one defined by a script. Every call registers
+    /// a distinct component, values of it can only be accessed untyped through `EntityMut::insert_dynamic` and `get_dynamic`.
+    /// `drop` gets called with a pointer to every value which gets dropped.
+    pub fn register_dynamic(&mut self, name: impl Into<Cow<'static, str>>, layout: Layout, drop: Option<unsafe fn(*mut u8)>,
+                            kind: StorageKind) -> ComponentId {
+        self.components.register_dynamic(name.into(), layout, drop, kind)
+    }
+
+    /// Makes `CT` a singleton, at most one entity may have a `CT` component at a time and adding it to
+    /// a second one panics. This has to happen before the first component of the type gets added.
+    pub fn register_singleton<CT: 'static>(&mut self) -> ComponentId {
+        self.components.register_singleton::<CT>()
+    }
+
+    /// Runs `hook` whenever a component of type `CT` gets added to an entity which didn't have one before,
+    /// this replaces any previous `on_add` hook of the type. The hook runs after the component got added.
+    pub fn on_add<CT: 'static>(&mut self, hook: impl Fn(&mut World, EntityId) + Send + Sync + 'static) {
+        let component_id = self.components.init::<CT>();
+        self.components.hooks_mut(component_id).on_add = Some(Arc::new(hook));
+    }
+
+    /// Runs `hook` whenever a component of type `CT` gets overwritten by a new value, this replaces any previous
+    /// `on_replace` hook of the type. The hook runs after the new value got stored.
+    pub fn on_replace<CT: 'static>(&mut self, hook: impl Fn(&mut World, EntityId) + Send + Sync + 'static) {
+        let component_id = self.components.init::<CT>();
+        self.components.hooks_mut(component_id).on_replace = Some(Arc::new(hook));
+    }
+
+    /// Runs `hook` whenever a component of type `CT` gets removed from an entity, this includes despawning the entity.
+    /// This replaces any previous `on_remove` hook of the type. The hook runs while the component is still present.
+    pub fn on_remove<CT: 'static>(&mut self, hook: impl Fn(&mut World, EntityId) + Send + Sync + 'static) {
+        let component_id = self.components.init::<CT>();
+        self.components.hooks_mut(component_id).on_remove = Some(Arc::new(hook));
+    }
+
+    /// Observers run in the order they were added, an observer which triggers itself doesn't run again for that trigger.
+    pub fn add_observer<CT: Clone + Send + 'static>(&mut self, observer: Observer<CT>) -> ObserverId {
+        let component_id = self.components.init::<CT>();
+        self.observers.add(component_id, observer)
+    }
+
+    /// Returns whether the observer existed, its pending triggers get dropped.
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.observers.remove(id)
+    }
+
+    /// Runs the deferred observers which got triggered since they last ran, schedules do this at every sync point.
+    pub fn run_deferred_observers(&mut self) {
+        loop {
+            let pending = self.observers.take_pending();
+            if pending.is_empty() {
+                break;
+            }
+            for (observer_id, trigger) in pending {
+                self.run_observer(observer_id, trigger);
+            }
+        }
+    }
+
+    /// Makes every entity which gets a `CT` component also get an `R` component, unless it already has one.
+    /// Requirements are transitive, so `R` can have requirements of its own.
+    #[inline]
+    pub fn register_required<CT: 'static, R: Default + 'static>(&mut self) {
+        self.register_required_with::<CT, R>(R::default);
+    }
+
+    /// Like `register_required`, but the required component gets created through `constructor`.
+    pub fn register_required_with<CT: 'static, R: 'static>(&mut self, constructor: impl Fn() -> R + Send + Sync + 'static) {
+        let component_id = self.components.init::<CT>();
+        let required = self.components.init::<R>();
+        assert_ne!(component_id, required, "a component can't require itself");
+        self.components.require(component_id, required, Arc::new(move || ComponentValue::new(constructor())));
+    }
+
+    #[inline(always)]
+    pub fn components(&self) -> &Components {
+        &self.components
+    }
+
+    /// The types of the resources the world saw, their ids are separate from the ones of components.
+    #[inline(always)]
+    pub fn resource_types(&self) -> &Components {
+        &self.resource_types
+    }
+
+    /// Returns `None` if the world didn't see the component type yet.
+    #[inline]
+    pub fn component_id<CT: 'static>(&self) -> Option<ComponentId> {
+        self.components.get_id(TypeId::of::<CT>())
+    }
+
+    pub fn new_entity(&mut self) -> EntityMut<'_> {
+        self.flush();
+        let id = self.entities.alloc();
+        let tick = self.change_tick();
+        let location = EntityLocation {
+            archetype: ArchetypeId::EMPTY,
+            row: self.archetypes.get_mut(ArchetypeId::EMPTY).push(id, tick, |_| unreachable!()),
+        };
+        self.entities.set_location(id, location);
+        self.send_lifecycle_event(EntitySpawned { entity: id });
+        EntityMut::new(self, id, location)
+    }
+
+    /// Reserves an entity id without requiring exclusive access to the world,
+    /// the entity gets spawned (without any components) on the next `flush`.
+    #[inline]
+    pub fn reserve_entity(&self) -> EntityId {
+        self.entities.reserve_entity()
+    }
+
+    /// Spawns all entities which were reserved through `reserve_entity`, this happens automatically
+    /// whenever the world gets modified structurally.
+    pub fn flush(&mut self) {
+        let tick = self.change_tick();
+        let empty = self.archetypes.get_mut(ArchetypeId::EMPTY);
+        let mut spawned = vec![];
+        self.entities.flush(|id, location| {
+            *location = EntityLocation {
+                archetype: ArchetypeId::EMPTY,
+                row: empty.push(id, tick, |_| unreachable!()),
+            };
+            if self.lifecycle_events {
+                spawned.push(id);
+            }
+        });
+        for id in spawned {
+            self.send_lifecycle_event(EntitySpawned { entity: id });
+        }
+    }
+
+    #[inline]
+    pub fn build_entity(&mut self) -> EntityBuilder<'_> {
+        EntityBuilder::new(self)
+    }
+
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityId {
+        let components = bundle::bundle_components(&mut self.components, bundle);
+        self.spawn_boxed(components)
+    }
+
+    pub fn spawn_batch<B: Bundle>(&mut self, bundles: impl IntoIterator<Item = B>) -> Vec<EntityId> {
+        let bundles = bundles.into_iter();
+        let mut ids = Vec::with_capacity(bundles.size_hint().0);
+        self.spawn_each(bundles, |id| ids.push(id));
+        ids
+    }
+
+    /// Reserves storage for all bundles in the archetype of the first one, bundles of the same type usually share it.
+    fn spawn_each<B: Bundle>(&mut self, bundles: impl Iterator<Item = B>, mut spawned: impl FnMut(EntityId)) {
+        let (lower, _) = bundles.size_hint();
+        self.reserve(lower);
+        for (idx, bundle) in bundles.enumerate() {
+            let id = self.spawn(bundle);
+            if idx == 0 {
+                if let Some(location) = self.entities.get_location(id) {
+                    self.archetypes.get_mut(location.archetype).reserve(lower.saturating_sub(1));
+                }
+            }
+            spawned(id);
+        }
+    }
+
+    /// Spawns an entity directly into the archetype matching its components, if the same
+    /// component type is present multiple times the last value wins.
+    pub(crate) fn spawn_boxed(&mut self, mut components: Vec<(ComponentId, ComponentValue)>) -> EntityId {
+        self.flush();
+        self.add_required(&mut components, |_| false);
+        sort_components(&mut components);
+        let component_ids = components.iter().map(|(component_id, _)| *component_id).collect::<Vec<_>>();
+        self.check_singletons(None, component_ids.iter().copied());
+        let (table_components, sparse_components): (Vec<_>, Vec<_>) = components.into_iter()
+            .partition(|(component_id, _)| self.components.storage_kind(*component_id) == StorageKind::Table);
+        let archetype = self.archetypes.get_or_insert(table_components.iter().map(|(component_id, _)| *component_id).collect(), &self.components);
+        let id = self.entities.alloc();
+        let mut table_components = table_components.into_iter();
+        let tick = self.change_tick();
+        let row = self.archetypes.get_mut(archetype).push(id, tick, |_| table_components.next().unwrap().1);
+        self.entities.set_location(id, EntityLocation {
+            archetype,
+            row,
+        });
+        for (component_id, component) in sparse_components {
+            self.sparse_set_for(component_id).insert(id, component, tick);
+        }
+        self.send_lifecycle_event(EntitySpawned { entity: id });
+        self.run_hooks(id, &component_ids, HookKind::Add);
+        id
+    }
+
+    /// Finds the only entity with a `CT` component, `CT` has to be registered through `register_singleton`.
+    pub fn singleton<CT: 'static>(&self) -> Option<(EntityId, &CT)> {
+        let id = self.singleton_entity::<CT>()?;
+        Some((id, self.entity(id)?.get_component::<CT>()?))
+    }
+
+    pub fn singleton_mut<CT: 'static>(&mut self) -> Option<(EntityId, &mut CT)> {
+        let id = self.singleton_entity::<CT>()?;
+        let location = self.entities.get_location(id)?;
+        let component_id = self.component_id::<CT>()?;
+        Some((id, self.component_any_mut(id, location, component_id)?.downcast_mut::<CT>().unwrap()))
+    }
+
+    fn singleton_entity<CT: 'static>(&self) -> Option<EntityId> {
+        let component_id = self.component_id::<CT>()?;
+        assert!(self.components.get_info(component_id).unwrap().is_singleton(), "component type isn't a singleton");
+        self.sparse_sets.get(component_id)?.entities()?.first().copied()
+    }
+
+    #[inline]
+    pub fn entity_count(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn stats(&self) -> WorldStats {
+        let mut stats = WorldStats {
+            entity_count: self.entity_count(),
+            ..Default::default()
+        };
+        for archetype in self.archetypes.iter().filter(|archetype| !archetype.is_empty()) {
+            for component_id in archetype.components() {
+                if let Some(type_id) = self.type_id_of(*component_id) {
+                    *stats.component_counts.entry(type_id).or_insert(0) += archetype.len();
+                }
+            }
+            stats.total_components += archetype.components().len() * archetype.len();
+        }
+        for (component_id, set) in self.sparse_sets.iter().filter(|(_, set)| set.len() != 0) {
+            if let Some(type_id) = self.type_id_of(component_id) {
+                stats.component_counts.insert(type_id, set.len());
+            }
+            stats.total_components += set.len();
+        }
+        stats
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> WorldId {
+        self.id
+    }
+
+    /// The tick changes made right now get marked with.
+    #[inline(always)]
+    pub fn change_tick(&self) -> Tick {
+        Tick::new(self.change_tick.load(Ordering::Relaxed))
+    }
+
+    /// Advances the tick and returns the previous one, systems do this every time they run, so they see the changes
+    /// made after their previous run. This only requires shared access to the world.
+    #[inline]
+    pub fn increment_change_tick(&self) -> Tick {
+        Tick::new(self.change_tick.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Changes which happened after this tick are reported by `Changed` filters of queries created through the world.
+    #[inline(always)]
+    pub fn last_change_tick(&self) -> Tick {
+        self.last_change_tick
+    }
+
+    /// Advances the world's tick, queries created through the world afterwards only report changes
+    /// which happened after this call.
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.increment_change_tick();
+    }
+
+    /// Clamps all ticks which are older than `MAX_CHANGE_AGE`, otherwise they would look new again once the
+    /// tick counter wrapped around. This covers components, resources, observers and the schedules stored in
+    /// the world, `Schedule::run` calls it at least every `CHECK_TICK_THRESHOLD` ticks.
+    pub fn check_change_ticks(&mut self) {
+        let tick = self.change_tick();
+        self.archetypes.check_ticks(tick);
+        self.sparse_sets.iter_mut().for_each(|(_, set)| set.check_ticks(tick));
+        self.resources.check_ticks(tick);
+        self.non_send_resources.check_ticks(tick);
+        self.observers.check_change_ticks(tick);
+        self.schedules.values_mut().for_each(|schedule| schedule.check_change_ticks(tick));
+        self.last_change_tick.check_tick(tick);
+    }
+
+    /// Returns whether the entity is alive, this includes entities which were reserved but not yet flushed.
+    #[inline]
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.entities.contains(id)
+    }
+
+    /// Returns whether the entity is alive and has a component of type `CT`.
+    #[inline]
+    pub fn has<CT: 'static>(&self, id: EntityId) -> bool {
+        self.entity(id).is_some_and(|entity| entity.has_component::<CT>())
+    }
+
+    /// Applies `f` to the entity's `CT` component, returns whether the entity is alive and has one.
+    pub fn update<CT: 'static>(&mut self, id: EntityId, f: impl FnOnce(&mut CT)) -> bool {
+        match self.entity_mut(id).as_mut().and_then(EntityMut::get_component_mut::<CT>) {
+            Some(component) => {
+                f(component);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stores `value` as the only value of its type, replacing and returning the previous one.
+    /// Resources hold global state which doesn't belong to any entity, e.g. the time or the input.
+    pub fn insert_resource<R: Send + Sync + 'static>(&mut self, value: R) -> Option<R> {
+        let id = self.resource_types.init::<R>();
+        let tick = self.change_tick();
+        self.resources.insert(id, Box::new(value), tick).map(|previous| *previous.downcast::<R>().unwrap())
+    }
+
+    /// Inserts the resource created through `FromWorld` unless it exists already, e.g. so plugins can declare
+    /// the resources they need without overwriting the ones the user provided.
+    pub fn init_resource<R: FromWorld + Send + Sync + 'static>(&mut self) {
+        if !self.contains_resource::<R>() {
+            let value = R::from_world(self);
+            self.insert_resource(value);
+        }
+    }
+
+    #[inline]
+    pub fn contains_resource<R: 'static>(&self) -> bool {
+        self.get_resource::<R>().is_some()
+    }
+
+    #[inline]
+    pub fn get_resource<R: 'static>(&self) -> Option<&R> {
+        let id = self.resource_types.get_id(TypeId::of::<R>())?;
+        self.get_resource_by_id(ResourceId::new(id))
+    }
+
+    /// Marks the resource as changed.
+    #[inline]
+    pub fn get_resource_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        let id = self.resource_types.get_id(TypeId::of::<R>())?;
+        self.get_resource_mut_by_id(ResourceId::new(id))
+    }
+
+    /// The handle of the resource type, it stays valid when the resource gets removed and inserted again.
+    /// Resolving it once avoids looking up the type on every access, e.g. in tight loops.
+    #[inline]
+    pub fn resource_id<R: 'static>(&mut self) -> ResourceId<R> {
+        ResourceId::new(self.resource_types.init::<R>())
+    }
+
+    #[inline]
+    pub fn get_resource_by_id<R: 'static>(&self, id: ResourceId<R>) -> Option<&R> {
+        self.resources.get(id.id())?.0.downcast_ref()
+    }
+
+    /// Marks the resource as changed.
+    #[inline]
+    pub fn get_resource_mut_by_id<R: 'static>(&mut self, id: ResourceId<R>) -> Option<&mut R> {
+        let tick = self.change_tick();
+        let (value, ticks) = self.resources.get_mut(id.id())?;
+        ticks.changed = tick;
+        value.downcast_mut()
+    }
+
+    /// Inserts the value `f` returns if the resource doesn't exist yet, marks the resource as changed either way.
+    pub fn get_resource_or_insert_with<R: Send + Sync + 'static>(&mut self, f: impl FnOnce() -> R) -> &mut R {
+        if !self.contains_resource::<R>() {
+            self.insert_resource(f());
+        }
+        self.get_resource_mut().unwrap()
+    }
+
+    /// When the resource was inserted and last changed, see `Tick::is_newer_than`.
+    #[inline]
+    pub fn resource_ticks<R: 'static>(&self) -> Option<ComponentTicks> {
+        let id = self.resource_types.get_id(TypeId::of::<R>())?;
+        self.resources.get(id).map(|(_, ticks)| *ticks)
+    }
+
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R> {
+        let id = self.resource_types.get_id(TypeId::of::<R>())?;
+        self.resources.remove(id).map(|value| *value.downcast::<R>().unwrap())
+    }
+
+    /// Adds the resource storing the events of type `T`, this has to happen before events of the type can be sent or received.
+    /// The events get updated automatically, see `add_event_with_policy`.
+    #[inline]
+    pub fn add_event<T: Send + Sync + 'static>(&mut self) {
+        self.init_resource::<Events<T>>();
+        self.register_event_updater::<T>();
+    }
+
+    /// Like `add_event`, but sets the policy which decides when the events get dropped, also if the events were added already.
+    pub fn add_event_with_policy<T: Send + Sync + 'static>(&mut self, policy: EventUpdatePolicy) {
+        self.get_resource_or_insert_with(Events::<T>::new).set_policy(policy);
+        self.register_event_updater::<T>();
+    }
+
+    fn register_event_updater<T: Send + Sync + 'static>(&mut self) {
+        if self.event_updaters.iter().all(|(id, _)| *id != TypeId::of::<T>()) {
+            self.event_updaters.push((TypeId::of::<T>(), |world| {
+                if let Some(events) = world.get_resource_mut::<Events<T>>() {
+                    if events.policy() == EventUpdatePolicy::Automatic {
+                        events.update();
+                    }
+                }
+            }));
+        }
+    }
+
+    /// Adds `EntitySpawned`, `EntityDespawned`, `ComponentAdded` and `ComponentRemoved` as events and starts sending them
+    /// whenever entities get spawned or despawned and components get added or removed, no matter how it happens.
+    pub fn add_lifecycle_events(&mut self) {
+        self.add_event::<EntitySpawned>();
+        self.add_event::<EntityDespawned>();
+        self.add_event::<ComponentAdded>();
+        self.add_event::<ComponentRemoved>();
+        self.lifecycle_events = true;
+    }
+
+    /// Updates all events added through `add_event` whose policy is `EventUpdatePolicy::Automatic`, this happens
+    /// at the start of every run of schedules with `Schedule::set_update_events` enabled.
+    pub fn update_events(&mut self) {
+        let updaters = self.event_updaters.iter().map(|(_, updater)| *updater).collect::<Vec<_>>();
+        for updater in updaters {
+            updater(self);
+        }
+    }
+
+    /// Panics if the event type wasn't added.
+    pub fn send_event<T: Send + Sync + 'static>(&mut self, event: T) {
+        self.get_resource_mut::<Events<T>>()
+            .unwrap_or_else(|| panic!("event {} wasn't added to the world", type_name::<T>()))
+            .send(event);
+    }
+
+    /// Takes the resource out of the world while `f` runs, so both can be accessed mutably at once. The resource counts as changed afterwards.
+    /// Panics if the resource doesn't exist, a value of the resource inserted by `f` gets replaced by the one taken out.
+    pub fn resource_scope<R: Send + Sync + 'static, U>(&mut self, f: impl FnOnce(&mut World, &mut R) -> U) -> U {
+        let id = self.resource_types.get_id(TypeId::of::<R>());
+        let Some((value, mut ticks)) = id.and_then(|id| self.resources.take(id)) else {
+            panic!("resource {} doesn't exist", type_name::<R>());
+        };
+        let mut value = value.downcast::<R>().unwrap();
+        let out = f(self, &mut value);
+        ticks.changed = self.change_tick();
+        self.resources.restore(id.unwrap(), value, ticks);
+        out
+    }
+
+    /// Stores a resource which isn't `Send`, e.g. a window handle. Non-send resources live apart from the regular ones
+    /// and can only be accessed on the thread which created the world, all of these methods panic on other threads.
+    pub fn insert_non_send_resource<R: 'static>(&mut self, value: R) -> Option<R> {
+        let id = self.resource_types.init::<R>();
+        let tick = self.change_tick();
+        self.non_send_resources.insert(id, Box::new(value), tick).map(|previous| *previous.downcast::<R>().unwrap())
+    }
+
+    #[inline]
+    pub fn contains_non_send_resource<R: 'static>(&self) -> bool {
+        self.get_non_send_resource::<R>().is_some()
+    }
+
+    #[inline]
+    pub fn get_non_send_resource<R: 'static>(&self) -> Option<&R> {
+        let id = self.resource_types.get_id(TypeId::of::<R>())?;
+        self.non_send_resources.get(id)?.0.downcast_ref()
+    }
+
+    #[inline]
+    pub fn get_non_send_resource_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        let id = self.resource_types.get_id(TypeId::of::<R>())?;
+        let tick = self.change_tick();
+        let (value, ticks) = self.non_send_resources.get_mut(id)?;
+        ticks.changed = tick;
+        value.downcast_mut()
+    }
+
+    pub fn remove_non_send_resource<R: 'static>(&mut self) -> Option<R> {
+        let id = self.resource_types.get_id(TypeId::of::<R>())?;
+        self.non_send_resources.remove(id).map(|value| *value.downcast::<R>().unwrap())
+    }
+
+    /// Stores `value` once so many entities can refer to it through the returned handle, if an
+    /// equal value is already stored its handle gets returned instead.
+    pub fn add_shared<T: Hash + Eq + Clone + 'static>(&mut self, value: T) -> Shared<T> {
+        self.shared.add(value)
+    }
+
+    #[inline]
+    pub fn shared<T: 'static>(&self, handle: Shared<T>) -> Option<&T> {
+        self.shared.get(handle)
+    }
+
+    /// Modifying the value affects all entities holding the handle, use `set_shared` to merge
+    /// the batch with one of an equal value afterwards.
+    #[inline]
+    pub fn shared_mut<T: 'static>(&mut self, handle: Shared<T>) -> Option<&mut T> {
+        self.shared.get_mut(handle)
+    }
+
+    /// Replaces the value behind `handle`. If another handle already refers to an equal value, all entities
+    /// of the batch get moved over to that one and the old value gets dropped. Returns the handle the batch ends up with.
+    pub fn set_shared<T: Hash + Eq + 'static>(&mut self, handle: Shared<T>, value: T) -> Shared<T> {
+        match self.shared.find(&value, Some(handle)) {
+            Some(existing) => {
+                self.rebatch(handle, existing);
+                self.shared.remove(handle);
+                existing
+            }
+            None => {
+                *self.shared.get_mut(handle).expect("shared value was removed") = value;
+                handle
+            }
+        }
+    }
+
+    /// Drops the shared value, entities which still hold its handle can't resolve it anymore.
+    #[inline]
+    pub fn remove_shared<T: 'static>(&mut self, handle: Shared<T>) -> Option<T> {
+        self.shared.remove(handle)
+    }
+
+    /// All entities holding `handle`.
+    pub fn shared_batch<T: 'static>(&self, handle: Shared<T>) -> Vec<EntityId> {
+        let Some(component_id) = self.component_id::<Shared<T>>() else {
+            return vec![];
+        };
+        // only the storages holding handles have to be visited
+        match self.components.storage_kind(component_id) {
+            StorageKind::Table => self.archetypes.iter()
+                .filter_map(|archetype| Some((archetype, archetype.column(component_id)?)))
+                .flat_map(|(archetype, column)| archetype.entities().iter().enumerate()
+                    // SAFETY: the column stores `Shared<T>` values
+                    .filter(move |(row, _)| unsafe { *column.get_ptr(*row).cast::<Shared<T>>() } == handle)
+                    .map(|(_, id)| *id))
+                .collect(),
+            StorageKind::SparseSet => {
+                let Some(set) = self.sparse_sets.get(component_id) else {
+                    return vec![];
+                };
+                set.entities().unwrap_or_default().iter()
+                    .copied()
+                    // SAFETY: the set stores `Shared<T>` values
+                    .filter(|id| unsafe { *set.get_ptr(*id).unwrap().cast::<Shared<T>>() } == handle)
+                    .collect()
+            }
+        }
+    }
+
+    /// Makes all entities holding `from` hold `to` instead, returns how many entities were moved.
+    pub fn rebatch<T: 'static>(&mut self, from: Shared<T>, to: Shared<T>) -> usize {
+        let batch = self.shared_batch(from);
+        for id in batch.iter() {
+            self.entity_mut(*id).unwrap().add_component(to);
+        }
+        batch.len()
+    }
+
+    #[inline]
+    pub fn entity(&self, id: EntityId) -> Option<EntityRef<'_>> {
+        // reserved entities don't have any storage until they get flushed
+        let location = self.entities.get_location(id)?;
+        Some(EntityRef::new(self, id, location))
+    }
+
+    #[inline]
+    pub fn entity_mut(&mut self, id: EntityId) -> Option<EntityMut<'_>> {
+        self.flush();
+        let location = self.entities.get_location(id)?;
+        Some(EntityMut::new(self, id, location))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntityRef<'_>> {
+        self.archetypes.iter().flat_map(move |archetype| {
+            archetype.entities().iter().enumerate().map(move |(row, id)| EntityRef::new(self, *id, EntityLocation {
+                archetype: archetype.id(),
+                row,
+            }))
+        })
+    }
+
+    /// Visits all entities which have all components `Q` asks for, e.g. `world.query::<(Read<A>, Write<B>)>()`.
+    pub fn query<Q: WorldQuery>(&mut self) -> Query<'_, 'static, Q> {
+        self.query_filtered::<Q, ()>()
+    }
+
+    /// Like `query`, but only visits entities passing the filter `F`, e.g. `(With<Player>, Without<Frozen>)`.
+    pub fn query_filtered<Q: WorldQuery, F: ReadOnlyWorldQuery>(&mut self) -> Query<'_, 'static, Q, F> {
+        self.flush();
+        let state = QueryState::new(self);
+        Query::new(self, Cow::Owned(state), self.last_change_tick, self.change_tick())
+    }
+
+    /// Like `query`, but only visits entities whose `T` component passes `predicate`, e.g.
+    /// `world.query_where::<(EntityId,), Health>(|health| health.value <= 0.0)`.
+    pub fn query_where<Q: WorldQuery, T: 'static>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static) -> Query<'_, 'static, Q, Filter<T>> {
+        self.flush();
+        let mut state = QueryState::new(self);
+        state.set_predicate(predicate);
+        Query::new(self, Cow::Owned(state), self.last_change_tick, self.change_tick())
+    }
+
+    /// Initializes the system, runs it once and applies its deferred changes, e.g. the ones recorded through `Commands`.
+    pub fn run_system_once<Marker, S: IntoSystem<Marker>>(&mut self, system: S) -> <S::System as AnySystem>::Out
+        where S::System: AnySystem<In = ()> {
+        self.run_system_with(system, ())
+    }
+
+    /// Like `run_system_once`, but passes `input` to the system, e.g. `world.run_system_with(apply_damage, DamageEvent { .. })`
+    /// for a system whose first parameter is an `In<DamageEvent>`.
+    pub fn run_system_with<Marker, S: IntoSystem<Marker>>(&mut self, system: S, input: <S::System as AnySystem>::In) -> <S::System as AnySystem>::Out {
+        system.into_system().run(input, self)
+    }
+
+    /// Stores the schedule under `label`, returning the one which was stored under it before.
+    pub fn add_schedule<L: ScheduleLabel>(&mut self, _label: L, schedule: Schedule) -> Option<Schedule> {
+        self.schedules.insert(TypeId::of::<L>(), schedule)
+    }
+
+    pub fn remove_schedule<L: ScheduleLabel>(&mut self, _label: L) -> Option<Schedule> {
+        self.schedules.remove(&TypeId::of::<L>())
+    }
+
+    #[inline]
+    pub fn schedule_mut<L: ScheduleLabel>(&mut self, _label: L) -> Option<&mut Schedule> {
+        self.schedules.get_mut(&TypeId::of::<L>())
+    }
+
+    /// Runs the schedule stored under `label`, panics if there is none. While it runs, the schedule is taken
+    /// out of the world, so its systems can't run it again.
+    pub fn run_schedule<L: ScheduleLabel>(&mut self, _label: L) {
+        let mut schedule = self.schedules.remove(&TypeId::of::<L>())
+            .unwrap_or_else(|| panic!("there is no schedule labeled {}", type_name::<L>()));
+        schedule.run(self);
+        self.schedules.insert(TypeId::of::<L>(), schedule);
+    }
+
+    /// Visits all entities one after another, as only one `EntityMut` can exist at a time
+    /// this can't be expressed as an iterator.
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(EntityMut<'_>)) {
+        self.flush();
+        let ids = self.iter().map(|entity| entity.id()).collect::<Vec<_>>();
+        for id in ids {
+            // the previous entity might have despawned others
+            if let Some(entity) = self.entity_mut(id) {
+                f(entity);
+            }
+        }
+    }
+
+    pub fn despawn(&mut self, id: EntityId) -> bool {
+        self.flush();
+        // the entity counts as despawned even if its hooks despawned it themselves
+        let alive = self.entities.get_location(id).is_some();
+        self.despawn_with(id, |_, _| {});
+        alive
+    }
+
+    /// Despawns the entity, handing out its components instead of dropping them. Returns `None` if the entity isn't alive
+    /// or its remove hooks despawned it. Dynamic components have no rust type, so they get dropped instead.
+    pub fn remove_entity(&mut self, id: EntityId) -> Option<Vec<(TypeId, Box<dyn Any>)>> {
+        let components = self.remove_entity_raw(id)?;
+        Some(components.into_iter()
+            .filter_map(|(component_id, component)| Some((self.type_id_of(component_id)?, component.into_box().ok()?)))
+            .collect())
+    }
+
+    fn remove_entity_raw(&mut self, id: EntityId) -> Option<Vec<(ComponentId, ComponentValue)>> {
+        let mut components = vec![];
+        if !self.despawn_with(id, |component_id, component| components.push((component_id, component))) {
+            return None;
+        }
+        Some(components)
+    }
+
+    /// Returns whether the components got handed to `component`, which isn't the case if the entity isn't alive
+    /// or the hooks despawned it themselves.
+    fn despawn_with(&mut self, id: EntityId, mut component: impl FnMut(ComponentId, ComponentValue)) -> bool {
+        self.flush();
+        let Some(location) = self.entities.get_location(id) else {
+            return false;
+        };
+        let component_ids = self.component_ids_of(id, location);
+        self.run_hooks(id, &component_ids, HookKind::Remove);
+        let Some(location) = self.entities.get_location(id) else {
+            return false;
+        };
+        self.entities.free(id);
+        if let Some(swapped) = self.archetypes.get_mut(location.archetype).swap_remove(location.row, &mut component) {
+            self.entities.set_location(swapped, location);
+        }
+        for (component_id, set) in self.sparse_sets.iter_mut() {
+            if let Some(removed) = set.remove(id) {
+                component(component_id, removed);
+            }
+        }
+        self.send_lifecycle_event(EntityDespawned { entity: id });
+        true
+    }
+
+    /// Marks the entity to be despawned on the next call to `maintain`, this only requires shared
+    /// access to the world, so it can be used while iterating over entities.
+    pub fn despawn_deferred(&self, id: EntityId) {
+        self.deferred_despawns.lock().unwrap().push(id);
+    }
+
+    /// Applies all changes which were deferred until the world is accessed exclusively again.
+    pub fn maintain(&mut self) {
+        self.flush();
+        let mut despawns = mem::take(self.deferred_despawns.get_mut().unwrap());
+        for id in despawns.drain(..) {
+            self.despawn(id);
+        }
+        // hand the allocation back so it can be reused
+        *self.deferred_despawns.get_mut().unwrap() = despawns;
+    }
+
+    /// Moves the entity and all of its components over to `target`, returning the id it got assigned there.
+    /// Returns `None` if the entity isn't alive or its remove hooks despawned it.
+    pub fn transfer_entity(&mut self, id: EntityId, target: &mut World) -> Option<EntityId> {
+        let components = self.remove_entity_raw(id)?;
+        let components = self.components_for(target, components, &mut HashMap::new());
+        Some(target.spawn_boxed(components))
+    }
+
+    /// Absorbs all entities of `other`, returning which ids they got assigned in this world.
+    pub fn merge(&mut self, mut other: World) -> EntityIdMap {
+        other.flush();
+        let ids = other.iter().map(|entity| entity.id()).collect::<Vec<_>>();
+        let mut mapping = EntityIdMap::with_capacity(ids.len());
+        self.reserve(ids.len());
+        let mut dynamic = HashMap::new();
+        for id in ids {
+            // the hooks of previously merged entities might have despawned it
+            let Some(components) = other.remove_entity_raw(id) else {
+                continue;
+            };
+            let components = other.components_for(self, components, &mut dynamic);
+            mapping.insert(id, self.spawn_boxed(components));
+        }
+        mapping
+    }
+
+    /// Translates the ids of `components` into the ids `target` uses for the same component types.
+    /// Dynamic components can't be matched up by type, so they get registered anew in `target`
+    /// and `dynamic` remembers which ids they got. `Shared<T>` handles get remapped to equal values in `target`.
+    fn components_for(&self, target: &mut World, components: Vec<(ComponentId, ComponentValue)>,
+                      dynamic: &mut HashMap<ComponentId, ComponentId>) -> Vec<(ComponentId, ComponentValue)> {
+        components.into_iter()
+            .map(|(component_id, mut component)| {
+                let info = self.components.get_info(component_id).unwrap();
+                if let Some(type_id) = info.type_id() {
+                    self.shared.remap(&mut target.shared, type_id, &mut component);
+                }
+                let target_id = if info.is_dynamic() {
+                    *dynamic.entry(component_id).or_insert_with(|| target.components.init_like(info))
+                } else {
+                    target.components.init_like(info)
+                };
+                (target_id, component)
+            })
+            .collect()
+    }
+
+    #[inline]
+    pub(crate) fn type_id_of(&self, component_id: ComponentId) -> Option<TypeId> {
+        self.components.get_info(component_id).unwrap().type_id()
+    }
+
+    pub(crate) fn contains_component(&self, id: EntityId, location: EntityLocation, component_id: ComponentId) -> bool {
+        match self.components.storage_kind(component_id) {
+            StorageKind::Table => self.archetypes.get(location.archetype).unwrap().contains(component_id),
+            StorageKind::SparseSet => self.sparse_sets.get(component_id).is_some_and(|set| set.get_ptr(id).is_some()),
+        }
+    }
+
+    /// Points to the entity's value of the component, which stays valid until the next structural change.
+    pub(crate) fn component_ptr(&self, id: EntityId, location: EntityLocation, component_id: ComponentId) -> Option<*mut u8> {
+        self.storage_fetch(location, component_id).get(id, location.row)
+    }
+
+    /// Like `component_ptr`, but marks the component as changed.
+    pub(crate) fn component_ptr_mut(&mut self, id: EntityId, location: EntityLocation, component_id: ComponentId) -> Option<*mut u8> {
+        let fetch = self.storage_fetch(location, component_id);
+        let ptr = fetch.get(id, location.row)?;
+        // SAFETY: the world is borrowed mutably, so nothing else can access the ticks
+        unsafe { (*fetch.get_ticks(id, location.row).unwrap()).changed = self.change_tick(); }
+        Some(ptr)
+    }
+
+    pub(crate) fn component_ticks(&self, id: EntityId, location: EntityLocation, component_id: ComponentId) -> Option<ComponentTicks> {
+        self.storage_fetch(location, component_id).get_ticks(id, location.row).map(|ticks| unsafe { *ticks })
+    }
+
+    #[inline]
+    fn storage_fetch(&self, location: EntityLocation, component_id: ComponentId) -> StorageFetch<'_> {
+        StorageFetch::new(self, self.archetypes.get(location.archetype).unwrap(), component_id)
+    }
+
+    pub(crate) fn component_any(&self, id: EntityId, location: EntityLocation, component_id: ComponentId) -> Option<&dyn Any> {
+        let metadata = self.components.get_info(component_id)?.vtable().any?;
+        let ptr = self.component_ptr(id, location, component_id)?;
+        Some(unsafe { &*ptr::from_raw_parts::<dyn Any>(ptr, metadata) })
+    }
+
+    pub(crate) fn component_any_mut(&mut self, id: EntityId, location: EntityLocation, component_id: ComponentId) -> Option<&mut dyn Any> {
+        let metadata = self.components.get_info(component_id)?.vtable().any?;
+        let ptr = self.component_ptr_mut(id, location, component_id)?;
+        // SAFETY: the world is borrowed mutably, so nothing else can access the value
+        Some(unsafe { &mut *ptr::from_raw_parts_mut::<dyn Any>(ptr, metadata) })
+    }
+
+    /// Stores `component` on the entity, replacing and returning a previous value of the same type.
+    pub(crate) fn insert_component(&mut self, id: EntityId, location: EntityLocation, component_id: ComponentId,
+                                   component: ComponentValue) -> (EntityLocation, Option<ComponentValue>) {
+        if !self.components.get_info(component_id).unwrap().required_constructors().is_empty() {
+            // requirements are only added if they are missing, so the only replaced value is the one of `component_id`
+            let (location, mut replaced) = self.insert_components(id, location, vec![(component_id, component)]);
+            return (location, replaced.pop());
+        }
+        self.check_singletons(Some(id), [component_id]);
+        let tick = self.change_tick();
+        let old = if self.components.storage_kind(component_id) == StorageKind::SparseSet {
+            self.sparse_set_for(component_id).insert(id, component, tick)
+        } else if let Some(column) = self.archetypes.get_mut(location.archetype).column_mut(component_id) {
+            Some(column.replace(location.row, component, tick))
+        } else {
+            let dst = self.archetypes.with_component(location.archetype, component_id, &self.components);
+            let mut component = Some(component);
+            self.move_entity(location, dst, |_| component.take().unwrap(), |_, _| unreachable!());
+            None
+        };
+        if old.is_some() {
+            self.run_hooks(id, &[component_id], HookKind::Replace);
+        } else {
+            self.run_hooks(id, &[component_id], HookKind::Add);
+        }
+        (self.location_of(id), old)
+    }
+
+    /// Stores all `components` on the entity while moving it at most once, previous values of the same types get returned.
+    /// If the same component type is present multiple times the last value wins.
+    pub(crate) fn insert_components(&mut self, id: EntityId, location: EntityLocation,
+                                    mut components: Vec<(ComponentId, ComponentValue)>) -> (EntityLocation, Vec<ComponentValue>) {
+        self.add_required(&mut components, |component_id| self.contains_component(id, location, component_id));
+        sort_components(&mut components);
+        self.check_singletons(Some(id), components.iter().map(|(component_id, _)| *component_id));
+        let mut replaced = vec![];
+        let mut replaced_ids = vec![];
+        let mut added = vec![];
+        let mut added_ids = vec![];
+        let mut dst = location.archetype;
+        let tick = self.change_tick();
+        for (component_id, component) in components {
+            if self.components.storage_kind(component_id) == StorageKind::SparseSet {
+                match self.sparse_set_for(component_id).insert(id, component, tick) {
+                    Some(old) => {
+                        replaced.push(old);
+                        replaced_ids.push(component_id);
+                    }
+                    None => added_ids.push(component_id),
+                }
+            } else if let Some(column) = self.archetypes.get_mut(location.archetype).column_mut(component_id) {
+                replaced.push(column.replace(location.row, component, tick));
+                replaced_ids.push(component_id);
+            } else {
+                added.push(component);
+                added_ids.push(component_id);
+                dst = self.archetypes.with_component(dst, component_id, &self.components);
+            }
+        }
+        if dst != location.archetype {
+            // `added` is sorted just like the components of `dst`, so they get requested in the same order
+            let mut added = added.into_iter();
+            self.move_entity(location, dst, |_| added.next().unwrap(), |_, _| unreachable!());
+        }
+        self.run_hooks(id, &replaced_ids, HookKind::Replace);
+        self.run_hooks(id, &added_ids, HookKind::Add);
+        (self.location_of(id), replaced)
+    }
+
+    #[inline]
+    fn sparse_set_for(&mut self, component_id: ComponentId) -> &mut SparseSet {
+        self.sparse_sets.get_or_insert(self.components.get_info(component_id).unwrap())
+    }
+
+    /// Panics if one of the components is a singleton an entity other than `id` already has. This happens before the entity
+    /// gets changed, so it doesn't end up with only some of the components.
+    fn check_singletons(&self, id: Option<EntityId>, components: impl IntoIterator<Item = ComponentId>) {
+        for component_id in components {
+            let info = self.components.get_info(component_id).unwrap();
+            if !info.is_singleton() {
+                continue;
+            }
+            if let Some(other) = self.sparse_sets.get(component_id).and_then(|set| set.entities().unwrap().first()) {
+                assert_eq!(Some(*other), id, "singleton component {} already exists on another entity", info.name());
+            }
+        }
+    }
+
+    pub(crate) fn remove_component(&mut self, id: EntityId, location: EntityLocation, component_id: ComponentId) -> (EntityLocation, Option<ComponentValue>) {
+        if !self.contains_component(id, location, component_id) {
+            return (location, None);
+        }
+        self.run_hooks(id, &[component_id], HookKind::Remove);
+        let location = self.location_of(id);
+        if location == EntityLocation::INVALID || !self.contains_component(id, location, component_id) {
+            return (location, None);
+        }
+        if self.components.storage_kind(component_id) == StorageKind::SparseSet {
+            return (location, self.sparse_sets.get_mut(component_id).unwrap().remove(id));
+        }
+        let dst = self.archetypes.without_component(location.archetype, component_id, &self.components);
+        let mut removed = None;
+        let location = self.move_entity(location, dst, |_| unreachable!(), |_, component| removed = Some(component));
+        (location, removed)
+    }
+
+    /// Removes all of the `components` from the entity while moving it at most once, their values get returned in the
+    /// order of `components`. If the entity lacks any of them, nothing gets removed at all.
+    pub(crate) fn remove_components(&mut self, id: EntityId, location: EntityLocation,
+                                    components: &[ComponentId]) -> Option<(EntityLocation, Vec<ComponentValue>)> {
+        let present = |world: &World, location| components.iter().enumerate()
+            .all(|(idx, component_id)| !components[..idx].contains(component_id) && world.contains_component(id, location, *component_id));
+        if !present(self, location) {
+            return None;
+        }
+        self.run_hooks(id, components, HookKind::Remove);
+        let location = self.entities.get_location(id)?;
+        if !present(self, location) {
+            return None;
+        }
+        let mut removed = Vec::with_capacity(components.len());
+        let mut dst = location.archetype;
+        for component_id in components {
+            match self.components.storage_kind(*component_id) {
+                StorageKind::Table => dst = self.archetypes.without_component(dst, *component_id, &self.components),
+                StorageKind::SparseSet => removed.push((*component_id, self.sparse_sets.get_mut(*component_id).unwrap().remove(id).unwrap())),
+            }
+        }
+        let location = if dst == location.archetype {
+            location
+        } else {
+            self.move_entity(location, dst, |_| unreachable!(), |component_id, component| removed.push((component_id, component)))
+        };
+        let removed = components.iter().map(|component_id| {
+            let idx = removed.iter().position(|(removed_id, _)| removed_id == component_id).unwrap();
+            removed.swap_remove(idx).1
+        }).collect();
+        Some((location, removed))
+    }
+
+    /// Removes all components from the entity while keeping the entity itself alive.
+    pub(crate) fn take_components(&mut self, id: EntityId, location: EntityLocation) -> (EntityLocation, Vec<(ComponentId, ComponentValue)>) {
+        let component_ids = self.component_ids_of(id, location);
+        self.run_hooks(id, &component_ids, HookKind::Remove);
+        let mut location = self.location_of(id);
+        let mut taken = vec![];
+        if location == EntityLocation::INVALID {
+            return (location, taken);
+        }
+        if location.archetype != ArchetypeId::EMPTY {
+            location = self.move_entity(location, ArchetypeId::EMPTY, |_| unreachable!(), |component_id, component| taken.push((component_id, component)));
+        }
+        for (component_id, set) in self.sparse_sets.iter_mut() {
+            if let Some(removed) = set.remove(id) {
+                taken.push((component_id, removed));
+            }
+        }
+        (location, taken)
+    }
+
+    /// The ids of all components the entity has, the table components come first.
+    pub(crate) fn component_ids_of(&self, id: EntityId, location: EntityLocation) -> Vec<ComponentId> {
+        let mut component_ids = self.archetypes.get(location.archetype).unwrap().components().to_vec();
+        component_ids.extend(self.sparse_sets.iter().filter(|(_, set)| set.get_ptr(id).is_some()).map(|(component_id, _)| component_id));
+        component_ids
+    }
+
+    /// Adds the components required by `components` (and their requirements in turn) which neither `components` contains
+    /// nor the entity already has.
+    fn add_required(&self, components: &mut Vec<(ComponentId, ComponentValue)>, has: impl Fn(ComponentId) -> bool) {
+        let mut idx = 0;
+        while idx < components.len() {
+            for (required, constructor) in self.components.get_info(components[idx].0).unwrap().required_constructors() {
+                if !components.iter().any(|(component_id, _)| component_id == required) && !has(*required) {
+                    components.push((*required, constructor()));
+                }
+            }
+            idx += 1;
+        }
+    }
+
+    /// Runs the hooks of kind `kind` for each of the components in order, followed by the observers.
+    fn run_hooks(&mut self, id: EntityId, components: &[ComponentId], kind: HookKind) {
+        let hooks = components.iter()
+            .filter_map(|component_id| self.components.get_info(*component_id).unwrap().hooks().get(kind).cloned())
+            .collect::<Vec<_>>();
+        for hook in hooks {
+            hook(self, id);
+        }
+        self.trigger_observers(id, components, kind);
+        if self.lifecycle_events {
+            for component_id in components {
+                match kind {
+                    HookKind::Add => self.send_lifecycle_event(ComponentAdded { entity: id, component: *component_id }),
+                    HookKind::Remove => self.send_lifecycle_event(ComponentRemoved { entity: id, component: *component_id }),
+                    HookKind::Replace => {}
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn send_lifecycle_event<E: Send + Sync + 'static>(&mut self, event: E) {
+        if self.lifecycle_events {
+            // the events might have been removed as resources in the meantime
+            if let Some(events) = self.get_resource_mut::<Events<E>>() {
+                events.send(event);
+            }
+        }
+    }
+
+    fn trigger_observers(&mut self, id: EntityId, components: &[ComponentId], kind: HookKind) {
+        for component_id in components {
+            // observers added by observers only get triggered by later changes
+            for (observer_id, deferred, capture) in self.observers.matching(*component_id, kind) {
+                let Some(trigger) = capture(self, id) else {
+                    continue;
+                };
+                if deferred {
+                    self.observers.push_pending(observer_id, trigger);
+                } else {
+                    self.run_observer(observer_id, trigger);
+                }
+            }
+        }
+    }
+
+    fn run_observer(&mut self, observer_id: ObserverId, trigger: ErasedTrigger) {
+        if let Some(mut system) = self.observers.take_system(observer_id) {
+            system.run(trigger, self);
+            self.observers.restore_system(observer_id, system);
+        }
+    }
+
+    /// Hooks are free to move or even despawn the entity which triggered them, so its location has to be looked up again.
+    #[inline]
+    fn location_of(&self, id: EntityId) -> EntityLocation {
+        self.entities.get_location(id).unwrap_or(EntityLocation::INVALID)
+    }
+
+    /// Moves the entity at `location` over to the `dst` archetype, components which `dst` doesn't store get passed
+    /// to `leftover` and components which the entity doesn't have yet have to be provided by `missing`.
+    fn move_entity(&mut self, location: EntityLocation, dst: ArchetypeId, mut missing: impl FnMut(ComponentId) -> ComponentValue,
+                   mut leftover: impl FnMut(ComponentId, ComponentValue)) -> EntityLocation {
+        let tick = self.change_tick();
+        let (src_archetype, dst_archetype) = self.archetypes.get_two_mut(location.archetype, dst);
+        let id = src_archetype.entities()[location.row];
+        // the ticks of components which stay on the entity are kept
+        let ticks = src_archetype.components().iter()
+            .filter(|component_id| dst_archetype.contains(**component_id))
+            .map(|component_id| (*component_id, src_archetype.column(*component_id).unwrap().get_ticks(location.row)))
+            .collect::<Vec<_>>();
+        let mut moved = Vec::with_capacity(src_archetype.components().len());
+        let swapped = src_archetype.swap_remove(location.row, |component_id, component| {
+            if dst_archetype.contains(component_id) {
+                moved.push((component_id, component));
+            } else {
+                leftover(component_id, component);
+            }
+        });
+        // both archetypes keep their components sorted, so the moved ones come out in the order `dst` expects them
+        let mut moved = moved.into_iter().peekable();
+        let row = dst_archetype.push(id, tick, |component_id| match moved.next_if(|(moved_id, _)| *moved_id == component_id) {
+            Some((_, component)) => component,
+            None => missing(component_id),
+        });
+        for (component_id, ticks) in ticks {
+            dst_archetype.column_mut(component_id).unwrap().set_ticks(row, ticks);
+        }
+        if let Some(swapped) = swapped {
+            self.entities.set_location(swapped, location);
+        }
+        let location = EntityLocation {
+            archetype: dst,
+            row,
+        };
+        self.entities.set_location(id, location);
+        location
+    }
+
+    /// Resets the whole world, the allocations backing it are kept around for reuse.
+    pub fn clear(&mut self) {
+        self.clear_entities();
+        self.clear_resources();
+    }
+
+    #[inline]
+    pub fn clear_resources(&mut self) {
+        self.resources.clear();
+        self.non_send_resources.clear();
+    }
+
+    /// Despawns all entities, the allocations backing them are kept around for reuse.
+    pub fn clear_entities(&mut self) {
+        self.flush();
+        if self.components.iter().any(|info| info.hooks().on_remove.is_some()) || self.observers.observes(HookKind::Remove) || self.lifecycle_events {
+            let ids = self.iter().map(|entity| entity.id()).collect::<Vec<_>>();
+            for id in ids {
+                self.despawn(id);
+            }
+        }
+        self.entities.clear();
+        self.archetypes.clear_entities();
+        self.sparse_sets.iter_mut().for_each(|(_, set)| set.clear());
+    }
+
+}
+
+/// Sorts the components by id and removes duplicates, keeping the value which came last.
+fn sort_components(components: &mut Vec<(ComponentId, ComponentValue)>) {
+    // the sort is stable, so duplicates stay in insertion order
+    components.sort_by_key(|(component_id, _)| *component_id);
+    components.dedup_by(|later, earlier| {
+        if later.0 != earlier.0 {
+            return false;
+        }
+        mem::swap(later, earlier);
+        true
+    });
+}
+
+/// Values which need the world to be constructed, e.g. caches derived from what the world contains.
+/// Implemented for all types implementing `Default`.
+pub trait FromWorld {
+
+    fn from_world(world: &mut World) -> Self;
+
+}
+
+impl<T: Default> FromWorld for T {
+    #[inline]
+    fn from_world(_world: &mut World) -> Self {
+        T::default()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldStats {
+    pub entity_count: usize,
+    pub component_counts: HashMap<TypeId, usize>,
+    pub total_components: usize,
+}
+
+impl<B: Bundle> Extend<B> for World {
+    fn extend<T: IntoIterator<Item = B>>(&mut self, iter: T) {
+        self.spawn_each(iter.into_iter(), |_| {});
+    }
+}